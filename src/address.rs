@@ -0,0 +1,269 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::notification::{ClientEventKind, Notification};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
+/// A virtual address or route learned by the openvpn server for a client
+pub enum VirtualAddress {
+    /// A single tunnel address (e.g. `10.8.0.6`)
+    Address(IpAddr),
+    /// A subnet routed to the client through `iroute` (e.g. `192.168.1.0/24`)
+    Subnet { network: IpAddr, prefix_len: u8 },
+    /// Any other address, such as a MAC address learned in tap mode
+    Other(String),
+}
+
+impl VirtualAddress {
+    /// Parses the address as reported in a `>CLIENT:ADDRESS` notification
+    pub fn parse(raw_address: &str) -> Result<VirtualAddress> {
+        if let Some((network, prefix_len)) = raw_address.split_once('/') {
+            let network: IpAddr = network.parse()?;
+            let prefix_len: u8 = prefix_len.parse()?;
+            let max_prefix_len = if network.is_ipv4() { 32 } else { 128 };
+            if prefix_len > max_prefix_len {
+                return Err(OpenvpnError::MalformedResponse(raw_address.to_string()));
+            }
+            return Ok(VirtualAddress::Subnet {
+                network,
+                prefix_len,
+            });
+        }
+        match raw_address.parse() {
+            Ok(ip) => Ok(VirtualAddress::Address(ip)),
+            Err(_) => Ok(VirtualAddress::Other(raw_address.to_string())),
+        }
+    }
+
+    /// Whether the given IP is this address or falls within this subnet
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        match *self {
+            VirtualAddress::Address(ref address) => address == ip,
+            VirtualAddress::Subnet {
+                ref network,
+                prefix_len,
+            } => match (network, ip) {
+                (IpAddr::V4(network), IpAddr::V4(ip)) => {
+                    prefix_matches(&network.octets(), &ip.octets(), prefix_len)
+                }
+                (IpAddr::V6(network), IpAddr::V6(ip)) => {
+                    prefix_matches(&network.octets(), &ip.octets(), prefix_len)
+                }
+                _ => false,
+            },
+            VirtualAddress::Other(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for VirtualAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VirtualAddress::Address(ref ip) => ip.fmt(f),
+            VirtualAddress::Subnet {
+                ref network,
+                prefix_len,
+            } => write!(f, "{}/{}", network, prefix_len),
+            VirtualAddress::Other(ref address) => f.write_str(address),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Contains the marshalled `>CLIENT:ADDRESS` notification, sent when the server learns a new
+/// virtual address for a client
+pub struct ClientAddress {
+    cid: u64,
+    address: VirtualAddress,
+    primary: bool,
+}
+
+impl ClientAddress {
+    pub fn new(cid: u64, address: VirtualAddress, primary: bool) -> ClientAddress {
+        ClientAddress {
+            cid,
+            address,
+            primary,
+        }
+    }
+
+    /// Client ID
+    pub fn cid(&self) -> u64 {
+        self.cid
+    }
+
+    /// The learned address or subnet
+    pub fn address(&self) -> &VirtualAddress {
+        &self.address
+    }
+
+    /// Whether this is the primary tunnel address of the client
+    pub fn primary(&self) -> bool {
+        self.primary
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// A live map from Client ID to the addresses learned for that client, kept up to date by
+/// applying real-time notifications
+pub struct AddressMap {
+    clients: HashMap<u64, Vec<ClientAddress>>,
+}
+
+impl AddressMap {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Updates the map from a notification, forgetting the addresses of disconnected clients.
+    /// Returns true if the notification changed the map
+    pub fn apply(&mut self, notification: &Notification) -> bool {
+        match *notification {
+            Notification::ClientAddress(ref address) => self.insert(address.clone()),
+            Notification::Client(ref event) if event.kind() == ClientEventKind::Disconnect => {
+                !self.remove(event.cid()).is_empty()
+            }
+            _ => false,
+        }
+    }
+
+    /// Records a learned address. A known address only has its primary flag updated
+    pub fn insert(&mut self, address: ClientAddress) -> bool {
+        let addresses = self.clients.entry(address.cid).or_default();
+        match addresses.iter_mut().find(|a| a.address == address.address) {
+            Some(known) if known.primary == address.primary => false,
+            Some(known) => {
+                known.primary = address.primary;
+                true
+            }
+            None => {
+                addresses.push(address);
+                true
+            }
+        }
+    }
+
+    /// Forgets every address of a client, returning them
+    pub fn remove(&mut self, cid: u64) -> Vec<ClientAddress> {
+        self.clients.remove(&cid).unwrap_or_default()
+    }
+
+    /// The addresses learned for a client, in the order they were learned
+    pub fn addresses(&self, cid: u64) -> &[ClientAddress] {
+        self.clients.get(&cid).map(Vec::as_slice).unwrap_or(&[])
+    }
+
+    /// The primary tunnel address of a client
+    pub fn primary(&self, cid: u64) -> Option<&VirtualAddress> {
+        self.addresses(cid)
+            .iter()
+            .find(|a| a.primary)
+            .map(|a| &a.address)
+    }
+
+    /// Finds the client owning an address, preferring exact addresses over subnets
+    pub fn client_for(&self, ip: &IpAddr) -> Option<u64> {
+        let mut subnet_match = None;
+        for address in self.clients.values().flatten() {
+            match address.address {
+                VirtualAddress::Address(ref a) if a == ip => return Some(address.cid),
+                ref subnet if subnet_match.is_none() && subnet.contains(ip) => {
+                    subnet_match = Some(address.cid)
+                }
+                _ => {}
+            }
+        }
+        subnet_match
+    }
+
+    /// Client IDs with at least one known address
+    pub fn cids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.clients.keys().cloned()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.clients.is_empty()
+    }
+}
+
+fn prefix_matches(network: &[u8], ip: &[u8], prefix_len: u8) -> bool {
+    let full_bytes = usize::from(prefix_len / 8);
+    let remaining_bits = prefix_len % 8;
+    if network[..full_bytes] != ip[..full_bytes] {
+        return false;
+    }
+    if remaining_bits == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - remaining_bits);
+    network[full_bytes] & mask == ip[full_bytes] & mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(cid: u64, raw: &str, primary: bool) -> ClientAddress {
+        ClientAddress::new(cid, VirtualAddress::parse(raw).unwrap(), primary)
+    }
+
+    #[test]
+    fn test_parse_virtual_address() {
+        assert_eq!(
+            VirtualAddress::Address("10.8.0.6".parse().unwrap()),
+            VirtualAddress::parse("10.8.0.6").unwrap()
+        );
+        assert_eq!(
+            VirtualAddress::Subnet {
+                network: "192.168.1.0".parse().unwrap(),
+                prefix_len: 24
+            },
+            VirtualAddress::parse("192.168.1.0/24").unwrap()
+        );
+        assert_eq!(
+            VirtualAddress::Other("0a:1b:2c:3d:4e:5f".to_string()),
+            VirtualAddress::parse("0a:1b:2c:3d:4e:5f").unwrap()
+        );
+        assert!(VirtualAddress::parse("192.168.1.0/33").is_err());
+    }
+
+    #[test]
+    fn test_subnet_contains() {
+        let subnet = VirtualAddress::parse("192.168.1.0/23").unwrap();
+
+        assert!(subnet.contains(&"192.168.0.200".parse().unwrap()));
+        assert!(subnet.contains(&"192.168.1.7".parse().unwrap()));
+        assert!(!subnet.contains(&"192.168.2.1".parse().unwrap()));
+        assert!(!subnet.contains(&"::1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_address_map_tracks_learned_addresses() {
+        let mut map = AddressMap::new();
+
+        assert!(map.insert(address(1, "10.8.0.6", true)));
+        assert!(map.insert(address(1, "192.168.1.0/24", false)));
+        assert!(!map.insert(address(1, "10.8.0.6", true)));
+        assert!(map.insert(address(2, "10.8.0.10", true)));
+
+        assert_eq!(2, map.addresses(1).len());
+        assert_eq!(
+            Some(&VirtualAddress::parse("10.8.0.6").unwrap()),
+            map.primary(1)
+        );
+        assert_eq!(Some(1), map.client_for(&"192.168.1.20".parse().unwrap()));
+        assert_eq!(Some(2), map.client_for(&"10.8.0.10".parse().unwrap()));
+        assert_eq!(None, map.client_for(&"10.8.0.11".parse().unwrap()));
+
+        assert_eq!(2, map.remove(1).len());
+        assert!(map.addresses(1).is_empty());
+        assert_eq!(None, map.client_for(&"192.168.1.20".parse().unwrap()));
+    }
+}
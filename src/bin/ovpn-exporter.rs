@@ -0,0 +1,533 @@
+//! `ovpn-exporter`, serves the status of openvpn servers as Prometheus metrics
+#[cfg(test)]
+#[path = "../fixtures.rs"]
+mod fixtures;
+
+use clap::Parser;
+#[cfg(test)]
+use openvpn_management::Client;
+use openvpn_management::{
+    CommandManager, CommandManagerBuilder, EventManager, LoadStats, StateRecord, Status,
+};
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+const UNIX_PREFIX: &str = "unix:";
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Parser)]
+#[command(
+    name = "ovpn-exporter",
+    version,
+    about = "Serves the status of openvpn servers as Prometheus metrics"
+)]
+struct Cli {
+    /// Management interface to scrape, as `[name=]host:port` or `[name=]unix:/path`. The name
+    /// labels the metrics of the server and defaults to the address
+    #[arg(long = "target", short, required = true)]
+    targets: Vec<String>,
+    /// Address to serve the metrics on
+    #[arg(long, short, default_value = "0.0.0.0:9176")]
+    listen: String,
+    /// Password of the management interfaces
+    #[arg(long, env = "OVPN_MGMT_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    /// Common names exported per server with their own labels, the bytes of the remaining
+    /// clients being added up under an empty common name and `other="true"`
+    #[arg(long, default_value_t = 500)]
+    max_clients: usize,
+    /// Seconds to wait for each management interface
+    #[arg(long, default_value_t = 5)]
+    timeout: u64,
+}
+
+/// A management interface and the name labelling its metrics
+struct Target {
+    name: String,
+    manager: CommandManager,
+    scrape_errors: u64,
+}
+
+impl Target {
+    fn new(
+        spec: &str,
+        password: Option<&str>,
+        timeout: Duration,
+    ) -> openvpn_management::Result<Target> {
+        let (name, address) = match spec.split_once('=') {
+            Some((name, address)) => (name, address),
+            None => (spec, spec),
+        };
+        let mut builder = CommandManagerBuilder::new();
+        builder
+            .connect_timeout(Some(timeout))
+            .read_timeout(Some(timeout));
+        match address.strip_prefix(UNIX_PREFIX) {
+            #[cfg(unix)]
+            Some(path) => {
+                builder.unix_socket(path);
+            }
+            #[cfg(not(unix))]
+            Some(_) => {
+                return Err(openvpn_management::OpenvpnError::InvalidArgument(
+                    address.to_string(),
+                ))
+            }
+            None => {
+                builder.management_url(address);
+            }
+        }
+        if let Some(password) = password {
+            builder.password(password);
+        }
+        Ok(Target {
+            name: name.to_string(),
+            manager: builder.build()?,
+            scrape_errors: 0,
+        })
+    }
+
+    fn scrape(&mut self) -> Option<Scrape> {
+        let start = Instant::now();
+        let scrape = self.manager.open_session().and_then(|mut session| {
+            Ok(Scrape {
+                status: session.get_status()?,
+                stats: session.load_stats()?,
+                state: session.state()?,
+                duration: Duration::default(),
+            })
+        });
+        match scrape {
+            Ok(mut scrape) => {
+                scrape.duration = start.elapsed();
+                Some(scrape)
+            }
+            Err(e) => {
+                eprintln!("ovpn-exporter: scraping {} failed: {}", self.name, e);
+                self.scrape_errors += 1;
+                None
+            }
+        }
+    }
+}
+
+/// What was read from a management interface during a scrape
+struct Scrape {
+    status: Status,
+    stats: LoadStats,
+    state: StateRecord,
+    duration: Duration,
+}
+
+/// The bytes and earliest session start of the clients sharing a common name
+#[derive(Debug, Default, PartialEq)]
+struct ClientTotals {
+    bytes_received: f64,
+    bytes_sent: f64,
+    connected_since: Option<i64>,
+}
+
+/// Adds up the clients by common name, keeping the `max_clients` common names with the most
+/// traffic and folding the others into the `None` entry, which has no session start
+fn client_totals(status: &Status, max_clients: usize) -> BTreeMap<Option<String>, ClientTotals> {
+    let mut by_name: BTreeMap<Option<String>, ClientTotals> = BTreeMap::new();
+    for client in status.clients() {
+        let totals = by_name.entry(Some(client.name().to_string())).or_default();
+        totals.bytes_received += client.bytes_received();
+        totals.bytes_sent += client.bytes_sent();
+        let since = client.connected_since().timestamp();
+        totals.connected_since = Some(totals.connected_since.map_or(since, |s| s.min(since)));
+    }
+    if by_name.len() <= max_clients {
+        return by_name;
+    }
+    let mut names: Vec<_> = by_name.into_iter().collect();
+    names.sort_by(|(_, a), (_, b)| {
+        (b.bytes_received + b.bytes_sent).total_cmp(&(a.bytes_received + a.bytes_sent))
+    });
+    let others = names.split_off(max_clients);
+    let mut kept: BTreeMap<_, _> = names.into_iter().collect();
+    let other = kept.entry(None).or_default();
+    for (_, totals) in others {
+        other.bytes_received += totals.bytes_received;
+        other.bytes_sent += totals.bytes_sent;
+    }
+    other.connected_since = None;
+    kept
+}
+
+/// Writes metric families in the Prometheus text format
+#[derive(Default)]
+struct Metrics {
+    output: String,
+}
+
+impl Metrics {
+    fn family(&mut self, name: &str, kind: &str, help: &str) {
+        let _ = writeln!(self.output, "# HELP {} {}", name, help);
+        let _ = writeln!(self.output, "# TYPE {} {}", name, kind);
+    }
+
+    fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let labels: Vec<_> = labels
+            .iter()
+            .map(|(label, value)| format!("{}=\"{}\"", label, escape_label(value)))
+            .collect();
+        let _ = writeln!(self.output, "{}{{{}}} {}", name, labels.join(","), value);
+    }
+}
+
+/// Labels the clients of a common name, or the other clients when there is none
+fn client_labels<'a>(server: &'a str, common_name: &'a Option<String>) -> Vec<(&'a str, &'a str)> {
+    match common_name {
+        Some(common_name) => vec![("server", server), ("common_name", common_name)],
+        None => vec![("server", server), ("common_name", ""), ("other", "true")],
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Renders the metrics of every target, `None` standing for a failed scrape
+fn render(targets: &[(&str, u64, Option<&Scrape>)], max_clients: usize) -> String {
+    let mut metrics = Metrics::default();
+    let scraped: Vec<_> = targets
+        .iter()
+        .filter_map(|&(name, _, scrape)| scrape.map(|s| (name, s)))
+        .collect();
+
+    metrics.family(
+        "openvpn_up",
+        "gauge",
+        "Whether the management interface was scraped successfully",
+    );
+    for &(name, _, scrape) in targets {
+        let up = if scrape.is_some() { 1.0 } else { 0.0 };
+        metrics.sample("openvpn_up", &[("server", name)], up);
+    }
+    metrics.family(
+        "openvpn_scrape_errors_total",
+        "counter",
+        "Failed scrapes of the management interface",
+    );
+    for &(name, errors, _) in targets {
+        metrics.sample(
+            "openvpn_scrape_errors_total",
+            &[("server", name)],
+            errors as f64,
+        );
+    }
+    metrics.family(
+        "openvpn_scrape_duration_seconds",
+        "gauge",
+        "Time taken to scrape the management interface",
+    );
+    for &(name, scrape) in &scraped {
+        metrics.sample(
+            "openvpn_scrape_duration_seconds",
+            &[("server", name)],
+            scrape.duration.as_secs_f64(),
+        );
+    }
+    metrics.family(
+        "openvpn_server_state",
+        "gauge",
+        "Current state of the openvpn server, labelled by state name",
+    );
+    for &(name, scrape) in &scraped {
+        metrics.sample(
+            "openvpn_server_state",
+            &[("server", name), ("state", scrape.state.name())],
+            1.0,
+        );
+    }
+    metrics.family(
+        "openvpn_server_state_timestamp_seconds",
+        "gauge",
+        "When the openvpn server entered its current state",
+    );
+    for &(name, scrape) in &scraped {
+        metrics.sample(
+            "openvpn_server_state_timestamp_seconds",
+            &[("server", name)],
+            scrape.state.timestamp().timestamp() as f64,
+        );
+    }
+    metrics.family(
+        "openvpn_server_received_bytes_total",
+        "counter",
+        "Bytes received by the server from all clients",
+    );
+    for &(name, scrape) in &scraped {
+        metrics.sample(
+            "openvpn_server_received_bytes_total",
+            &[("server", name)],
+            scrape.stats.bytes_in(),
+        );
+    }
+    metrics.family(
+        "openvpn_server_sent_bytes_total",
+        "counter",
+        "Bytes sent by the server to all clients",
+    );
+    for &(name, scrape) in &scraped {
+        metrics.sample(
+            "openvpn_server_sent_bytes_total",
+            &[("server", name)],
+            scrape.stats.bytes_out(),
+        );
+    }
+    metrics.family(
+        "openvpn_connected_clients",
+        "gauge",
+        "Clients currently connected to the server",
+    );
+    for &(name, scrape) in &scraped {
+        metrics.sample(
+            "openvpn_connected_clients",
+            &[("server", name)],
+            scrape.status.clients().len() as f64,
+        );
+    }
+
+    let totals: Vec<_> = scraped
+        .iter()
+        .map(|&(name, scrape)| (name, client_totals(&scrape.status, max_clients)))
+        .collect();
+    metrics.family(
+        "openvpn_client_received_bytes",
+        "gauge",
+        "Bytes received from the clients of a common name during their current sessions, \
+         dropping when a session ends",
+    );
+    for (name, clients) in &totals {
+        for (common_name, client) in clients {
+            metrics.sample(
+                "openvpn_client_received_bytes",
+                &client_labels(name, common_name),
+                client.bytes_received,
+            );
+        }
+    }
+    metrics.family(
+        "openvpn_client_sent_bytes",
+        "gauge",
+        "Bytes sent to the clients of a common name during their current sessions, \
+         dropping when a session ends",
+    );
+    for (name, clients) in &totals {
+        for (common_name, client) in clients {
+            metrics.sample(
+                "openvpn_client_sent_bytes",
+                &client_labels(name, common_name),
+                client.bytes_sent,
+            );
+        }
+    }
+    metrics.family(
+        "openvpn_client_connected_since_seconds",
+        "gauge",
+        "When the earliest current session of a common name started",
+    );
+    for (name, clients) in &totals {
+        for (common_name, client) in clients {
+            if let Some(since) = client.connected_since {
+                metrics.sample(
+                    "openvpn_client_connected_since_seconds",
+                    &client_labels(name, common_name),
+                    since as f64,
+                );
+            }
+        }
+    }
+    metrics.output
+}
+
+fn scrape_all(targets: &mut [Target], max_clients: usize) -> String {
+    let scrapes: Vec<_> = targets.iter_mut().map(Target::scrape).collect();
+    let rows: Vec<_> = targets
+        .iter()
+        .zip(&scrapes)
+        .map(|(target, scrape)| (target.name.as_str(), target.scrape_errors, scrape.as_ref()))
+        .collect();
+    render(&rows, max_clients)
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &str) {
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    if let Err(e) = stream.write_all(response.as_bytes()) {
+        eprintln!("ovpn-exporter: writing the response failed: {}", e);
+    }
+}
+
+fn serve(listener: TcpListener, targets: &mut [Target], max_clients: usize) {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("ovpn-exporter: accepting a connection failed: {}", e);
+                continue;
+            }
+        };
+        let _ = stream.set_read_timeout(Some(Duration::from_secs(5)));
+        let _ = stream.set_write_timeout(Some(Duration::from_secs(5)));
+        let mut request_line = String::new();
+        if BufReader::new(&stream)
+            .read_line(&mut request_line)
+            .is_err()
+        {
+            continue;
+        }
+        let mut parts = request_line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => {
+                let body = scrape_all(targets, max_clients);
+                respond(&mut stream, "200 OK", CONTENT_TYPE, &body);
+            }
+            (Some("GET"), Some("/")) => respond(
+                &mut stream,
+                "200 OK",
+                "text/html",
+                "<html><body><a href=\"/metrics\">Metrics</a></body></html>",
+            ),
+            _ => respond(&mut stream, "404 Not Found", "text/plain", "not found\n"),
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let timeout = Duration::from_secs(cli.timeout);
+    let targets: openvpn_management::Result<Vec<_>> = cli
+        .targets
+        .iter()
+        .map(|spec| Target::new(spec, cli.password.as_deref(), timeout))
+        .collect();
+    let mut targets = match targets {
+        Ok(targets) => targets,
+        Err(e) => {
+            eprintln!("ovpn-exporter: invalid target: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let listener = match TcpListener::bind(&cli.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("ovpn-exporter: listening on {} failed: {}", cli.listen, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    serve(listener, &mut targets, cli.max_clients);
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::client;
+
+    fn status(clients: &[(&str, f64)]) -> Status {
+        fixtures::status(
+            clients
+                .iter()
+                .enumerate()
+                .map(|(i, &(name, bytes))| {
+                    let address = format!("192.168.0.{}:41954", i);
+                    client(name, &address, 1546277714 + i as i64, bytes, bytes * 2.0)
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_client_totals_add_up_common_names() {
+        let totals = client_totals(&status(&[("alice", 10.0), ("alice", 5.0)]), 10);
+
+        assert_eq!(
+            Some(&ClientTotals {
+                bytes_received: 15.0,
+                bytes_sent: 30.0,
+                connected_since: Some(1546277714),
+            }),
+            totals.get(&Some("alice".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_client_totals_cap_cardinality() {
+        let totals = client_totals(
+            &status(&[
+                ("alice", 10.0),
+                ("bob", 100.0),
+                ("carol", 1.0),
+                ("dave", 2.0),
+            ]),
+            2,
+        );
+
+        assert_eq!(
+            vec![None, Some("alice"), Some("bob")],
+            totals.keys().map(Option::as_deref).collect::<Vec<_>>()
+        );
+        assert_eq!(3.0, totals[&None].bytes_received);
+        assert_eq!(None, totals[&None].connected_since);
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        let scrape = Scrape {
+            status: status(&[("al\"ice", 10.0)]),
+            stats: LoadStats::new(1, 1024.0, 2048.0),
+            state: StateRecord::parse("1547913893,CONNECTED,SUCCESS,10.8.0.1,,,,").unwrap(),
+            duration: Duration::from_millis(250),
+        };
+        let output = render(&[("vpn1", 0, Some(&scrape)), ("vpn2", 3, None)], 10);
+
+        assert!(output.contains("openvpn_up{server=\"vpn1\"} 1\nopenvpn_up{server=\"vpn2\"} 0\n"));
+        assert!(output.contains("openvpn_scrape_errors_total{server=\"vpn2\"} 3\n"));
+        assert!(output.contains("openvpn_server_state{server=\"vpn1\",state=\"CONNECTED\"} 1\n"));
+        assert!(output.contains("openvpn_server_sent_bytes_total{server=\"vpn1\"} 2048\n"));
+        assert!(output.contains("openvpn_connected_clients{server=\"vpn1\"} 1\n"));
+        assert!(output.contains(
+            "openvpn_client_received_bytes{server=\"vpn1\",common_name=\"al\\\"ice\"} 10\n"
+        ));
+        assert!(output.contains("# TYPE openvpn_client_received_bytes gauge\n"));
+        assert!(output.contains(
+            "openvpn_client_connected_since_seconds{server=\"vpn1\",common_name=\"al\\\"ice\"} 1546277714\n"
+        ));
+        assert!(!output.contains("server=\"vpn2\",common_name"));
+    }
+
+    #[test]
+    fn test_render_other_clients() {
+        let scrape = Scrape {
+            status: status(&[("__other__", 5.0), ("bob", 100.0), ("carol", 1.0)]),
+            stats: LoadStats::new(3, 1024.0, 2048.0),
+            state: StateRecord::parse("1547913893,CONNECTED,SUCCESS,10.8.0.1,,,,").unwrap(),
+            duration: Duration::from_millis(250),
+        };
+        let output = render(&[("vpn1", 0, Some(&scrape))], 2);
+
+        assert!(output.contains(
+            "openvpn_client_received_bytes{server=\"vpn1\",common_name=\"__other__\"} 5\n"
+        ));
+        assert!(output.contains(
+            "openvpn_client_received_bytes{server=\"vpn1\",common_name=\"\",other=\"true\"} 1\n"
+        ));
+        assert!(!output.contains("connected_since_seconds{server=\"vpn1\",common_name=\"\""));
+    }
+}
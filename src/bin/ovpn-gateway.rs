@@ -0,0 +1,736 @@
+//! `ovpn-gateway`, a REST gateway to the openvpn management interface
+#[path = "shared/connection.rs"]
+mod connection;
+#[cfg(test)]
+#[path = "../fixtures.rs"]
+mod fixtures;
+
+use clap::Parser;
+use connection::ConnectionArgs;
+#[cfg(test)]
+use openvpn_management::Client;
+use openvpn_management::{
+    CommandManager, EventManager, Notification, OpenvpnError, Session, Signal, Status, Versioned,
+};
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+const MAX_BODY_LENGTH: usize = 64 * 1024;
+const MAX_HEADER_LENGTH: u64 = 8 * 1024;
+const CONNECTION_TIMEOUT: Duration = Duration::from_secs(10);
+const JSON: &str = "application/json";
+
+#[derive(Parser)]
+#[command(
+    name = "ovpn-gateway",
+    version,
+    about = "REST gateway to the openvpn management interface"
+)]
+struct Cli {
+    #[command(flatten)]
+    connection: ConnectionArgs,
+    /// Address to serve the API on
+    #[arg(long, short, default_value = "127.0.0.1:8080")]
+    listen: String,
+    /// Bearer token allowed to read the status and the events
+    #[arg(
+        long = "read-token",
+        env = "OVPN_GATEWAY_READ_TOKENS",
+        value_delimiter = ',',
+        hide_env_values = true
+    )]
+    read_tokens: Vec<String>,
+    /// Bearer token also allowed to kill clients and send signals
+    #[arg(
+        long = "admin-token",
+        env = "OVPN_GATEWAY_ADMIN_TOKENS",
+        value_delimiter = ',',
+        hide_env_values = true
+    )]
+    admin_tokens: Vec<String>,
+    /// Most requests served at once, including the event streams
+    #[arg(long, default_value_t = 64)]
+    max_connections: usize,
+    /// Also streams the openvpn log in the events
+    #[arg(long)]
+    log_events: bool,
+    /// Streams the client byte counts in the events every given number of seconds
+    #[arg(long, default_value_t = 0)]
+    bytecount: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Access {
+    Read,
+    Admin,
+}
+
+/// The bearer tokens and the access they grant
+struct Tokens {
+    tokens: Vec<(String, Access)>,
+}
+
+impl Tokens {
+    fn new(read_tokens: &[String], admin_tokens: &[String]) -> Tokens {
+        let read = read_tokens.iter().map(|t| (t.clone(), Access::Read));
+        let admin = admin_tokens.iter().map(|t| (t.clone(), Access::Admin));
+        Tokens {
+            tokens: read.chain(admin).filter(|(t, _)| !t.is_empty()).collect(),
+        }
+    }
+
+    /// The access granted by an `Authorization` header, tokens being compared in constant time
+    fn access(&self, authorization: Option<&str>) -> Option<Access> {
+        let token = authorization?.strip_prefix("Bearer ")?.trim();
+        self.tokens
+            .iter()
+            .filter(|(known, _)| constant_time_eq(known.as_bytes(), token.as_bytes()))
+            .map(|&(_, access)| access)
+            .max()
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+#[derive(Debug, PartialEq)]
+enum Route {
+    Status,
+    Clients,
+    Client(u64),
+    KillClient(u64),
+    Signal,
+    Events,
+    NotFound,
+    MethodNotAllowed,
+}
+
+impl Route {
+    fn parse(method: &str, path: &str) -> Route {
+        let path = path.split('?').next().unwrap_or_default();
+        let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+        let route = match segments[..] {
+            ["status"] => (Route::Status, "GET"),
+            ["clients"] => (Route::Clients, "GET"),
+            ["clients", cid] => match cid.parse() {
+                Ok(cid) if method == "DELETE" => (Route::KillClient(cid), "DELETE"),
+                Ok(cid) => (Route::Client(cid), "GET"),
+                Err(_) => return Route::NotFound,
+            },
+            ["signal"] => (Route::Signal, "POST"),
+            ["events"] => (Route::Events, "GET"),
+            _ => return Route::NotFound,
+        };
+        match route {
+            (route, expected) if expected == method => route,
+            _ => Route::MethodNotAllowed,
+        }
+    }
+
+    fn access(&self) -> Access {
+        match *self {
+            Route::KillClient(_) | Route::Signal => Access::Admin,
+            _ => Access::Read,
+        }
+    }
+}
+
+/// A request handed to the broker, which owns the management session
+enum Command {
+    Status(Sender<Result<Status, String>>),
+    Kill(u64, Sender<Result<(), String>>),
+    Signal(Signal, Sender<Result<(), String>>),
+    Subscribe(Sender<Notification>),
+}
+
+/// Owns the single management session, answering the commands between the notifications and
+/// reconnecting when the session fails
+struct Broker {
+    manager: CommandManager,
+    log_events: bool,
+    bytecount: u32,
+    subscribers: Vec<Sender<Notification>>,
+}
+
+impl Broker {
+    fn connect(&self) -> openvpn_management::Result<Session> {
+        let mut session = self.manager.open_session()?;
+        session.state_on()?;
+        if self.log_events {
+            session.log_on()?;
+        }
+        if self.bytecount > 0 {
+            session.bytecount(self.bytecount)?;
+        }
+        Ok(session)
+    }
+
+    fn run(mut self, commands: Receiver<Command>) {
+        let mut session = None;
+        loop {
+            let connected = match session {
+                Some(ref mut connected) => connected,
+                None => match self.connect() {
+                    Ok(connected) => session.insert(connected),
+                    Err(e) => {
+                        eprintln!("ovpn-gateway: connecting to openvpn failed: {}", e);
+                        if !self.refuse_commands(&commands, &e) {
+                            return;
+                        }
+                        continue;
+                    }
+                },
+            };
+            if let Err(e) = self.step(connected, &commands) {
+                eprintln!("ovpn-gateway: the management session failed: {}", e);
+                session = None;
+            }
+        }
+    }
+
+    /// Answers the commands received while openvpn is unreachable, returns false once the
+    /// HTTP server is gone
+    fn refuse_commands(&mut self, commands: &Receiver<Command>, error: &OpenvpnError) -> bool {
+        let error = format!("openvpn is unreachable: {}", error);
+        let mut waited = Duration::default();
+        while waited < RECONNECT_DELAY {
+            match commands.recv_timeout(POLL_INTERVAL) {
+                Ok(Command::Status(reply)) => {
+                    let _ = reply.send(Err(error.clone()));
+                }
+                Ok(Command::Kill(_, reply)) | Ok(Command::Signal(_, reply)) => {
+                    let _ = reply.send(Err(error.clone()));
+                }
+                Ok(Command::Subscribe(subscriber)) => self.subscribers.push(subscriber),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return false,
+            }
+            waited += POLL_INTERVAL;
+        }
+        true
+    }
+
+    fn step(
+        &mut self,
+        session: &mut Session,
+        commands: &Receiver<Command>,
+    ) -> openvpn_management::Result<()> {
+        if let Some(notification) = session.poll_notification(POLL_INTERVAL)? {
+            if is_streamed(&notification) {
+                self.subscribers
+                    .retain(|subscriber| subscriber.send(notification.clone()).is_ok());
+            }
+        }
+        while let Ok(command) = commands.try_recv() {
+            match command {
+                Command::Status(reply) => answer(&reply, session.get_status())?,
+                Command::Kill(cid, reply) => answer(&reply, session.client_kill(cid, None))?,
+                Command::Signal(signal, reply) => answer(&reply, session.signal(signal))?,
+                Command::Subscribe(subscriber) => self.subscribers.push(subscriber),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sends the outcome of a command to the request waiting for it. Connection errors are also
+/// returned so the broker reconnects
+fn answer<T>(
+    reply: &Sender<Result<T, String>>,
+    result: openvpn_management::Result<T>,
+) -> openvpn_management::Result<()> {
+    match result {
+        Ok(value) => {
+            let _ = reply.send(Ok(value));
+            Ok(())
+        }
+        Err(OpenvpnError::Io(e)) => {
+            let _ = reply.send(Err(e.to_string()));
+            Err(OpenvpnError::Io(e))
+        }
+        Err(e) => {
+            let _ = reply.send(Err(e.to_string()));
+            Ok(())
+        }
+    }
+}
+
+/// A parsed HTTP request, with the only header the gateway reads
+struct Request {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+/// Reads a request whose request line and headers fit in [`MAX_HEADER_LENGTH`] bytes
+fn read_request<R: Read>(reader: &mut BufReader<R>) -> io::Result<Request> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let mut head = reader.by_ref().take(MAX_HEADER_LENGTH);
+    let mut read_line = |line: &mut String| match head.read_line(line)? {
+        0 => Err(invalid("truncated headers")),
+        _ if !line.ends_with('\n') && head.limit() == 0 => Err(invalid("headers too large")),
+        length => Ok(length),
+    };
+    let mut request_line = String::new();
+    read_line(&mut request_line)?;
+    let mut parts = request_line.split_whitespace();
+    let (method, path) = match (parts.next(), parts.next()) {
+        (Some(method), Some(path)) => (method.to_string(), path.to_string()),
+        _ => return Err(invalid("malformed request line")),
+    };
+    let mut authorization = None;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        read_line(&mut header)?;
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.trim().to_string());
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().map_err(|_| invalid("bad length"))?;
+            }
+        }
+    }
+    if content_length > MAX_BODY_LENGTH {
+        return Err(invalid("body too large"));
+    }
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+    Ok(Request {
+        method,
+        path,
+        authorization,
+        body,
+    })
+}
+
+fn respond<W: Write>(writer: &mut W, status: &str, body: &str) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        JSON,
+        body.len(),
+        body
+    )?;
+    writer.flush()
+}
+
+fn respond_json<W: Write, T: Serialize>(writer: &mut W, data: T) -> io::Result<()> {
+    let body = serde_json::to_string(&Versioned::new(data)).map_err(io::Error::other)?;
+    respond(writer, "200 OK", &body)
+}
+
+fn respond_error<W: Write>(writer: &mut W, status: &str, error: &str) -> io::Result<()> {
+    respond(
+        writer,
+        status,
+        &serde_json::json!({ "error": error }).to_string(),
+    )
+}
+
+#[derive(Deserialize)]
+struct SignalRequest {
+    signal: Signal,
+}
+
+/// Serves a request, holding the connection open for the event stream
+fn handle<S: Read + Write>(
+    mut stream: S,
+    tokens: &Tokens,
+    commands: &Sender<Command>,
+) -> io::Result<()> {
+    let request = {
+        let mut reader = BufReader::new(&mut stream);
+        read_request(&mut reader)
+    };
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => return respond_error(&mut stream, "400 Bad Request", &e.to_string()),
+    };
+    let route = Route::parse(&request.method, &request.path);
+    match route {
+        Route::NotFound => return respond_error(&mut stream, "404 Not Found", "not found"),
+        Route::MethodNotAllowed => {
+            return respond_error(&mut stream, "405 Method Not Allowed", "method not allowed")
+        }
+        _ => {}
+    }
+    match tokens.access(request.authorization.as_deref()) {
+        None => return respond_error(&mut stream, "401 Unauthorized", "missing or unknown token"),
+        Some(access) if access < route.access() => {
+            return respond_error(&mut stream, "403 Forbidden", "admin token required")
+        }
+        Some(_) => {}
+    }
+    let unavailable = || "the gateway is shutting down".to_string();
+    match route {
+        Route::Status | Route::Clients | Route::Client(_) | Route::KillClient(_) => {
+            let (reply, status) = mpsc::channel();
+            let _ = commands.send(Command::Status(reply));
+            let status = match status.recv().unwrap_or_else(|_| Err(unavailable())) {
+                Ok(status) => status,
+                Err(e) => return respond_error(&mut stream, "502 Bad Gateway", &e),
+            };
+            let client = |cid| status.clients().iter().find(|c| c.cid() == Some(cid));
+            match route {
+                Route::Status => respond_json(&mut stream, &status),
+                Route::Clients => respond_json(&mut stream, status.clients()),
+                Route::Client(cid) => match client(cid) {
+                    Some(client) => respond_json(&mut stream, client),
+                    None => respond_error(&mut stream, "404 Not Found", "unknown client"),
+                },
+                Route::KillClient(cid) if client(cid).is_none() => {
+                    respond_error(&mut stream, "404 Not Found", "unknown client")
+                }
+                Route::KillClient(cid) => {
+                    let (reply, killed) = mpsc::channel();
+                    let _ = commands.send(Command::Kill(cid, reply));
+                    match killed.recv().unwrap_or_else(|_| Err(unavailable())) {
+                        Ok(()) => respond_json(&mut stream, serde_json::json!({ "killed": cid })),
+                        Err(e) => respond_error(&mut stream, "502 Bad Gateway", &e),
+                    }
+                }
+                _ => unreachable!("status routes only"),
+            }
+        }
+        Route::Signal => {
+            let signal = match serde_json::from_slice::<SignalRequest>(&request.body) {
+                Ok(request) => request.signal,
+                Err(e) => return respond_error(&mut stream, "400 Bad Request", &e.to_string()),
+            };
+            let (reply, sent) = mpsc::channel();
+            let _ = commands.send(Command::Signal(signal, reply));
+            match sent.recv().unwrap_or_else(|_| Err(unavailable())) {
+                Ok(()) => respond_json(&mut stream, serde_json::json!({ "signal": signal })),
+                Err(e) => respond_error(&mut stream, "502 Bad Gateway", &e),
+            }
+        }
+        Route::Events => {
+            let (subscriber, notifications) = mpsc::channel();
+            let _ = commands.send(Command::Subscribe(subscriber));
+            stream_events(&mut stream, &notifications)
+        }
+        Route::NotFound | Route::MethodNotAllowed => unreachable!("answered above"),
+    }
+}
+
+/// Whether a notification is sent to the event streams. The client notifications without a
+/// typed representation, e.g. `>CLIENT:CR_RESPONSE` and the environment following it, may hold
+/// the answers of the users and are not, since read tokens get the events
+fn is_streamed(notification: &Notification) -> bool {
+    !matches!(*notification, Notification::Other { ref kind, .. } if kind == "CLIENT")
+}
+
+/// Writes the notifications as server-sent events, named after the notification type, until
+/// the client goes away
+fn stream_events<W: Write>(
+    writer: &mut W,
+    notifications: &Receiver<Notification>,
+) -> io::Result<()> {
+    writer.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+    )?;
+    writer.flush()?;
+    loop {
+        match notifications.recv_timeout(KEEPALIVE_INTERVAL) {
+            Ok(notification) => {
+                let kind = serde_json::to_value(&notification).map_err(io::Error::other)?;
+                let data = serde_json::to_string(&Versioned::new(&notification))
+                    .map_err(io::Error::other)?;
+                let event = kind["type"].as_str().unwrap_or("notification");
+                write!(writer, "event: {}\ndata: {}\n\n", event, data)?;
+            }
+            Err(RecvTimeoutError::Timeout) => writer.write_all(b": keepalive\n\n")?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        writer.flush()?;
+    }
+}
+
+/// Counts a request being served until dropped
+struct ConnectionSlot(Arc<AtomicUsize>);
+
+impl Drop for ConnectionSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn build_manager(cli: &Cli) -> openvpn_management::Result<CommandManager> {
+    let timeout = Some(Duration::from_secs(10));
+    cli.connection.builder(timeout, timeout)?.build()
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let tokens = Arc::new(Tokens::new(&cli.read_tokens, &cli.admin_tokens));
+    if tokens.tokens.is_empty() {
+        eprintln!("ovpn-gateway: at least one --read-token or --admin-token is required");
+        return ExitCode::FAILURE;
+    }
+    let manager = match build_manager(&cli) {
+        Ok(manager) => manager,
+        Err(e) => {
+            eprintln!("ovpn-gateway: invalid management interface: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let listener = match TcpListener::bind(&cli.listen) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("ovpn-gateway: listening on {} failed: {}", cli.listen, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let (commands, received) = mpsc::channel();
+    let broker = Broker {
+        manager,
+        log_events: cli.log_events,
+        bytecount: cli.bytecount,
+        subscribers: Vec::new(),
+    };
+    thread::spawn(move || broker.run(received));
+    let connections = Arc::new(AtomicUsize::new(0));
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("ovpn-gateway: accepting a connection failed: {}", e);
+                continue;
+            }
+        };
+        let _ = stream.set_read_timeout(Some(CONNECTION_TIMEOUT));
+        let _ = stream.set_write_timeout(Some(CONNECTION_TIMEOUT));
+        if connections.fetch_add(1, Ordering::SeqCst) >= cli.max_connections {
+            connections.fetch_sub(1, Ordering::SeqCst);
+            let _ = respond_error(
+                &mut stream,
+                "503 Service Unavailable",
+                "too many connections",
+            );
+            continue;
+        }
+        let connection = ConnectionSlot(Arc::clone(&connections));
+        let tokens = Arc::clone(&tokens);
+        let commands = commands.clone();
+        thread::spawn(move || {
+            if let Err(e) = handle(stream, &tokens, &commands) {
+                eprintln!("ovpn-gateway: serving a request failed: {}", e);
+            }
+            drop(connection);
+        });
+    }
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{client, status};
+    use std::io::Cursor;
+
+    /// An in-memory connection holding the request and collecting the response
+    struct Connection {
+        request: Cursor<Vec<u8>>,
+        response: Vec<u8>,
+    }
+
+    impl Read for Connection {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.request.read(buf)
+        }
+    }
+
+    impl Write for Connection {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.response.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn tokens() -> Tokens {
+        Tokens::new(&["reader".to_string()], &["admin".to_string()])
+    }
+
+    /// Answers the commands like a server with a single client, recording the kills and signals
+    fn fake_broker() -> (Sender<Command>, thread::JoinHandle<Vec<String>>) {
+        let (commands, received) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let mut actions = Vec::new();
+            for command in received {
+                match command {
+                    Command::Status(reply) => {
+                        let client =
+                            client("alice", "192.168.0.3", 1546277714, 100.0, 200.0).with_cid(4);
+                        reply.send(Ok(status(vec![client]))).unwrap();
+                    }
+                    Command::Kill(cid, reply) => {
+                        actions.push(format!("kill {}", cid));
+                        reply.send(Ok(())).unwrap();
+                    }
+                    Command::Signal(signal, reply) => {
+                        actions.push(signal.to_string());
+                        reply.send(Ok(())).unwrap();
+                    }
+                    Command::Subscribe(subscriber) => {
+                        let state = openvpn_management::StateRecord::parse(
+                            "1547913893,CONNECTED,SUCCESS,10.8.0.1,,,,",
+                        )
+                        .unwrap();
+                        subscriber.send(Notification::State(state)).unwrap();
+                    }
+                }
+            }
+            actions
+        });
+        (commands, handle)
+    }
+
+    fn request(commands: &Sender<Command>, raw: &str) -> String {
+        let mut connection = Connection {
+            request: Cursor::new(raw.as_bytes().to_vec()),
+            response: Vec::new(),
+        };
+        handle(&mut connection, &tokens(), commands).unwrap();
+        String::from_utf8(connection.response).unwrap()
+    }
+
+    #[test]
+    fn test_routes() {
+        assert_eq!(Route::Client(4), Route::parse("GET", "/clients/4"));
+        assert_eq!(Route::KillClient(4), Route::parse("DELETE", "/clients/4/"));
+        assert_eq!(Route::Status, Route::parse("GET", "/status?pretty"));
+        assert_eq!(Route::MethodNotAllowed, Route::parse("GET", "/signal"));
+        assert_eq!(Route::NotFound, Route::parse("GET", "/clients/alice"));
+    }
+
+    #[test]
+    fn test_token_access() {
+        let tokens = tokens();
+
+        assert_eq!(Some(Access::Read), tokens.access(Some("Bearer reader")));
+        assert_eq!(Some(Access::Admin), tokens.access(Some("Bearer admin")));
+        assert_eq!(None, tokens.access(Some("Bearer admin2")));
+        assert_eq!(None, tokens.access(Some("admin")));
+        assert_eq!(None, tokens.access(None));
+    }
+
+    #[test]
+    fn test_read_endpoints() {
+        let (commands, broker) = fake_broker();
+
+        let response = request(
+            &commands,
+            "GET /clients/4 HTTP/1.1\r\nAuthorization: Bearer reader\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains(r#"{"schema_version":1,"data":{"name":"alice""#));
+        let response = request(
+            &commands,
+            "GET /clients/5 HTTP/1.1\r\nAuthorization: Bearer reader\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 404"));
+        let response = request(&commands, "GET /status HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 401"));
+        let response = request(
+            &commands,
+            &format!(
+                "GET /status HTTP/1.1\r\nX-Padding: {}\r\n\r\n",
+                "a".repeat(10_000)
+            ),
+        );
+        assert!(response.starts_with("HTTP/1.1 400"));
+        assert!(response.contains("headers too large"));
+
+        drop(commands);
+        assert!(broker.join().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_admin_endpoints() {
+        let (commands, broker) = fake_broker();
+
+        let response = request(
+            &commands,
+            "DELETE /clients/4 HTTP/1.1\r\nAuthorization: Bearer reader\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 403"));
+        let response = request(
+            &commands,
+            "DELETE /clients/4 HTTP/1.1\r\nAuthorization: Bearer admin\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let response = request(
+            &commands,
+            "POST /signal HTTP/1.1\r\nAuthorization: Bearer admin\r\nContent-Length: 21\r\n\r\n{\"signal\":\"SIGUSR1\"}\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        let response = request(
+            &commands,
+            "POST /signal HTTP/1.1\r\nAuthorization: Bearer admin\r\nContent-Length: 20\r\n\r\n{\"signal\":\"SIGKILL\"}",
+        );
+        assert!(response.starts_with("HTTP/1.1 400"));
+
+        drop(commands);
+        assert_eq!(
+            vec!["kill 4".to_string(), "SIGUSR1".to_string()],
+            broker.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_event_stream() {
+        let (subscriber, notifications) = mpsc::channel();
+        let state =
+            openvpn_management::StateRecord::parse("1547913893,CONNECTED,SUCCESS,10.8.0.1,,,,")
+                .unwrap();
+        subscriber.send(Notification::State(state)).unwrap();
+        drop(subscriber);
+        let mut output = Vec::new();
+        stream_events(&mut output, &notifications).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(output.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/event-stream"));
+        assert!(output.contains(
+            "event: state\ndata: {\"schema_version\":1,\"data\":{\"type\":\"state\",\"data\":{\"timestamp\":\"2019-01-19T16:04:53Z\",\"name\":\"CONNECTED\""
+        ));
+    }
+
+    #[test]
+    fn test_unparsed_client_notifications_not_streamed() {
+        let mut parser = openvpn_management::NotificationParser::new();
+        let cr_response = parser
+            .push_line(">CLIENT:CR_RESPONSE,3,1,aGVsbG8=")
+            .unwrap()
+            .unwrap();
+        let env = parser
+            .push_line(">CLIENT:ENV,password=secret")
+            .unwrap()
+            .unwrap();
+        let info = parser.push_line(">INFO:hello").unwrap().unwrap();
+
+        assert!(!is_streamed(&cr_response));
+        assert!(!is_streamed(&env));
+        assert!(is_streamed(&info));
+    }
+}
@@ -0,0 +1,188 @@
+//! A minimal line editor for the REPL: cursor movement, history and tab completion on a
+//! terminal in raw mode
+use crate::terminal::Key;
+use std::io::{self, Write};
+
+#[derive(Debug, PartialEq)]
+pub enum Edit {
+    /// The line changed or the cursor moved
+    Changed,
+    /// Enter was pressed on the line
+    Submit(String),
+    /// Tab was pressed, the line up to the cursor is to be completed
+    Complete,
+    /// Ctrl-C, the line was dropped
+    Interrupted,
+    /// Ctrl-D on an empty line
+    Eof,
+}
+
+/// The line being edited and the lines entered before
+#[derive(Debug, Default)]
+pub struct Editor {
+    line: Vec<char>,
+    cursor: usize,
+    history: Vec<String>,
+    /// Position in the history while browsing it, with the line being edited before
+    browsing: Option<(usize, Vec<char>)>,
+}
+
+impl Editor {
+    pub fn new(history: Vec<String>) -> Editor {
+        Editor {
+            history,
+            ..Default::default()
+        }
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn line(&self) -> String {
+        self.line.iter().collect()
+    }
+
+    /// The line up to the cursor, which is what gets completed
+    pub fn before_cursor(&self) -> String {
+        self.line[..self.cursor].iter().collect()
+    }
+
+    /// Inserts text at the cursor, e.g. the end of a completed word
+    pub fn insert(&mut self, text: &str) {
+        for c in text.chars() {
+            self.line.insert(self.cursor, c);
+            self.cursor += 1;
+        }
+    }
+
+    pub fn handle(&mut self, key: Key) -> Edit {
+        match key {
+            Key::Char(c) => self.insert(&c.to_string()),
+            Key::Enter => {
+                let line = self.line();
+                self.line.clear();
+                self.cursor = 0;
+                self.browsing = None;
+                let trimmed = line.trim();
+                if !trimmed.is_empty() && self.history.last().map(String::as_str) != Some(trimmed) {
+                    self.history.push(trimmed.to_string());
+                }
+                return Edit::Submit(line);
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.line.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.line.len() => {
+                self.line.remove(self.cursor);
+            }
+            Key::Tab => return Edit::Complete,
+            Key::Left => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right => self.cursor = (self.cursor + 1).min(self.line.len()),
+            Key::Home => self.cursor = 0,
+            Key::End => self.cursor = self.line.len(),
+            Key::Up => self.browse_back(),
+            Key::Down => self.browse_forward(),
+            Key::Clear => {
+                self.line.clear();
+                self.cursor = 0;
+            }
+            Key::Interrupt => {
+                self.line.clear();
+                self.cursor = 0;
+                self.browsing = None;
+                return Edit::Interrupted;
+            }
+            Key::Eof if self.line.is_empty() => return Edit::Eof,
+            Key::Backspace | Key::Delete | Key::Eof => {}
+        }
+        Edit::Changed
+    }
+
+    fn browse_back(&mut self) {
+        let index = match self.browsing {
+            Some((0, _)) => return,
+            Some((index, _)) => index - 1,
+            None if self.history.is_empty() => return,
+            None => {
+                self.browsing = Some((self.history.len(), self.line.clone()));
+                self.history.len() - 1
+            }
+        };
+        if let Some((ref mut browsed, _)) = self.browsing {
+            *browsed = index;
+        }
+        self.line = self.history[index].chars().collect();
+        self.cursor = self.line.len();
+    }
+
+    fn browse_forward(&mut self) {
+        let (index, edited) = match self.browsing.take() {
+            Some(browsing) => browsing,
+            None => return,
+        };
+        if index + 1 < self.history.len() {
+            self.line = self.history[index + 1].chars().collect();
+            self.browsing = Some((index + 1, edited));
+        } else {
+            self.line = edited;
+        }
+        self.cursor = self.line.len();
+    }
+
+    /// Redraws the prompt and the line, leaving the terminal cursor at the editing position
+    pub fn draw<W: Write>(&self, writer: &mut W, prompt: &str) -> io::Result<()> {
+        write!(writer, "\r\x1b[K{}{}", prompt, self.line())?;
+        let back = self.line.len() - self.cursor;
+        if back > 0 {
+            write!(writer, "\x1b[{}D", back)?;
+        }
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::parse_keys;
+
+    fn type_text(editor: &mut Editor, text: &str) {
+        for key in parse_keys(text) {
+            editor.handle(key);
+        }
+    }
+
+    #[test]
+    fn test_edit_line() {
+        let mut editor = Editor::default();
+        type_text(&mut editor, "stat all\x1b[D\x1b[D\x1b[D\x1b[De");
+
+        assert_eq!("state all", editor.line());
+        assert_eq!("state", editor.before_cursor());
+        assert_eq!(
+            Edit::Submit("state all".to_string()),
+            editor.handle(Key::Enter)
+        );
+        assert_eq!(Edit::Eof, editor.handle(Key::Eof));
+    }
+
+    #[test]
+    fn test_browse_history() {
+        let mut editor = Editor::new(vec!["status".to_string(), "load-stats".to_string()]);
+        type_text(&mut editor, "pi");
+
+        editor.handle(Key::Up);
+        assert_eq!("load-stats", editor.line());
+        editor.handle(Key::Up);
+        editor.handle(Key::Up);
+        assert_eq!("status", editor.line());
+        editor.handle(Key::Down);
+        editor.handle(Key::Down);
+        assert_eq!("pi", editor.line());
+
+        type_text(&mut editor, "d\r");
+        type_text(&mut editor, "pid\r");
+        assert_eq!(&["status", "load-stats", "pid"], editor.history());
+    }
+}
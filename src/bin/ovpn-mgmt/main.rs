@@ -0,0 +1,440 @@
+//! `ovpn-mgmt`, a command line client for the openvpn management interface
+#[path = "../shared/connection.rs"]
+mod connection;
+mod editor;
+mod repl;
+#[path = "../shared/terminal.rs"]
+mod terminal;
+
+use clap::{Parser, Subcommand, ValueEnum};
+use connection::ConnectionArgs;
+use openvpn_management::{
+    EventManager, LogEntry, Notification, Session, Signal, StateRecord, Status,
+};
+use serde_json::{json, Map, Value};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(
+    name = "ovpn-mgmt",
+    version,
+    about = "Queries and controls openvpn over its management interface"
+)]
+struct Cli {
+    #[command(flatten)]
+    connection: ConnectionArgs,
+    /// Output format
+    #[arg(long, short, value_enum, default_value_t = Format::Table)]
+    format: Format,
+    /// Seconds to wait for a reply
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Shows the server status summary
+    Status,
+    /// Lists the connected clients
+    Clients,
+    /// Disconnects clients by common name or real address:port, or by client ID
+    Kill {
+        /// Common name or real address:port of the clients to disconnect
+        #[arg(required_unless_present = "cid")]
+        target: Option<String>,
+        /// Client ID of the client to disconnect
+        #[arg(long, conflicts_with = "target")]
+        cid: Option<u64>,
+        /// Message sent to the client disconnected by client ID, e.g. HALT
+        #[arg(long, requires = "cid")]
+        message: Option<String>,
+    },
+    /// Sends a signal to openvpn: SIGHUP, SIGTERM, SIGUSR1 or SIGUSR2
+    Signal { signal: Signal },
+    /// Shows the current state, or all the states with --all
+    State {
+        #[arg(long)]
+        all: bool,
+    },
+    /// Shows the log history, and the new lines with --follow
+    Log {
+        /// Number of history lines to show, all by default
+        #[arg(long, short = 'n')]
+        lines: Option<usize>,
+        /// Keeps printing new log lines as they arrive
+        #[arg(long, short = 'F')]
+        follow: bool,
+    },
+    /// Controls the hold state openvpn starts in with --management-hold
+    Hold {
+        #[command(subcommand)]
+        action: HoldAction,
+    },
+    /// Shows the global server statistics
+    LoadStats,
+    /// Starts an interactive prompt with completion, help and history
+    Repl {
+        /// History file, ~/.ovpn_mgmt_history by default
+        #[arg(long)]
+        history: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand)]
+enum HoldAction {
+    /// Lets openvpn start
+    Release,
+}
+
+/// Rows of cells printed as a table, JSON or CSV
+struct Output {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl Output {
+    fn new(headers: Vec<&'static str>) -> Output {
+        Output {
+            headers,
+            rows: Vec::new(),
+        }
+    }
+
+    fn message(message: String) -> Output {
+        let mut output = Output::new(vec!["result"]);
+        output.rows.push(vec![json!(message)]);
+        output
+    }
+
+    /// Renders the rows. A single record is rendered as a JSON object rather than an array
+    fn render(&self, format: Format, single: bool) -> String {
+        match format {
+            Format::Table => render_table(&self.headers, &self.rows),
+            Format::Csv => {
+                let mut lines = vec![csv_line(self.headers.iter().map(|h| h.to_string()))];
+                lines.extend(
+                    self.rows
+                        .iter()
+                        .map(|row| csv_line(row.iter().map(cell_text))),
+                );
+                lines.join("\n")
+            }
+            Format::Json => {
+                let mut objects: Vec<_> = self
+                    .rows
+                    .iter()
+                    .map(|row| json_object(&self.headers, row))
+                    .collect();
+                if single && objects.len() == 1 {
+                    objects.remove(0).to_string()
+                } else {
+                    Value::Array(objects).to_string()
+                }
+            }
+        }
+    }
+}
+
+fn render_table(headers: &[&str], rows: &[Vec<Value>]) -> String {
+    let cells: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(cell_text).collect())
+        .collect();
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(i, header)| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .chain(Some(header.len()))
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+    let line = |row: Vec<String>| {
+        let padded: Vec<_> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        padded.join("  ").trim_end().to_string()
+    };
+    let mut lines = vec![line(
+        headers.iter().map(|h| h.to_ascii_uppercase()).collect(),
+    )];
+    lines.extend(cells.into_iter().map(line));
+    lines.join("\n")
+}
+
+fn json_object(headers: &[&str], row: &[Value]) -> Value {
+    let mut object = Map::new();
+    for (header, cell) in headers.iter().zip(row) {
+        object.insert(header.to_string(), cell.clone());
+    }
+    Value::Object(object)
+}
+
+fn cell_text(cell: &Value) -> String {
+    match *cell {
+        Value::Null => String::new(),
+        Value::String(ref text) => text.clone(),
+        ref other => other.to_string(),
+    }
+}
+
+fn csv_line<I: Iterator<Item = String>>(cells: I) -> String {
+    let cells: Vec<_> = cells
+        .map(|cell| {
+            if cell.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", cell.replace('"', "\"\""))
+            } else {
+                cell
+            }
+        })
+        .collect();
+    cells.join(",")
+}
+
+fn state_row(state: &StateRecord) -> Vec<Value> {
+    vec![
+        json!(state.timestamp().to_rfc3339()),
+        json!(state.name()),
+        json!(state.description()),
+        json!(state.local_ip().map(|ip| ip.to_string())),
+        json!(state.remote_ip().map(|ip| ip.to_string())),
+        json!(state.remote_port()),
+    ]
+}
+
+fn log_row(entry: &LogEntry) -> Vec<Value> {
+    vec![
+        json!(entry.timestamp().to_rfc3339()),
+        json!(entry.flags()),
+        json!(entry.message()),
+    ]
+}
+
+fn clients_output(status: &Status) -> Output {
+    let mut output = Output::new(vec![
+        "cid",
+        "common_name",
+        "real_address",
+        "connected_since",
+        "bytes_received",
+        "bytes_sent",
+    ]);
+    for client in status.clients() {
+        output.rows.push(vec![
+            json!(client.cid()),
+            json!(client.name()),
+            json!(client.ip_address()),
+            json!(client.connected_since().to_rfc3339()),
+            json!(client.bytes_received()),
+            json!(client.bytes_sent()),
+        ]);
+    }
+    output
+}
+
+const STATE_HEADERS: [&str; 6] = [
+    "timestamp",
+    "state",
+    "description",
+    "local_ip",
+    "remote_ip",
+    "remote_port",
+];
+const LOG_HEADERS: [&str; 3] = ["timestamp", "flags", "message"];
+
+fn open_session(cli: &Cli, read_timeout: Option<Duration>) -> openvpn_management::Result<Session> {
+    cli.connection
+        .builder(Some(Duration::from_secs(cli.timeout)), read_timeout)?
+        .build()?
+        .open_session()
+}
+
+fn run(cli: &Cli) -> openvpn_management::Result<()> {
+    let follow = matches!(cli.command, Command::Log { follow: true, .. });
+    let read_timeout = if follow {
+        None
+    } else {
+        Some(Duration::from_secs(cli.timeout))
+    };
+    let mut session = open_session(cli, read_timeout)?;
+    let (output, single) = match cli.command {
+        Command::Status => {
+            let status = session.get_status()?;
+            let mut output = Output::new(vec!["title", "timestamp", "clients"]);
+            output.rows.push(vec![
+                json!(status.title()),
+                json!(status.timestamp().to_rfc3339()),
+                json!(status.clients().len()),
+            ]);
+            (output, true)
+        }
+        Command::Clients => (clients_output(&session.get_status()?), false),
+        Command::Kill {
+            ref target,
+            cid,
+            ref message,
+        } => match (target, cid) {
+            (_, Some(cid)) => {
+                session.client_kill(cid, message.as_deref())?;
+                (Output::message(format!("client {} killed", cid)), true)
+            }
+            (Some(target), None) => (Output::message(session.kill(target)?), true),
+            (None, None) => unreachable!("clap requires a target or a client ID"),
+        },
+        Command::Signal { signal } => {
+            session.signal(signal)?;
+            (Output::message(format!("{} sent", signal)), true)
+        }
+        Command::State { all } => {
+            let mut output = Output::new(STATE_HEADERS.to_vec());
+            if all {
+                output.rows = session.state_history()?.iter().map(state_row).collect();
+                (output, false)
+            } else {
+                output.rows.push(state_row(&session.state()?));
+                (output, true)
+            }
+        }
+        Command::Log { lines, follow } => {
+            let mut output = Output::new(LOG_HEADERS.to_vec());
+            output.rows = session.log_history(lines)?.iter().map(log_row).collect();
+            if follow {
+                return follow_log(&mut session, output, cli.format);
+            }
+            (output, false)
+        }
+        Command::Hold {
+            action: HoldAction::Release,
+        } => {
+            session.hold_release()?;
+            (Output::message("hold released".to_string()), true)
+        }
+        Command::LoadStats => {
+            let stats = session.load_stats()?;
+            let mut output = Output::new(vec!["clients", "bytes_in", "bytes_out"]);
+            output.rows.push(vec![
+                json!(stats.clients()),
+                json!(stats.bytes_in()),
+                json!(stats.bytes_out()),
+            ]);
+            (output, true)
+        }
+        Command::Repl { ref history } => {
+            let history = history.clone().or_else(repl::default_history);
+            return repl::run(&mut session, cli.format, history);
+        }
+    };
+    println!("{}", output.render(cli.format, single));
+    Ok(())
+}
+
+/// Prints the history then each new log line as it arrives. JSON is printed one object per
+/// line and the table is not aligned past the history
+fn follow_log(
+    session: &mut Session,
+    history: Output,
+    format: Format,
+) -> openvpn_management::Result<()> {
+    match format {
+        Format::Json => {
+            for row in &history.rows {
+                println!("{}", json_object(&history.headers, row));
+            }
+        }
+        _ => println!("{}", history.render(format, false)),
+    }
+    session.log_on()?;
+    loop {
+        if let Notification::Log(entry) = session.next_notification()? {
+            let row = log_row(&entry);
+            match format {
+                Format::Json => println!("{}", json_object(&LOG_HEADERS, &row)),
+                Format::Csv => println!("{}", csv_line(row.iter().map(cell_text))),
+                Format::Table => println!(
+                    "{}  {}  {}",
+                    cell_text(&row[0]),
+                    cell_text(&row[1]),
+                    cell_text(&row[2])
+                ),
+            }
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("ovpn-mgmt: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn clients() -> Output {
+        let mut output = Output::new(vec!["common_name", "bytes_received"]);
+        output.rows.push(vec![json!("alice"), json!(100)]);
+        output
+            .rows
+            .push(vec![json!("bob, \"the builder\""), json!(2048)]);
+        output
+    }
+
+    #[test]
+    fn test_render_table() {
+        assert_eq!(
+            "COMMON_NAME         BYTES_RECEIVED\nalice               100\nbob, \"the builder\"  2048",
+            clients().render(Format::Table, false)
+        );
+    }
+
+    #[test]
+    fn test_render_csv_quotes_cells() {
+        assert_eq!(
+            "common_name,bytes_received\nalice,100\n\"bob, \"\"the builder\"\"\",2048",
+            clients().render(Format::Csv, false)
+        );
+    }
+
+    #[test]
+    fn test_render_json() {
+        assert_eq!(
+            r#"[{"bytes_received":100,"common_name":"alice"},{"bytes_received":2048,"common_name":"bob, \"the builder\""}]"#,
+            clients().render(Format::Json, false)
+        );
+        assert_eq!(
+            r#"{"result":"SIGHUP sent"}"#,
+            Output::message("SIGHUP sent".to_string()).render(Format::Json, true)
+        );
+    }
+
+    #[test]
+    fn test_cli_arguments() {
+        let cli = Cli::try_parse_from(["ovpn-mgmt", "-f", "json", "kill", "--cid", "7"]).unwrap();
+        assert_eq!(Format::Json, cli.format);
+        assert!(matches!(cli.command, Command::Kill { cid: Some(7), .. }));
+        assert!(Cli::try_parse_from(["ovpn-mgmt", "kill"]).is_err());
+        assert!(Cli::try_parse_from(["ovpn-mgmt", "signal", "SIGKILL"]).is_err());
+    }
+}
@@ -0,0 +1,755 @@
+//! The interactive `repl` subcommand: management commands are typed at a prompt, their
+//! replies pretty-printed and the notifications shown as they arrive
+use crate::editor::{Edit, Editor};
+use crate::terminal::{parse_keys, Key, RawMode};
+use crate::{clients_output, log_row, state_row, Format, Output, LOG_HEADERS, STATE_HEADERS};
+use openvpn_management::{
+    EventManager, LoadStats, LogEntry, Notification, OpenvpnError, Result, Session, StateRecord,
+};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+use std::time::Duration;
+
+const PROMPT: &str = "openvpn> ";
+/// Lines kept in the history file
+const HISTORY_SIZE: usize = 1000;
+/// Commands whose arguments are secrets, kept out of the history file
+const SECRET_COMMANDS: [&str; 4] = ["cr-response", "needstr", "password", "username"];
+/// Commands followed by lines up to `END`, which the REPL cannot send
+const BLOCK_COMMANDS: [&str; 5] = [
+    "certificate",
+    "client-auth",
+    "client-pf",
+    "pk-sig",
+    "rsa-sig",
+];
+
+const GREEN: &str = "\x1b[32m";
+const RED: &str = "\x1b[31m";
+const CYAN: &str = "\x1b[36m";
+const RESET: &str = "\x1b[0m";
+
+/// A command of the management interface, or of the REPL itself
+struct CommandHelp {
+    name: &'static str,
+    usage: &'static str,
+    description: &'static str,
+    /// Keywords completed after the command name
+    arguments: &'static [&'static str],
+}
+
+const COMMANDS: &[CommandHelp] = &[
+    CommandHelp {
+        name: "auth-retry",
+        usage: "auth-retry none|nointeract|interact",
+        description: "Sets how openvpn retries after an authentication failure",
+        arguments: &["none", "nointeract", "interact"],
+    },
+    CommandHelp {
+        name: "bytecount",
+        usage: "bytecount n",
+        description: "Sends the traffic counters every n seconds, 0 to stop",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "client-auth-nt",
+        usage: "client-auth-nt cid kid",
+        description: "Authorizes a waiting client without pushing any configuration",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "client-deny",
+        usage: "client-deny cid kid \"reason\" [\"client reason\"]",
+        description: "Denies a waiting client",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "client-kill",
+        usage: "client-kill cid [message]",
+        description: "Disconnects a client by client ID, e.g. with the HALT message",
+        arguments: &["HALT", "RESTART"],
+    },
+    CommandHelp {
+        name: "cr-response",
+        usage: "cr-response response",
+        description: "Answers the challenge of a dynamic challenge/response",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "echo",
+        usage: "echo on|off|all|n",
+        description: "Turns the echo notifications on or off, or shows the last n",
+        arguments: &["on", "off", "all"],
+    },
+    CommandHelp {
+        name: "exit",
+        usage: "exit",
+        description: "Leaves the REPL",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "forget-passwords",
+        usage: "forget-passwords",
+        description: "Forgets the passwords entered so far",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "help",
+        usage: "help [command]",
+        description: "Lists the commands, or shows the usage of one",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "hold",
+        usage: "hold [on|off|release]",
+        description: "Shows or sets the hold flag, release lets openvpn start",
+        arguments: &["on", "off", "release"],
+    },
+    CommandHelp {
+        name: "kill",
+        usage: "kill cn|address:port",
+        description: "Disconnects clients by common name or real address:port",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "load-stats",
+        usage: "load-stats",
+        description: "Shows the global server statistics",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "log",
+        usage: "log on|off|all|n",
+        description: "Turns the log notifications on or off, or shows the last n lines",
+        arguments: &["on", "off", "all"],
+    },
+    CommandHelp {
+        name: "mute",
+        usage: "mute [n]",
+        description: "Shows or sets the log mute level",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "needok",
+        usage: "needok name ok|cancel",
+        description: "Confirms or cancels a >NEED-OK prompt",
+        arguments: &["ok", "cancel"],
+    },
+    CommandHelp {
+        name: "needstr",
+        usage: "needstr name \"value\"",
+        description: "Answers a >NEED-STR prompt",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "password",
+        usage: "password type password",
+        description: "Answers a >PASSWORD request",
+        arguments: &["Auth", "\"Private Key\""],
+    },
+    CommandHelp {
+        name: "pid",
+        usage: "pid",
+        description: "Shows the process ID of openvpn",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "proxy",
+        usage: "proxy HTTP|SOCKS host port [nct] | proxy NONE",
+        description: "Answers a >PROXY request",
+        arguments: &["HTTP", "SOCKS", "NONE"],
+    },
+    CommandHelp {
+        name: "quit",
+        usage: "quit",
+        description: "Leaves the REPL",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "remote",
+        usage: "remote ACCEPT|SKIP | remote MOD host port",
+        description: "Answers a >REMOTE request",
+        arguments: &["ACCEPT", "SKIP", "MOD"],
+    },
+    CommandHelp {
+        name: "remote-entry-count",
+        usage: "remote-entry-count",
+        description: "Shows the number of remotes in the configuration",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "remote-entry-get",
+        usage: "remote-entry-get i|all [j]",
+        description: "Shows the remotes from i to j, or all of them",
+        arguments: &["all"],
+    },
+    CommandHelp {
+        name: "signal",
+        usage: "signal SIGHUP|SIGTERM|SIGUSR1|SIGUSR2",
+        description: "Sends a signal to openvpn",
+        arguments: &["SIGHUP", "SIGTERM", "SIGUSR1", "SIGUSR2"],
+    },
+    CommandHelp {
+        name: "state",
+        usage: "state [on|off|all|n]",
+        description:
+            "Shows the current state or the last n, or turns the state notifications on or off",
+        arguments: &["on", "off", "all"],
+    },
+    CommandHelp {
+        name: "status",
+        usage: "status [1|2|3]",
+        description: "Shows the connected clients",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "username",
+        usage: "username type username",
+        description: "Answers a >PASSWORD request for a username",
+        arguments: &["Auth"],
+    },
+    CommandHelp {
+        name: "verb",
+        usage: "verb [n]",
+        description: "Shows or sets the log verbosity",
+        arguments: &[],
+    },
+    CommandHelp {
+        name: "version",
+        usage: "version",
+        description: "Shows the versions of openvpn and of the management interface",
+        arguments: &[],
+    },
+];
+
+fn find_command(name: &str) -> Option<&'static CommandHelp> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// How the reply to a command is read and printed
+#[derive(Debug, PartialEq)]
+enum Reply {
+    /// Answered by the REPL without contacting openvpn
+    Local,
+    /// `status` parsed into the clients table
+    Status,
+    /// State records terminated by `END`
+    States,
+    /// Log lines terminated by `END`
+    Logs,
+    /// `load-stats` parsed into its counters
+    LoadStats,
+    /// Other lines terminated by `END`, printed as they are
+    Lines,
+    /// A single `SUCCESS:` or `ERROR:` line
+    Line,
+    /// A command followed by a block of lines, refused
+    Block,
+}
+
+fn reply_kind(name: &str, arguments: &[&str]) -> Reply {
+    let history = match arguments {
+        [] => true,
+        [argument] => *argument == "all" || argument.parse::<u32>().is_ok(),
+        _ => false,
+    };
+    match name {
+        "help" | "exit" | "quit" => Reply::Local,
+        "status" if arguments.is_empty() => Reply::Status,
+        "status" | "version" | "remote-entry-count" | "remote-entry-get" => Reply::Lines,
+        "state" if history => Reply::States,
+        "log" if history && !arguments.is_empty() => Reply::Logs,
+        "echo" if history && !arguments.is_empty() => Reply::Lines,
+        "load-stats" => Reply::LoadStats,
+        _ if BLOCK_COMMANDS.contains(&name) => Reply::Block,
+        _ => Reply::Line,
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Completion {
+    /// The text completing the word under the cursor
+    Insert(String),
+    /// Several words match what was typed
+    Candidates(Vec<&'static str>),
+    /// Nothing to complete, the usage of the command is shown instead
+    Usage(&'static str),
+    Nothing,
+}
+
+/// Completes the command name or the argument keyword being typed at the end of the line
+fn complete(line: &str) -> Completion {
+    let mut words: Vec<&str> = line.split_whitespace().collect();
+    let partial = if line.ends_with(char::is_whitespace) || line.is_empty() {
+        ""
+    } else {
+        words.pop().unwrap_or_default()
+    };
+    let (candidates, usage): (Vec<&'static str>, _) = match words.first() {
+        None => (COMMANDS.iter().map(|command| command.name).collect(), None),
+        Some(name) => match find_command(name) {
+            Some(command) => (command.arguments.to_vec(), Some(command.usage)),
+            None => return Completion::Nothing,
+        },
+    };
+    let matching: Vec<&'static str> = candidates
+        .into_iter()
+        .filter(|candidate| candidate.starts_with(partial))
+        .collect();
+    match matching[..] {
+        [] => usage.map_or(Completion::Nothing, Completion::Usage),
+        [word] => Completion::Insert(format!("{} ", &word[partial.len()..])),
+        _ => {
+            let common = matching[1..].iter().fold(matching[0], |common, word| {
+                let length = common
+                    .chars()
+                    .zip(word.chars())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                &common[..length]
+            });
+            if common.len() > partial.len() {
+                Completion::Insert(common[partial.len()..].to_string())
+            } else {
+                Completion::Candidates(matching)
+            }
+        }
+    }
+}
+
+fn help(command: Option<&str>) -> String {
+    match command {
+        Some(name) => match find_command(name) {
+            Some(command) => format!("{}\n  {}", command.usage, command.description),
+            None => format!("unknown command {}", name),
+        },
+        None => {
+            let width = COMMANDS.iter().map(|c| c.name.len()).max().unwrap_or(0);
+            let lines: Vec<_> = COMMANDS
+                .iter()
+                .map(|c| format!("{:width$}  {}", c.name, c.description, width = width))
+                .collect();
+            lines.join("\n")
+        }
+    }
+}
+
+/// A one line summary of a notification
+fn describe(notification: &Notification) -> String {
+    match *notification {
+        Notification::State(ref state) => format!(
+            ">STATE {} {} {}",
+            state.timestamp().to_rfc3339(),
+            state.name(),
+            state.description()
+        ),
+        Notification::Log(ref entry) => format!(
+            ">LOG {} {} {}",
+            entry.timestamp().to_rfc3339(),
+            entry.flags(),
+            entry.message()
+        ),
+        Notification::ByteCount(ref count) => match count.cid() {
+            Some(cid) => format!(
+                ">BYTECOUNT_CLI cid={} in={} out={}",
+                cid,
+                count.bytes_received(),
+                count.bytes_sent()
+            ),
+            None => format!(
+                ">BYTECOUNT in={} out={}",
+                count.bytes_received(),
+                count.bytes_sent()
+            ),
+        },
+        Notification::Client(ref event) => format!(
+            ">CLIENT {:?} cid={} cn={}",
+            event.kind(),
+            event.cid(),
+            event.env().common_name().unwrap_or("-")
+        ),
+        Notification::Info(ref message) => format!(">INFO {}", message),
+        Notification::Other {
+            ref kind,
+            ref payload,
+        } => format!(">{}:{}", kind, payload),
+        ref other => format!("{:?}", other),
+    }
+}
+
+/// Writes to the terminal, with colours only on a terminal
+struct Console {
+    colour: bool,
+    editing: bool,
+}
+
+impl Console {
+    fn paint(&self, colour: &str, text: &str) -> String {
+        if self.colour {
+            format!("{}{}{}", colour, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+
+    /// Prints above the line being edited
+    fn print(&self, text: &str, editor: &Editor) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        if self.editing {
+            write!(stdout, "\r\x1b[K")?;
+        }
+        writeln!(stdout, "{}", text)?;
+        if self.editing {
+            editor.draw(&mut stdout, PROMPT)?;
+        }
+        stdout.flush()
+    }
+}
+
+/// Runs a command line and returns the text to print, or `None` to leave the REPL. Failed
+/// commands are reported in the text, and only the connection errors are returned
+fn execute(
+    session: &mut Session,
+    console: &Console,
+    format: Format,
+    line: &str,
+) -> Result<Option<String>> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let (name, arguments) = match words.split_first() {
+        Some((name, arguments)) => (*name, arguments),
+        None => return Ok(Some(String::new())),
+    };
+    let result = match reply_kind(name, arguments) {
+        Reply::Local if name == "help" => Ok(help(arguments.first().copied())),
+        Reply::Local => return Ok(None),
+        Reply::Block => {
+            let message = format!(
+                "{} takes a block of lines, which the REPL cannot send",
+                name
+            );
+            return Ok(Some(console.paint(RED, &message)));
+        }
+        Reply::Status => session
+            .get_status()
+            .map(|status| clients_output(&status).render(format, false)),
+        Reply::States => session.multiline_command(line).and_then(|lines| {
+            let mut output = Output::new(STATE_HEADERS.to_vec());
+            for line in &lines {
+                output.rows.push(state_row(&StateRecord::parse(line)?));
+            }
+            Ok(output.render(format, false))
+        }),
+        Reply::Logs => session.multiline_command(line).and_then(|lines| {
+            let mut output = Output::new(LOG_HEADERS.to_vec());
+            for line in &lines {
+                output.rows.push(log_row(&LogEntry::parse(line)?));
+            }
+            Ok(output.render(format, false))
+        }),
+        Reply::LoadStats => session.command(line).and_then(|message| {
+            let stats = LoadStats::parse(&message)?;
+            let mut output = Output::new(vec!["clients", "bytes_in", "bytes_out"]);
+            output.rows.push(vec![
+                json!(stats.clients()),
+                json!(stats.bytes_in()),
+                json!(stats.bytes_out()),
+            ]);
+            Ok(output.render(format, true))
+        }),
+        Reply::Lines => session
+            .multiline_command(line)
+            .map(|lines| lines.join("\n")),
+        Reply::Line => session
+            .command(line)
+            .map(|message| console.paint(GREEN, &format!("SUCCESS: {}", message))),
+    };
+    match result {
+        Ok(text) => Ok(Some(text)),
+        Err(OpenvpnError::Io(e)) => Err(OpenvpnError::Io(e)),
+        Err(OpenvpnError::CommandFailed(message)) => {
+            let mut text = console.paint(RED, &format!("ERROR: {}", message));
+            if let Some(command) = find_command(name) {
+                text.push_str(&format!("\nusage: {}", command.usage));
+            }
+            Ok(Some(text))
+        }
+        Err(e) => Ok(Some(console.paint(RED, &e.to_string()))),
+    }
+}
+
+enum Input {
+    /// Keys typed on a terminal in raw mode
+    Keys(String),
+    /// A line read from a pipe
+    Line(String),
+    Closed,
+}
+
+fn read_input(editing: bool) -> Receiver<Input> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        if editing {
+            let mut stdin = io::stdin().lock();
+            let mut buffer = [0; 256];
+            while let Ok(length @ 1..) = stdin.read(&mut buffer) {
+                let keys = String::from_utf8_lossy(&buffer[..length]).into_owned();
+                if sender.send(Input::Keys(keys)).is_err() {
+                    return;
+                }
+            }
+        } else {
+            for line in io::stdin().lock().lines().map_while(|line| line.ok()) {
+                if sender.send(Input::Line(line)).is_err() {
+                    return;
+                }
+            }
+        }
+        let _ = sender.send(Input::Closed);
+    });
+    receiver
+}
+
+fn is_terminal(fd: i32) -> bool {
+    // SAFETY: isatty only inspects the file descriptor
+    #[cfg(unix)]
+    unsafe {
+        libc::isatty(fd) == 1
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = fd;
+        false
+    }
+}
+
+fn load_history(path: Option<&Path>) -> Vec<String> {
+    path.and_then(|path| fs::read_to_string(path).ok())
+        .map(|contents| contents.lines().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+fn is_secret(line: &str) -> bool {
+    line.split_whitespace()
+        .next()
+        .is_some_and(|name| SECRET_COMMANDS.contains(&name))
+}
+
+/// Writes the history readable by the user only, without the commands holding secrets
+fn save_history(path: Option<&Path>, history: &[String]) {
+    if let Some(path) = path {
+        let history: Vec<_> = history.iter().filter(|line| !is_secret(line)).collect();
+        let start = history.len().saturating_sub(HISTORY_SIZE);
+        let mut contents = String::new();
+        for line in &history[start..] {
+            contents.push_str(line);
+            contents.push('\n');
+        }
+        if let Err(e) = write_private(path, &contents) {
+            eprintln!(
+                "ovpn-mgmt: cannot save the history to {}: {}",
+                path.display(),
+                e
+            );
+        }
+    }
+}
+
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        let file = options.open(path)?;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        (&file).write_all(contents.as_bytes())
+    }
+    #[cfg(not(unix))]
+    {
+        options.open(path)?.write_all(contents.as_bytes())
+    }
+}
+
+/// The history file used when none is given, `~/.ovpn_mgmt_history`
+pub fn default_history() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ovpn_mgmt_history"))
+}
+
+/// Reads commands until `exit`, Ctrl-D or the end of the input. Line editing is only done when
+/// the input is a terminal, otherwise the lines are read as they come, e.g. from a script
+pub fn run(session: &mut Session, format: Format, history_path: Option<PathBuf>) -> Result<()> {
+    let editing = is_terminal(0) && is_terminal(1);
+    let console = Console {
+        colour: is_terminal(1),
+        editing,
+    };
+    let history_path = history_path.as_deref();
+    let mut editor = Editor::new(load_history(history_path));
+    let _raw_mode = if editing {
+        Some(RawMode::enter()?)
+    } else {
+        None
+    };
+    let input = read_input(editing);
+    if editing {
+        println!("Type help to list the commands, Tab to complete them");
+        editor.draw(&mut io::stdout(), PROMPT)?;
+    }
+    let mut keys = VecDeque::new();
+    let result = (|| loop {
+        if let Some(notification) = session.poll_notification(Duration::from_millis(50))? {
+            console.print(&console.paint(CYAN, &describe(&notification)), &editor)?;
+            continue;
+        }
+        let line = match next_line(&input, &mut keys, &mut editor, &console)? {
+            Some(Next::Line(line)) => line,
+            Some(Next::Eof) => return Ok(()),
+            None => continue,
+        };
+        match execute(session, &console, format, &line)? {
+            Some(text) if text.is_empty() => {}
+            Some(text) => println!("{}", text),
+            None => return Ok(()),
+        }
+        if editing {
+            editor.draw(&mut io::stdout(), PROMPT)?;
+        }
+    })();
+    if editing {
+        save_history(history_path, editor.history());
+    }
+    result
+}
+
+enum Next {
+    Line(String),
+    Eof,
+}
+
+/// Handles the input received so far until a line is entered. The keys typed after that line
+/// are kept for the next call, so the notifications are printed between the commands
+fn next_line(
+    input: &Receiver<Input>,
+    keys: &mut VecDeque<Key>,
+    editor: &mut Editor,
+    console: &Console,
+) -> io::Result<Option<Next>> {
+    loop {
+        let key = match keys.pop_front() {
+            Some(key) => key,
+            None => {
+                match input.try_recv() {
+                    Ok(Input::Line(line)) => return Ok(Some(Next::Line(line))),
+                    Ok(Input::Keys(typed)) => keys.extend(parse_keys(&typed)),
+                    Ok(Input::Closed) | Err(TryRecvError::Disconnected) => {
+                        return Ok(Some(Next::Eof))
+                    }
+                    Err(TryRecvError::Empty) => return Ok(None),
+                }
+                continue;
+            }
+        };
+        match editor.handle(key) {
+            Edit::Submit(line) => {
+                // the last keys typed may not have been drawn yet
+                println!("\r\x1b[K{}{}", PROMPT, line);
+                return Ok(Some(Next::Line(line)));
+            }
+            Edit::Complete => match complete(&editor.before_cursor()) {
+                Completion::Insert(text) => editor.insert(&text),
+                Completion::Candidates(words) => console.print(&words.join("  "), editor)?,
+                Completion::Usage(usage) => console.print(&format!("usage: {}", usage), editor)?,
+                Completion::Nothing => {}
+            },
+            Edit::Interrupted => println!("^C"),
+            Edit::Eof => {
+                println!();
+                return Ok(Some(Next::Eof));
+            }
+            Edit::Changed => {}
+        }
+        if keys.is_empty() {
+            editor.draw(&mut io::stdout(), PROMPT)?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_every_command_has_help() {
+        assert!(COMMANDS.windows(2).all(|pair| pair[0].name < pair[1].name));
+        for command in COMMANDS {
+            assert!(command.usage.starts_with(command.name));
+            assert!(help(Some(command.name)).contains(command.description));
+        }
+        assert_eq!("unknown command frobnicate", help(Some("frobnicate")));
+    }
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(Completion::Insert("s ".to_string()), complete("statu"));
+        assert_eq!(Completion::Insert("at".to_string()), complete("st"));
+        assert_eq!(
+            Completion::Candidates(vec!["state", "status"]),
+            complete("stat")
+        );
+        assert_eq!(
+            Completion::Insert("SR".to_string()),
+            complete("signal SIGU")
+        );
+        assert_eq!(
+            Completion::Insert("elease ".to_string()),
+            complete("hold r")
+        );
+        assert_eq!(Completion::Usage("kill cn|address:port"), complete("kill "));
+        assert_eq!(Completion::Nothing, complete("frobnicate "));
+    }
+
+    #[test]
+    fn test_reply_kind() {
+        assert_eq!(Reply::Status, reply_kind("status", &[]));
+        assert_eq!(Reply::Lines, reply_kind("status", &["2"]));
+        assert_eq!(Reply::States, reply_kind("state", &[]));
+        assert_eq!(Reply::States, reply_kind("state", &["all"]));
+        assert_eq!(Reply::Line, reply_kind("state", &["on"]));
+        assert_eq!(Reply::Logs, reply_kind("log", &["20"]));
+        assert_eq!(Reply::Line, reply_kind("log", &["off"]));
+        assert_eq!(Reply::Line, reply_kind("hold", &[]));
+        assert_eq!(Reply::LoadStats, reply_kind("load-stats", &[]));
+        assert_eq!(Reply::Local, reply_kind("help", &["kill"]));
+        assert_eq!(Reply::Lines, reply_kind("remote-entry-count", &[]));
+        assert_eq!(Reply::Block, reply_kind("client-auth", &["1", "2"]));
+    }
+
+    #[test]
+    fn test_history_file_without_secrets() {
+        let path =
+            std::env::temp_dir().join(format!("openvpn-management-history-{}", std::process::id()));
+        let history: Vec<String> = ["status", "password Auth secret", "username Auth bob", "pid"]
+            .iter()
+            .map(|line| line.to_string())
+            .collect();
+
+        save_history(Some(&path), &history);
+        let contents = fs::read_to_string(&path).unwrap();
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            fs::metadata(&path).unwrap().permissions().mode()
+        };
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!("status\npid\n", contents);
+        #[cfg(unix)]
+        assert_eq!(0o600, mode & 0o777);
+    }
+}
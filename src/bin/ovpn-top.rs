@@ -0,0 +1,632 @@
+//! `ovpn-top`, a live terminal dashboard of an openvpn server
+#[path = "shared/connection.rs"]
+mod connection;
+#[cfg(test)]
+#[path = "../fixtures.rs"]
+mod fixtures;
+#[path = "shared/terminal.rs"]
+mod terminal;
+
+use chrono::prelude::{DateTime, Utc};
+use clap::Parser;
+use connection::ConnectionArgs;
+#[cfg(test)]
+use openvpn_management::Client;
+use openvpn_management::{
+    ByteCount, EventManager, LogEntry, Notification, Session, Signal, StateRecord, Status,
+};
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, Read, Write};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use terminal::{parse_keys, Key, RawMode};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const LOG_CAPACITY: usize = 500;
+const HELP: &str = "\u{2191}/\u{2193} select  s sort  x kill  ! signal  q quit";
+
+#[derive(Parser)]
+#[command(
+    name = "ovpn-top",
+    version,
+    about = "Live dashboard of an openvpn server over its management interface"
+)]
+struct Cli {
+    #[command(flatten)]
+    connection: ConnectionArgs,
+    /// Seconds between status refreshes and byte counts
+    #[arg(long, short, default_value_t = 2)]
+    interval: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SortKey {
+    Throughput,
+    Duration,
+    Name,
+}
+
+impl SortKey {
+    fn next(self) -> SortKey {
+        match self {
+            SortKey::Throughput => SortKey::Duration,
+            SortKey::Duration => SortKey::Name,
+            SortKey::Name => SortKey::Throughput,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SortKey::Throughput => "throughput",
+            SortKey::Duration => "duration",
+            SortKey::Name => "name",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Action {
+    Quit,
+    Kill(u64),
+    Signal(Signal),
+}
+
+#[derive(Debug, PartialEq)]
+enum Prompt {
+    None,
+    Kill { cid: u64, name: String },
+    Signal,
+}
+
+/// A connected client with the throughput measured between its last two byte counts
+#[derive(Clone, Debug, PartialEq)]
+struct Row {
+    cid: Option<u64>,
+    name: String,
+    address: String,
+    connected_since: DateTime<Utc>,
+    bytes_received: f64,
+    bytes_sent: f64,
+    received_rate: f64,
+    sent_rate: f64,
+    updated: Instant,
+}
+
+impl Row {
+    fn key(&self) -> String {
+        match self.cid {
+            Some(cid) => cid.to_string(),
+            None => format!("{}@{}", self.name, self.address),
+        }
+    }
+
+    fn update(&mut self, bytes_received: f64, bytes_sent: f64, now: Instant) {
+        let elapsed = now.duration_since(self.updated).as_secs_f64();
+        if elapsed > 0.0 {
+            self.received_rate = (bytes_received - self.bytes_received).max(0.0) / elapsed;
+            self.sent_rate = (bytes_sent - self.bytes_sent).max(0.0) / elapsed;
+        }
+        self.bytes_received = bytes_received;
+        self.bytes_sent = bytes_sent;
+        self.updated = now;
+    }
+}
+
+struct Dashboard {
+    title: String,
+    state: Option<StateRecord>,
+    rows: Vec<Row>,
+    log: VecDeque<LogEntry>,
+    sort: SortKey,
+    selected: usize,
+    prompt: Prompt,
+    message: String,
+}
+
+impl Dashboard {
+    fn new() -> Dashboard {
+        Dashboard {
+            title: String::new(),
+            state: None,
+            rows: Vec::new(),
+            log: VecDeque::new(),
+            sort: SortKey::Throughput,
+            selected: 0,
+            prompt: Prompt::None,
+            message: String::new(),
+        }
+    }
+
+    /// Replaces the client list, keeping the throughput of the clients already listed
+    fn update_status(&mut self, status: &Status, now: Instant) {
+        let selected = self.selected_key();
+        let mut previous: HashMap<_, _> = self.rows.drain(..).map(|r| (r.key(), r)).collect();
+        self.title = status.title().to_string();
+        for client in status.clients() {
+            let mut row = Row {
+                cid: client.cid(),
+                name: client.name().to_string(),
+                address: client.ip_address().to_string(),
+                connected_since: *client.connected_since(),
+                bytes_received: client.bytes_received(),
+                bytes_sent: client.bytes_sent(),
+                received_rate: 0.0,
+                sent_rate: 0.0,
+                updated: now,
+            };
+            if let Some(mut known) = previous.remove(&row.key()) {
+                known.update(row.bytes_received, row.bytes_sent, now);
+                row = known;
+            }
+            self.rows.push(row);
+        }
+        self.sort_rows_selecting(selected);
+    }
+
+    fn apply_byte_count(&mut self, byte_count: &ByteCount, now: Instant) {
+        let row = self
+            .rows
+            .iter_mut()
+            .find(|r| r.cid.is_some() && r.cid == byte_count.cid());
+        if let Some(row) = row {
+            row.update(byte_count.bytes_received(), byte_count.bytes_sent(), now);
+            self.sort_rows();
+        }
+    }
+
+    fn push_log(&mut self, entry: LogEntry) {
+        if self.log.len() == LOG_CAPACITY {
+            self.log.pop_front();
+        }
+        self.log.push_back(entry);
+    }
+
+    fn selected_key(&self) -> Option<String> {
+        self.rows.get(self.selected).map(Row::key)
+    }
+
+    fn sort_rows(&mut self) {
+        let selected = self.selected_key();
+        self.sort_rows_selecting(selected);
+    }
+
+    /// Sorts the rows, the selection following the client with the given key
+    fn sort_rows_selecting(&mut self, selected: Option<String>) {
+        match self.sort {
+            SortKey::Throughput => self.rows.sort_by(|a, b| {
+                (b.received_rate + b.sent_rate).total_cmp(&(a.received_rate + a.sent_rate))
+            }),
+            SortKey::Duration => self.rows.sort_by_key(|r| r.connected_since),
+            SortKey::Name => self.rows.sort_by(|a, b| a.name.cmp(&b.name)),
+        }
+        self.selected = selected
+            .and_then(|key| self.rows.iter().position(|r| r.key() == key))
+            .unwrap_or(0);
+    }
+
+    fn handle_key(&mut self, key: Key) -> Option<Action> {
+        match std::mem::replace(&mut self.prompt, Prompt::None) {
+            Prompt::Kill { cid, .. } => {
+                return match key {
+                    Key::Char('y') | Key::Char('Y') => Some(Action::Kill(cid)),
+                    _ => None,
+                };
+            }
+            Prompt::Signal => {
+                return match key {
+                    Key::Char('h') => Some(Action::Signal(Signal::Hup)),
+                    Key::Char('t') => Some(Action::Signal(Signal::Term)),
+                    Key::Char('1') => Some(Action::Signal(Signal::Usr1)),
+                    Key::Char('2') => Some(Action::Signal(Signal::Usr2)),
+                    _ => None,
+                };
+            }
+            Prompt::None => {}
+        }
+        match key {
+            Key::Char('q') | Key::Interrupt => return Some(Action::Quit),
+            Key::Up => self.selected = self.selected.saturating_sub(1),
+            Key::Down if self.selected + 1 < self.rows.len() => self.selected += 1,
+            Key::Char('s') => {
+                self.sort = self.sort.next();
+                self.sort_rows();
+            }
+            Key::Char('x') => match self.rows.get(self.selected) {
+                Some(Row {
+                    cid: Some(cid),
+                    name,
+                    ..
+                }) => {
+                    self.prompt = Prompt::Kill {
+                        cid: *cid,
+                        name: name.clone(),
+                    }
+                }
+                Some(_) => {
+                    self.message = "the client ID is unknown, openvpn 2.4 or later lists it".into()
+                }
+                None => {}
+            },
+            Key::Char('!') => self.prompt = Prompt::Signal,
+            _ => {}
+        }
+        None
+    }
+
+    fn render(&self, width: usize, height: usize, now: DateTime<Utc>) -> Vec<String> {
+        let mut lines = Vec::new();
+        let state = match self.state {
+            Some(ref state) => format!(
+                "{} {} since {}",
+                state.name(),
+                state.description(),
+                state.timestamp().format("%H:%M:%S")
+            ),
+            None => "unknown".to_string(),
+        };
+        let (received, sent) = self.rows.iter().fold((0.0, 0.0), |(r, s), row| {
+            (r + row.received_rate, s + row.sent_rate)
+        });
+        lines.push(truncate(
+            &format!(
+                "{}  state: {}  clients: {}  in: {}/s  out: {}/s  sort: {}",
+                self.title,
+                state,
+                self.rows.len(),
+                format_bytes(received),
+                format_bytes(sent),
+                self.sort.label()
+            ),
+            width,
+        ));
+        lines.push(String::new());
+        lines.push(truncate(
+            &format!(
+                "{:>5}  {:<24}  {:<15}  {:>11}  {:>11}  {:>11}  {:>10}  {:>10}",
+                "CID", "COMMON NAME", "ADDRESS", "CONNECTED", "IN/S", "OUT/S", "IN", "OUT"
+            ),
+            width,
+        ));
+        let body = height.saturating_sub(lines.len() + 1);
+        let table_rows = if self.log.is_empty() {
+            body
+        } else {
+            body * 2 / 3
+        };
+        let offset = (self.selected + 1).saturating_sub(table_rows);
+        for (i, row) in self.rows.iter().enumerate().skip(offset).take(table_rows) {
+            let line = truncate(
+                &format!(
+                    "{:>5}  {:<24}  {:<15}  {:>11}  {:>11}  {:>11}  {:>10}  {:>10}",
+                    row.cid.map(|c| c.to_string()).unwrap_or_default(),
+                    truncate(&row.name, 24),
+                    truncate(&row.address, 15),
+                    format_duration((now - row.connected_since).num_seconds()),
+                    format!("{}/s", format_bytes(row.received_rate)),
+                    format!("{}/s", format_bytes(row.sent_rate)),
+                    format_bytes(row.bytes_received),
+                    format_bytes(row.bytes_sent)
+                ),
+                width,
+            );
+            if i == self.selected {
+                lines.push(format!("\x1b[7m{:<width$}\x1b[0m", line, width = width));
+            } else {
+                lines.push(line);
+            }
+        }
+        while lines.len() < 3 + table_rows {
+            lines.push(String::new());
+        }
+        let log_rows = height.saturating_sub(lines.len() + 1);
+        if log_rows > 0 && !self.log.is_empty() {
+            lines.push(truncate(
+                &format!("{:\u{2500}<width$}", "\u{2500} log ", width = width),
+                width,
+            ));
+            let skip = self.log.len().saturating_sub(log_rows - 1);
+            for entry in self.log.iter().skip(skip) {
+                let line = truncate(
+                    &format!(
+                        "{} {} {}",
+                        entry.timestamp().format("%H:%M:%S"),
+                        entry.flags(),
+                        entry.message()
+                    ),
+                    width,
+                );
+                if entry.is_error() {
+                    lines.push(format!("\x1b[31m{}\x1b[0m", line));
+                } else {
+                    lines.push(line);
+                }
+            }
+        }
+        while lines.len() + 1 < height {
+            lines.push(String::new());
+        }
+        let footer = match self.prompt {
+            Prompt::Kill { cid, ref name } => {
+                format!("Kill {} (client ID {})? y/n", name, cid)
+            }
+            Prompt::Signal => {
+                "Signal: h SIGHUP  t SIGTERM  1 SIGUSR1  2 SIGUSR2  other key cancels".to_string()
+            }
+            Prompt::None if !self.message.is_empty() => format!("{}  |  {}", self.message, HELP),
+            Prompt::None => HELP.to_string(),
+        };
+        lines.push(format!("\x1b[1m{}\x1b[0m", truncate(&footer, width)));
+        lines
+    }
+}
+
+fn truncate(text: &str, width: usize) -> String {
+    text.chars().take(width).collect()
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{:.0} {}", value, UNITS[unit])
+    } else {
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+}
+
+fn format_duration(seconds: i64) -> String {
+    let seconds = seconds.max(0);
+    let (days, rest) = (seconds / 86_400, seconds % 86_400);
+    let clock = format!(
+        "{:02}:{:02}:{:02}",
+        rest / 3600,
+        rest % 3600 / 60,
+        rest % 60
+    );
+    if days > 0 {
+        format!("{}d {}", days, clock)
+    } else {
+        clock
+    }
+}
+
+/// Puts the terminal in raw mode on the alternate screen, and restores it when dropped
+struct Terminal {
+    _raw_mode: RawMode,
+}
+
+impl Terminal {
+    fn enter() -> io::Result<Terminal> {
+        let raw_mode = RawMode::enter()?;
+        print!("\x1b[?1049h\x1b[?25l");
+        io::stdout().flush()?;
+        Ok(Terminal {
+            _raw_mode: raw_mode,
+        })
+    }
+
+    #[cfg(unix)]
+    fn size(&self) -> (usize, usize) {
+        // SAFETY: TIOCGWINSZ only writes to the winsize struct passed to it
+        let size = unsafe {
+            let mut size: libc::winsize = std::mem::zeroed();
+            if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) != 0 {
+                return (80, 24);
+            }
+            size
+        };
+        (usize::from(size.ws_col), usize::from(size.ws_row))
+    }
+
+    #[cfg(not(unix))]
+    fn size(&self) -> (usize, usize) {
+        (80, 24)
+    }
+
+    fn draw(&self, lines: &[String]) -> io::Result<()> {
+        let mut screen = String::from("\x1b[H");
+        for (i, line) in lines.iter().enumerate() {
+            if i > 0 {
+                screen.push_str("\r\n");
+            }
+            screen.push_str(line);
+            screen.push_str("\x1b[K");
+        }
+        screen.push_str("\x1b[J");
+        let mut stdout = io::stdout();
+        stdout.write_all(screen.as_bytes())?;
+        stdout.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1b[?25h\x1b[?1049l");
+        let _ = io::stdout().flush();
+    }
+}
+
+fn spawn_input() -> Receiver<Key> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 32];
+        let mut stdin = io::stdin();
+        while let Ok(read) = stdin.read(&mut buffer) {
+            if read == 0 {
+                break;
+            }
+            for key in parse_keys(&String::from_utf8_lossy(&buffer[..read])) {
+                if sender.send(key).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+    receiver
+}
+
+fn open_session(cli: &Cli) -> openvpn_management::Result<Session> {
+    let timeout = Some(Duration::from_secs(10));
+    cli.connection
+        .builder(timeout, timeout)?
+        .build()?
+        .open_session()
+}
+
+fn run(cli: &Cli) -> openvpn_management::Result<()> {
+    let mut session = open_session(cli)?;
+    let mut dashboard = Dashboard::new();
+    dashboard.state = Some(session.state()?);
+    for entry in session.log_history(Some(LOG_CAPACITY))? {
+        dashboard.push_log(entry);
+    }
+    session.state_on()?;
+    session.log_on()?;
+    session.bytecount(cli.interval)?;
+    let refresh = Duration::from_secs(u64::from(cli.interval.max(1)));
+
+    let terminal = Terminal::enter()?;
+    let keys = spawn_input();
+    let mut last_refresh: Option<Instant> = None;
+    loop {
+        let due = match last_refresh {
+            Some(at) => at.elapsed() >= refresh,
+            None => true,
+        };
+        if due {
+            let status = session.get_status()?;
+            dashboard.update_status(&status, Instant::now());
+            last_refresh = Some(Instant::now());
+        }
+        match session.poll_notification(POLL_INTERVAL)? {
+            Some(Notification::State(state)) => dashboard.state = Some(state),
+            Some(Notification::Log(entry)) => dashboard.push_log(entry),
+            Some(Notification::ByteCount(byte_count)) => {
+                dashboard.apply_byte_count(&byte_count, Instant::now())
+            }
+            _ => {}
+        }
+        while let Ok(key) = keys.try_recv() {
+            match dashboard.handle_key(key) {
+                Some(Action::Quit) => return Ok(()),
+                Some(Action::Kill(cid)) => {
+                    dashboard.message = match session.client_kill(cid, None) {
+                        Ok(()) => format!("client {} killed", cid),
+                        Err(e) => format!("killing client {} failed: {}", cid, e),
+                    };
+                    last_refresh = None;
+                }
+                Some(Action::Signal(signal)) => {
+                    dashboard.message = match session.signal(signal) {
+                        Ok(()) => format!("{} sent", signal),
+                        Err(e) => format!("sending {} failed: {}", signal, e),
+                    };
+                }
+                None => {}
+            }
+        }
+        let (width, height) = terminal.size();
+        terminal.draw(&dashboard.render(width, height, Utc::now()))?;
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("ovpn-top: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::client;
+    use chrono::TimeZone;
+
+    fn status(clients: &[(&str, u64, f64, i64)]) -> Status {
+        fixtures::status(
+            clients
+                .iter()
+                .map(|&(name, cid, bytes, since)| {
+                    client(name, "192.168.0.3", since, bytes, bytes).with_cid(cid)
+                })
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_throughput_from_byte_counts_and_sorting() {
+        let start = Instant::now();
+        let mut dashboard = Dashboard::new();
+        dashboard.update_status(
+            &status(&[("alice", 1, 0.0, 1000), ("bob", 2, 0.0, 2000)]),
+            start,
+        );
+        dashboard.apply_byte_count(
+            &ByteCount::new(Some(2), 4096.0, 2048.0),
+            start + Duration::from_secs(2),
+        );
+
+        assert_eq!("bob", dashboard.rows[0].name);
+        assert_eq!(1, dashboard.selected, "the selection follows alice");
+        assert_eq!(2048.0, dashboard.rows[0].received_rate);
+        assert_eq!(1024.0, dashboard.rows[0].sent_rate);
+
+        dashboard.handle_key(Key::Char('s'));
+        assert_eq!(SortKey::Duration, dashboard.sort);
+        assert_eq!("alice", dashboard.rows[0].name);
+        assert_eq!(0, dashboard.selected);
+    }
+
+    #[test]
+    fn test_kill_and_signal_prompts() {
+        let mut dashboard = Dashboard::new();
+        dashboard.update_status(&status(&[("alice", 4, 0.0, 1000)]), Instant::now());
+
+        assert_eq!(None, dashboard.handle_key(Key::Char('x')));
+        assert_eq!(Some(Action::Kill(4)), dashboard.handle_key(Key::Char('y')));
+        dashboard.handle_key(Key::Char('x'));
+        assert_eq!(None, dashboard.handle_key(Key::Char('n')));
+        assert_eq!(Prompt::None, dashboard.prompt);
+        dashboard.handle_key(Key::Char('!'));
+        assert_eq!(
+            Some(Action::Signal(Signal::Usr1)),
+            dashboard.handle_key(Key::Char('1'))
+        );
+        assert_eq!(Some(Action::Quit), dashboard.handle_key(Key::Char('q')));
+    }
+
+    #[test]
+    fn test_render() {
+        let mut dashboard = Dashboard::new();
+        dashboard.update_status(&status(&[("alice", 4, 2048.0, 1000)]), Instant::now());
+        dashboard
+            .push_log(LogEntry::parse("1547913893,I,Initialization Sequence Completed").unwrap());
+        let lines = dashboard.render(120, 12, Utc.timestamp_opt(1000 + 90_061, 0).unwrap());
+
+        assert_eq!(12, lines.len());
+        assert!(lines[0].starts_with("OpenVPN 2.6.8  state: unknown  clients: 1"));
+        assert!(lines[3].contains("alice"));
+        assert!(lines[3].contains("1d 01:01:01"));
+        assert!(lines[3].contains("2.0 KiB"));
+        assert!(lines[9].ends_with("Initialization Sequence Completed"));
+        assert!(lines[11].contains("q quit"));
+    }
+
+    #[test]
+    fn test_format_bytes() {
+        assert_eq!("512 B", format_bytes(512.0));
+        assert_eq!("1.5 MiB", format_bytes(1.5 * 1024.0 * 1024.0));
+    }
+}
@@ -0,0 +1,48 @@
+//! The management interface options shared by the command line tools
+use clap::Args;
+use openvpn_management::CommandManagerBuilder;
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Args)]
+pub struct ConnectionArgs {
+    /// Address of the management interface
+    #[arg(long, short, env = "OVPN_MGMT_URL", default_value = "localhost:5555")]
+    url: String,
+    /// Path of the management unix socket, used in place of the address
+    #[arg(long, env = "OVPN_MGMT_SOCKET")]
+    unix_socket: Option<PathBuf>,
+    /// Password of the management interface
+    #[arg(long, env = "OVPN_MGMT_PASSWORD", hide_env_values = true)]
+    password: Option<String>,
+    /// File holding the password of the management interface on its first line
+    #[arg(long, conflicts_with = "password")]
+    password_file: Option<PathBuf>,
+}
+
+impl ConnectionArgs {
+    /// The builder of a manager connecting with these options and timeouts
+    pub fn builder(
+        &self,
+        connect_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+    ) -> openvpn_management::Result<CommandManagerBuilder> {
+        let mut builder = CommandManagerBuilder::new();
+        builder
+            .management_url(&self.url)
+            .connect_timeout(connect_timeout)
+            .read_timeout(read_timeout);
+        if let Some(ref path) = self.unix_socket {
+            builder.unix_socket(path);
+        }
+        if let Some(ref password) = self.password {
+            builder.password(password);
+        }
+        if let Some(ref path) = self.password_file {
+            let contents = fs::read_to_string(path)?;
+            builder.password(contents.lines().next().unwrap_or_default());
+        }
+        Ok(builder)
+    }
+}
@@ -0,0 +1,130 @@
+//! Raw mode and key decoding shared by the interactive command line tools
+use std::io;
+
+/// A key read from a terminal in raw mode
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Delete,
+    Tab,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    /// Ctrl-U, clears the line
+    Clear,
+    /// Ctrl-C, the terminal does not send SIGINT in raw mode
+    Interrupt,
+    /// Ctrl-D
+    Eof,
+}
+
+/// Decodes the input read from a terminal in raw mode, skipping the escape sequences of the
+/// other keys
+pub fn parse_keys(input: &str) -> Vec<Key> {
+    let mut keys = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        let key = match c {
+            '\x1b' if chars.peek() == Some(&'[') || chars.peek() == Some(&'O') => {
+                chars.next();
+                let mut parameters = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_digit() || *c == ';') {
+                    parameters.push(c);
+                }
+                match (parameters.as_str(), chars.next()) {
+                    ("", Some('A')) => Key::Up,
+                    ("", Some('B')) => Key::Down,
+                    ("", Some('C')) => Key::Right,
+                    ("", Some('D')) => Key::Left,
+                    ("", Some('H')) => Key::Home,
+                    ("", Some('F')) => Key::End,
+                    ("3", Some('~')) => Key::Delete,
+                    _ => continue,
+                }
+            }
+            '\r' | '\n' => Key::Enter,
+            '\x7f' | '\x08' => Key::Backspace,
+            '\t' => Key::Tab,
+            '\x01' => Key::Home,
+            '\x05' => Key::End,
+            '\x15' => Key::Clear,
+            '\x03' => Key::Interrupt,
+            '\x04' => Key::Eof,
+            c if c.is_control() => continue,
+            c => Key::Char(c),
+        };
+        keys.push(key);
+    }
+    keys
+}
+
+/// Puts the terminal in raw mode, and restores it when dropped
+pub struct RawMode {
+    #[cfg(unix)]
+    original: libc::termios,
+}
+
+impl RawMode {
+    #[cfg(unix)]
+    pub fn enter() -> io::Result<RawMode> {
+        // SAFETY: tcgetattr and tcsetattr only write to the termios structs passed to them
+        unsafe {
+            let mut original = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut original) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            let mut raw = original;
+            libc::cfmakeraw(&mut raw);
+            // keeps translating the newlines of the output
+            raw.c_oflag |= libc::OPOST;
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(RawMode { original })
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub fn enter() -> io::Result<RawMode> {
+        Ok(RawMode {})
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        // SAFETY: restores the attributes read in enter
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_keys() {
+        assert_eq!(
+            vec![Key::Up, Key::Char('x'), Key::Down, Key::Interrupt],
+            parse_keys("\x1b[Ax\x1b[B\x1b[5~\x03")
+        );
+        assert_eq!(
+            vec![
+                Key::Char('s'),
+                Key::Left,
+                Key::Delete,
+                Key::Tab,
+                Key::Backspace,
+                Key::Enter
+            ],
+            parse_keys("s\x1b[D\x1b[3~\t\x7f\r")
+        );
+    }
+}
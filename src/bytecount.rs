@@ -0,0 +1,80 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The traffic counters sent every few seconds after `bytecount n`, in a `>BYTECOUNT_CLI`
+/// notification per client on a server or in a `>BYTECOUNT` notification on a client
+pub struct ByteCount {
+    cid: Option<u64>,
+    bytes_received: f64,
+    bytes_sent: f64,
+}
+
+impl ByteCount {
+    pub fn new(cid: Option<u64>, bytes_received: f64, bytes_sent: f64) -> ByteCount {
+        ByteCount {
+            cid,
+            bytes_received,
+            bytes_sent,
+        }
+    }
+
+    /// Parses the payload of a client `>BYTECOUNT` notification, e.g. `1024,2048`
+    pub fn parse(payload: &str) -> Result<ByteCount> {
+        match payload.split(',').collect::<Vec<_>>()[..] {
+            [received, sent] => Ok(ByteCount::new(None, received.parse()?, sent.parse()?)),
+            _ => Err(OpenvpnError::MalformedResponse(payload.to_string())),
+        }
+    }
+
+    /// Parses the payload of a server `>BYTECOUNT_CLI` notification, e.g. `4,1024,2048`
+    pub fn parse_client(payload: &str) -> Result<ByteCount> {
+        match payload.split(',').collect::<Vec<_>>()[..] {
+            [cid, received, sent] => Ok(ByteCount::new(
+                Some(cid.parse()?),
+                received.parse()?,
+                sent.parse()?,
+            )),
+            _ => Err(OpenvpnError::MalformedResponse(payload.to_string())),
+        }
+    }
+
+    /// Client ID, only sent by a server
+    pub fn cid(&self) -> Option<u64> {
+        self.cid
+    }
+
+    /// Bytes received since the connection started, from the client on a server
+    pub fn bytes_received(&self) -> f64 {
+        self.bytes_received
+    }
+
+    /// Bytes sent since the connection started, to the client on a server
+    pub fn bytes_sent(&self) -> f64 {
+        self.bytes_sent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_byte_counts() {
+        assert_eq!(
+            ByteCount::new(None, 1024.0, 2048.0),
+            ByteCount::parse("1024,2048").unwrap()
+        );
+        assert_eq!(
+            ByteCount::new(Some(4), 1024.0, 2048.0),
+            ByteCount::parse_client("4,1024,2048").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed_byte_counts() {
+        assert!(ByteCount::parse("1024").is_err());
+        assert!(ByteCount::parse_client("1024,2048").is_err());
+        assert!(ByteCount::parse_client("four,1024,2048").is_err());
+    }
+}
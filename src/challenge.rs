@@ -0,0 +1,194 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+const STATIC_CHALLENGE: &str = "SC:";
+const DYNAMIC_CHALLENGE: &str = "CRV1:";
+const ECHO_FLAG: u32 = 1;
+const CONCATENATE_FLAG: u32 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A static challenge (`SC:<flags>,<text>`) configured on the client with `static-challenge`.
+/// The response is sent along with the password
+pub struct StaticChallenge {
+    echo: bool,
+    concatenate: bool,
+    text: String,
+}
+
+impl StaticChallenge {
+    pub fn new(echo: bool, concatenate: bool, text: String) -> StaticChallenge {
+        StaticChallenge {
+            echo,
+            concatenate,
+            text,
+        }
+    }
+
+    /// Parses the `SC:<flags>,<text>` suffix of a `>PASSWORD:Need` notification
+    pub fn parse(raw_challenge: &str) -> Result<StaticChallenge> {
+        let malformed = || OpenvpnError::MalformedResponse(raw_challenge.to_string());
+        let (flags, text) = raw_challenge
+            .strip_prefix(STATIC_CHALLENGE)
+            .and_then(|c| c.split_once(','))
+            .ok_or_else(malformed)?;
+        let flags: u32 = flags.parse()?;
+        Ok(StaticChallenge::new(
+            flags & ECHO_FLAG != 0,
+            flags & CONCATENATE_FLAG != 0,
+            text.to_string(),
+        ))
+    }
+
+    /// Whether the response may be displayed while typed
+    pub fn echo(&self) -> bool {
+        self.echo
+    }
+
+    /// Whether the response is appended to the password as is, instead of the `SCRV1` format
+    pub fn concatenate(&self) -> bool {
+        self.concatenate
+    }
+
+    /// The challenge text to show the user
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Builds the password to send: `SCRV1:<base64 password>:<base64 response>`, or the
+    /// password followed by the response when [`concatenate`](#method.concatenate) is set
+    pub fn response(&self, password: &str, response: &str) -> String {
+        if self.concatenate {
+            return format!("{}{}", password, response);
+        }
+        format!(
+            "SCRV1:{}:{}",
+            STANDARD.encode(password),
+            STANDARD.encode(response)
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A dynamic challenge (`CRV1:<flags>:<state id>:<base64 username>:<text>`) sent by the server
+/// in place of `AUTH_FAILED`. The client must reconnect and answer it as its credentials
+pub struct DynamicChallenge {
+    echo: bool,
+    response_required: bool,
+    state_id: String,
+    username: String,
+    text: String,
+}
+
+impl DynamicChallenge {
+    /// Parses a `CRV1:...` challenge
+    pub fn parse(raw_challenge: &str) -> Result<DynamicChallenge> {
+        let malformed = || OpenvpnError::MalformedResponse(raw_challenge.to_string());
+        let fields: Vec<_> = raw_challenge
+            .strip_prefix(DYNAMIC_CHALLENGE)
+            .ok_or_else(malformed)?
+            .splitn(4, ':')
+            .collect();
+        if fields.len() != 4 {
+            return Err(malformed());
+        }
+        let flags: Vec<_> = fields[0].split(',').collect();
+        let username = STANDARD
+            .decode(fields[2])
+            .ok()
+            .and_then(|u| String::from_utf8(u).ok())
+            .ok_or_else(malformed)?;
+        Ok(DynamicChallenge {
+            echo: flags.contains(&"E"),
+            response_required: flags.contains(&"R"),
+            state_id: fields[1].to_string(),
+            username,
+            text: fields[3].to_string(),
+        })
+    }
+
+    /// Whether the response may be displayed while typed
+    pub fn echo(&self) -> bool {
+        self.echo
+    }
+
+    /// Whether a response is expected, as opposed to an acknowledgement
+    pub fn response_required(&self) -> bool {
+        self.response_required
+    }
+
+    /// Opaque identifier of the authentication in progress on the server
+    pub fn state_id(&self) -> &str {
+        &self.state_id
+    }
+
+    /// The username to send back with the response
+    pub fn username(&self) -> &str {
+        &self.username
+    }
+
+    /// The challenge text to show the user
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Builds the password to send: `CRV1::<state id>::<response>`
+    pub fn response(&self, response: &str) -> String {
+        format!("CRV1::{}::{}", self.state_id, response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_static_challenge_response() {
+        let challenge = StaticChallenge::parse("SC:1,Please enter token PIN").unwrap();
+
+        assert!(challenge.echo());
+        assert!(!challenge.concatenate());
+        assert_eq!("Please enter token PIN", challenge.text());
+        assert_eq!(
+            "SCRV1:aHVudGVyMg==:MTIzNDU2",
+            challenge.response("hunter2", "123456")
+        );
+    }
+
+    #[test]
+    fn test_static_challenge_concatenated() {
+        let challenge = StaticChallenge::parse("SC:2,OTP, please").unwrap();
+
+        assert!(!challenge.echo());
+        assert_eq!("OTP, please", challenge.text());
+        assert_eq!("hunter2123456", challenge.response("hunter2", "123456"));
+    }
+
+    #[test]
+    fn test_dynamic_challenge_response() {
+        let challenge = DynamicChallenge::parse(
+            "CRV1:R,E:Om01u7Fh4LrGBS7uh0SWmzwabUiGiW6l:Y3Ix:Enter PIN: now",
+        )
+        .unwrap();
+
+        assert!(challenge.echo());
+        assert!(challenge.response_required());
+        assert_eq!("Om01u7Fh4LrGBS7uh0SWmzwabUiGiW6l", challenge.state_id());
+        assert_eq!("cr1", challenge.username());
+        assert_eq!("Enter PIN: now", challenge.text());
+        assert_eq!(
+            "CRV1::Om01u7Fh4LrGBS7uh0SWmzwabUiGiW6l::8675309",
+            challenge.response("8675309")
+        );
+    }
+
+    #[test]
+    fn test_malformed_challenges() {
+        assert!(StaticChallenge::parse("SC:yes,text").is_err());
+        assert!(StaticChallenge::parse("SC:1").is_err());
+        assert!(DynamicChallenge::parse("CRV1:R:state:not base64!:text").is_err());
+        assert!(DynamicChallenge::parse("CRV1:R:state").is_err());
+    }
+}
@@ -1,6 +1,8 @@
 use chrono::prelude::{DateTime, Utc};
+use std::net::IpAddr;
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Contains useful information on a client which is connected to the openvpn server
 pub struct Client {
     name: String,
@@ -8,6 +10,8 @@ pub struct Client {
     connected_since: DateTime<Utc>,
     bytes_received: f64,
     bytes_sent: f64,
+    cid: Option<u64>,
+    virtual_address: Option<IpAddr>,
 }
 
 impl Client {
@@ -24,9 +28,23 @@ impl Client {
             connected_since,
             bytes_received,
             bytes_sent,
+            cid: None,
+            virtual_address: None,
         }
     }
 
+    /// Adds the client ID, listed in the status of openvpn 2.4 and later
+    pub fn with_cid(mut self, cid: u64) -> Client {
+        self.cid = Some(cid);
+        self
+    }
+
+    /// Adds the virtual address assigned to the client
+    pub fn with_virtual_address(mut self, virtual_address: IpAddr) -> Client {
+        self.virtual_address = Some(virtual_address);
+        self
+    }
+
     /// Common Name
     pub fn name(&self) -> &str {
         &self.name
@@ -51,4 +69,14 @@ impl Client {
     pub fn bytes_sent(&self) -> f64 {
         self.bytes_sent
     }
+
+    /// Client ID, as used by `client-kill` and the client notifications
+    pub fn cid(&self) -> Option<u64> {
+        self.cid
+    }
+
+    /// Virtual address, missing for the clients of a `dev tap` server listed by MAC address
+    pub fn virtual_address(&self) -> Option<IpAddr> {
+        self.virtual_address
+    }
 }
@@ -0,0 +1,461 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::get_utc_start_time;
+use chrono::prelude::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::time::Duration;
+
+const ENV_PREFIX: &str = ">CLIENT:ENV,";
+const ENV_END: &str = "END";
+const PEER_INFO_PREFIX: &str = "IV_";
+const TLS_PREFIX: &str = "tls_";
+const REDACTED: &str = "<redacted>";
+
+#[derive(Clone, Debug, Default, PartialEq)]
+/// An entry of the client certificate chain, as reported in the `tls_*_N` variables
+pub struct CertificateEntry {
+    depth: u32,
+    id: Option<String>,
+    serial: Option<String>,
+    serial_hex: Option<String>,
+    digest: Option<String>,
+    digest_sha256: Option<String>,
+}
+
+impl CertificateEntry {
+    fn new(depth: u32) -> CertificateEntry {
+        CertificateEntry {
+            depth,
+            ..Default::default()
+        }
+    }
+
+    /// Position in the chain, 0 being the client certificate itself
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    /// Subject of the certificate (`tls_id_N`)
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    /// Decimal serial number (`tls_serial_N`)
+    pub fn serial(&self) -> Option<&str> {
+        self.serial.as_deref()
+    }
+
+    /// Hexadecimal serial number (`tls_serial_hex_N`)
+    pub fn serial_hex(&self) -> Option<&str> {
+        self.serial_hex.as_deref()
+    }
+
+    /// SHA1 fingerprint (`tls_digest_N`)
+    pub fn digest(&self) -> Option<&str> {
+        self.digest.as_deref()
+    }
+
+    /// SHA256 fingerprint (`tls_digest_sha256_N`)
+    pub fn digest_sha256(&self) -> Option<&str> {
+        self.digest_sha256.as_deref()
+    }
+}
+
+#[derive(Clone, PartialEq)]
+/// The environment sent by the openvpn server in a `>CLIENT:ENV` block. Well known variables are
+/// available through typed accessors and every variable, known or not, is kept in the raw map
+pub struct ClientEnv {
+    vars: BTreeMap<String, String>,
+    untrusted_ip: Option<IpAddr>,
+    untrusted_port: Option<u16>,
+    trusted_ip: Option<IpAddr>,
+    trusted_port: Option<u16>,
+    ifconfig_pool_remote_ip: Option<IpAddr>,
+    ifconfig_pool_remote_ip6: Option<IpAddr>,
+    connected_since: Option<DateTime<Utc>>,
+    time_duration: Option<Duration>,
+    bytes_received: Option<f64>,
+    bytes_sent: Option<f64>,
+    certificates: Vec<CertificateEntry>,
+}
+
+impl ClientEnv {
+    /// Parses the lines of a `>CLIENT:ENV` block. Parsing stops at the `>CLIENT:ENV,END` line,
+    /// which may be omitted
+    pub fn parse<'a, I>(lines: I) -> Result<ClientEnv>
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut vars = BTreeMap::new();
+        for line in lines {
+            let line = line.trim_end_matches(['\r', '\n']);
+            let entry = match line.strip_prefix(ENV_PREFIX) {
+                Some(e) => e,
+                None => return Err(OpenvpnError::MalformedResponse(line.to_string())),
+            };
+            if entry == ENV_END {
+                break;
+            }
+            let (name, value) = split_env_entry(entry)
+                .ok_or_else(|| OpenvpnError::MalformedResponse(line.to_string()))?;
+            vars.insert(name.to_string(), value.to_string());
+        }
+        ClientEnv::from_vars(vars)
+    }
+
+    /// Builds the environment from already split `name=value` pairs
+    pub fn from_vars(vars: BTreeMap<String, String>) -> Result<ClientEnv> {
+        let untrusted_ip = match vars.get("untrusted_ip") {
+            Some(ip) => Some(ip.parse()?),
+            None => parse_optional(&vars, "untrusted_ip6")?,
+        };
+        let trusted_ip = match vars.get("trusted_ip") {
+            Some(ip) => Some(ip.parse()?),
+            None => parse_optional(&vars, "trusted_ip6")?,
+        };
+        let untrusted_port = parse_optional(&vars, "untrusted_port")?;
+        let trusted_port = parse_optional(&vars, "trusted_port")?;
+        let ifconfig_pool_remote_ip = parse_optional(&vars, "ifconfig_pool_remote_ip")?;
+        let ifconfig_pool_remote_ip6 = parse_optional(&vars, "ifconfig_pool_remote_ip6")?;
+        let connected_since = parse_optional(&vars, "time_unix")?
+            .map(get_utc_start_time)
+            .transpose()?;
+        let time_duration = parse_optional(&vars, "time_duration")?.map(Duration::from_secs);
+        let bytes_received = parse_optional(&vars, "bytes_received")?;
+        let bytes_sent = parse_optional(&vars, "bytes_sent")?;
+        let certificates = parse_certificates(&vars)?;
+        Ok(ClientEnv {
+            vars,
+            untrusted_ip,
+            untrusted_port,
+            trusted_ip,
+            trusted_port,
+            ifconfig_pool_remote_ip,
+            ifconfig_pool_remote_ip6,
+            connected_since,
+            time_duration,
+            bytes_received,
+            bytes_sent,
+            certificates,
+        })
+    }
+
+    /// Looks up any variable by name
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(String::as_str)
+    }
+
+    /// All the variables sent by the server
+    pub fn vars(&self) -> &BTreeMap<String, String> {
+        &self.vars
+    }
+
+    /// Common Name of the client certificate
+    pub fn common_name(&self) -> Option<&str> {
+        self.get("common_name")
+    }
+
+    /// Username supplied by the client with `auth-user-pass`
+    pub fn username(&self) -> Option<&str> {
+        self.get("username")
+    }
+
+    /// Password supplied by the client with `auth-user-pass`
+    pub fn password(&self) -> Option<&str> {
+        self.get("password")
+    }
+
+    /// Remote address of the client before authentication, from `untrusted_ip` or `untrusted_ip6`
+    pub fn untrusted_ip(&self) -> Option<IpAddr> {
+        self.untrusted_ip
+    }
+
+    /// Remote port of the client before authentication
+    pub fn untrusted_port(&self) -> Option<u16> {
+        self.untrusted_port
+    }
+
+    /// Remote address of the client once authenticated, from `trusted_ip` or `trusted_ip6`
+    pub fn trusted_ip(&self) -> Option<IpAddr> {
+        self.trusted_ip
+    }
+
+    /// Remote port of the client once authenticated
+    pub fn trusted_port(&self) -> Option<u16> {
+        self.trusted_port
+    }
+
+    /// Virtual IPv4 address assigned to the client
+    pub fn ifconfig_pool_remote_ip(&self) -> Option<IpAddr> {
+        self.ifconfig_pool_remote_ip
+    }
+
+    /// Virtual IPv6 address assigned to the client
+    pub fn ifconfig_pool_remote_ip6(&self) -> Option<IpAddr> {
+        self.ifconfig_pool_remote_ip6
+    }
+
+    /// Date time the client connected at (`time_unix`)
+    pub fn connected_since(&self) -> Option<&DateTime<Utc>> {
+        self.connected_since.as_ref()
+    }
+
+    /// How long the client was connected for, only sent on disconnect (`time_duration`)
+    pub fn time_duration(&self) -> Option<Duration> {
+        self.time_duration
+    }
+
+    /// Bytes received from the client, only sent on disconnect
+    pub fn bytes_received(&self) -> Option<f64> {
+        self.bytes_received
+    }
+
+    /// Bytes sent to the client, only sent on disconnect
+    pub fn bytes_sent(&self) -> Option<f64> {
+        self.bytes_sent
+    }
+
+    /// The certificate chain, ordered by depth
+    pub fn certificates(&self) -> &[CertificateEntry] {
+        &self.certificates
+    }
+
+    /// The certificate at the given depth, 0 being the client certificate
+    pub fn certificate(&self, depth: u32) -> Option<&CertificateEntry> {
+        self.certificates.iter().find(|c| c.depth == depth)
+    }
+
+    /// The `IV_*` peer information pushed by the client (e.g. `IV_VER`, `IV_PLAT`)
+    pub fn peer_info(&self) -> BTreeMap<&str, &str> {
+        self.vars
+            .iter()
+            .filter(|(name, _)| name.starts_with(PEER_INFO_PREFIX))
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+            .collect()
+    }
+}
+
+impl fmt::Debug for ClientEnv {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let vars: BTreeMap<&str, &str> = self
+            .vars
+            .iter()
+            .map(|(name, value)| match name.as_str() {
+                "password" => (name.as_str(), REDACTED),
+                _ => (name.as_str(), value.as_str()),
+            })
+            .collect();
+        f.debug_struct("ClientEnv")
+            .field("vars", &vars)
+            .field("untrusted_ip", &self.untrusted_ip)
+            .field("untrusted_port", &self.untrusted_port)
+            .field("trusted_ip", &self.trusted_ip)
+            .field("trusted_port", &self.trusted_port)
+            .field("ifconfig_pool_remote_ip", &self.ifconfig_pool_remote_ip)
+            .field("ifconfig_pool_remote_ip6", &self.ifconfig_pool_remote_ip6)
+            .field("connected_since", &self.connected_since)
+            .field("time_duration", &self.time_duration)
+            .field("bytes_received", &self.bytes_received)
+            .field("bytes_sent", &self.bytes_sent)
+            .field("certificates", &self.certificates)
+            .finish()
+    }
+}
+
+fn split_env_entry(entry: &str) -> Option<(&str, &str)> {
+    let (name, value) = entry.split_once('=')?;
+    if name.is_empty() {
+        return None;
+    }
+    Some((name, value))
+}
+
+fn parse_optional<T>(vars: &BTreeMap<String, String>, name: &str) -> Result<Option<T>>
+where
+    T: std::str::FromStr,
+    OpenvpnError: From<T::Err>,
+{
+    match vars.get(name) {
+        Some(value) => Ok(Some(value.parse()?)),
+        None => Ok(None),
+    }
+}
+
+fn parse_certificates(vars: &BTreeMap<String, String>) -> Result<Vec<CertificateEntry>> {
+    let mut entries: BTreeMap<u32, CertificateEntry> = BTreeMap::new();
+    for (name, value) in vars {
+        let field = match name.strip_prefix(TLS_PREFIX) {
+            Some(f) => f,
+            None => continue,
+        };
+        let (field, depth) = match field.rfind('_') {
+            Some(i) => (&field[..i], &field[i + 1..]),
+            None => continue,
+        };
+        if depth.is_empty() || !depth.chars().all(|c| c.is_ascii_digit()) {
+            continue;
+        }
+        let depth: u32 = depth.parse()?;
+        let entry = entries
+            .entry(depth)
+            .or_insert_with(|| CertificateEntry::new(depth));
+        let value = Some(value.to_owned());
+        match field {
+            "id" => entry.id = value,
+            "serial" => entry.serial = value,
+            "serial_hex" => entry.serial_hex = value,
+            "digest" => entry.digest = value,
+            "digest_sha256" => entry.digest_sha256 = value,
+            _ => {}
+        }
+    }
+    Ok(entries.into_values().collect())
+}
+
+#[cfg(feature = "serde")]
+/// Serialized as the map of raw variables with the password redacted, the typed fields being
+/// derived from it again on deserialization
+impl serde::Serialize for ClientEnv {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_map(self.vars.iter().map(|(name, value)| match name.as_str() {
+            "password" => (name.as_str(), REDACTED),
+            _ => (name.as_str(), value.as_str()),
+        }))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for ClientEnv {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<ClientEnv, D::Error> {
+        let vars = BTreeMap::deserialize(deserializer)?;
+        ClientEnv::from_vars(vars).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENV_BLOCK: &[&str] = &[
+        ">CLIENT:ENV,common_name=test-client",
+        ">CLIENT:ENV,username=alice",
+        ">CLIENT:ENV,password=hunter2",
+        ">CLIENT:ENV,untrusted_ip=192.168.0.3",
+        ">CLIENT:ENV,untrusted_port=41954",
+        ">CLIENT:ENV,IV_VER=2.6.8",
+        ">CLIENT:ENV,IV_PLAT=linux",
+        ">CLIENT:ENV,tls_id_0=CN=test-client",
+        ">CLIENT:ENV,tls_serial_0=2",
+        ">CLIENT:ENV,tls_digest_0=aa:bb:cc",
+        ">CLIENT:ENV,tls_id_1=CN=test-ca",
+        ">CLIENT:ENV,tls_serial_hex_1=01",
+        ">CLIENT:ENV,X509_0_CN=test-client",
+        ">CLIENT:ENV,END",
+    ];
+
+    #[test]
+    fn test_typed_accessors() {
+        let env = ClientEnv::parse(ENV_BLOCK.iter().cloned()).unwrap();
+
+        assert_eq!(Some("test-client"), env.common_name());
+        assert_eq!(Some("alice"), env.username());
+        assert_eq!(Some("hunter2"), env.password());
+        assert_eq!(Some("192.168.0.3".parse().unwrap()), env.untrusted_ip());
+        assert_eq!(Some(41954), env.untrusted_port());
+        assert_eq!(None, env.trusted_ip());
+        assert_eq!(Some("test-client"), env.get("X509_0_CN"));
+    }
+
+    #[test]
+    fn test_certificate_chain_by_depth() {
+        let env = ClientEnv::parse(ENV_BLOCK.iter().cloned()).unwrap();
+
+        assert_eq!(2, env.certificates().len());
+        let client = env.certificate(0).unwrap();
+        assert_eq!(Some("CN=test-client"), client.id());
+        assert_eq!(Some("2"), client.serial());
+        assert_eq!(Some("aa:bb:cc"), client.digest());
+        let ca = env.certificate(1).unwrap();
+        assert_eq!(Some("CN=test-ca"), ca.id());
+        assert_eq!(Some("01"), ca.serial_hex());
+        assert!(env.certificate(2).is_none());
+    }
+
+    #[test]
+    fn test_peer_info() {
+        let env = ClientEnv::parse(ENV_BLOCK.iter().cloned()).unwrap();
+        let peer_info = env.peer_info();
+
+        assert_eq!(2, peer_info.len());
+        assert_eq!(Some(&"2.6.8"), peer_info.get("IV_VER"));
+        assert_eq!(Some(&"linux"), peer_info.get("IV_PLAT"));
+    }
+
+    #[test]
+    fn test_password_redacted_in_debug() {
+        let env = ClientEnv::parse(ENV_BLOCK.iter().cloned()).unwrap();
+        let debug = format!("{:?}", env);
+
+        assert!(!debug.contains("hunter2"));
+        assert!(debug.contains(REDACTED));
+    }
+
+    #[test]
+    fn test_disconnect_accounting() {
+        let env = ClientEnv::parse(vec![
+            ">CLIENT:ENV,time_unix=1546277714",
+            ">CLIENT:ENV,time_duration=60",
+            ">CLIENT:ENV,bytes_received=100",
+            ">CLIENT:ENV,bytes_sent=200",
+            ">CLIENT:ENV,END",
+        ])
+        .unwrap();
+
+        assert_eq!(1_546_277_714, env.connected_since().unwrap().timestamp());
+        assert_eq!(Some(Duration::from_secs(60)), env.time_duration());
+        assert_eq!(Some(100.0), env.bytes_received());
+        assert_eq!(Some(200.0), env.bytes_sent());
+    }
+
+    #[test]
+    fn test_value_containing_equals() {
+        let env = ClientEnv::parse(vec![">CLIENT:ENV,X509_0_emailAddress=a=b"]).unwrap();
+
+        assert_eq!(Some("a=b"), env.get("X509_0_emailAddress"));
+    }
+
+    #[test]
+    fn test_malformed_env_line() {
+        let result = ClientEnv::parse(vec![
+            ">CLIENT:ENV,common_name=test",
+            ">CLIENT:ESTABLISHED,0",
+        ]);
+
+        match result {
+            Err(OpenvpnError::MalformedResponse(line)) => {
+                assert_eq!(">CLIENT:ESTABLISHED,0", line)
+            }
+            _ => panic!("was expecting malformed response"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_ip_address() {
+        let result = ClientEnv::parse(vec![">CLIENT:ENV,untrusted_ip=not-an-ip"]);
+
+        assert!(matches!(result, Err(OpenvpnError::ParseAddr(_))));
+    }
+
+    #[test]
+    fn test_out_of_range_time() {
+        let result = ClientEnv::parse(vec![">CLIENT:ENV,time_unix=99999999999999"]);
+
+        assert!(matches!(result, Err(OpenvpnError::MalformedResponse(_))));
+    }
+}
@@ -0,0 +1,49 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+
+/// Quotes an argument for the management interface, which splits commands on whitespace and
+/// unescapes `\\` and `\"` within double quotes
+pub(crate) fn quote(argument: &str) -> Result<String> {
+    check_single_line(argument)?;
+    let mut quoted = String::with_capacity(argument.len() + 2);
+    quoted.push('"');
+    for c in argument.chars() {
+        if c == '"' || c == '\\' {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    Ok(quoted)
+}
+
+/// Rejects text which would be read as several lines by the management interface
+pub(crate) fn check_single_line(text: &str) -> Result<()> {
+    if text.contains(['\r', '\n']) {
+        return Err(OpenvpnError::InvalidArgument(text.to_string()));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quote_escapes_quotes_and_backslashes() {
+        assert_eq!(r#""plain""#, quote("plain").unwrap());
+        assert_eq!(r#""with space""#, quote("with space").unwrap());
+        assert_eq!(r#""a\"b\\c""#, quote(r#"a"b\c"#).unwrap());
+    }
+
+    #[test]
+    fn test_quote_rejects_newlines() {
+        let result = quote("line\nclient-kill 1");
+
+        match result {
+            Err(OpenvpnError::InvalidArgument(argument)) => {
+                assert_eq!("line\nclient-kill 1", argument)
+            }
+            _ => panic!("was expecting invalid argument"),
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use crate::challenge::{DynamicChallenge, StaticChallenge};
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use std::fmt;
+
+const NEED: &str = "Need ";
+const VERIFICATION_FAILED: &str = "Verification Failed: ";
+const AUTH_TOKEN: &str = "Auth-Token:";
+const USERNAME_PASSWORD: &str = "username/password";
+const REDACTED: &str = "<redacted>";
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A prompt for credentials sent to a client in a `>PASSWORD:Need` notification
+pub struct PasswordRequest {
+    realm: String,
+    needs_username: bool,
+    static_challenge: Option<StaticChallenge>,
+}
+
+impl PasswordRequest {
+    pub fn new(realm: String, needs_username: bool) -> PasswordRequest {
+        PasswordRequest {
+            realm,
+            needs_username,
+            static_challenge: None,
+        }
+    }
+
+    /// Adds the static challenge the client asks to answer along with the password
+    pub fn with_static_challenge(mut self, challenge: StaticChallenge) -> PasswordRequest {
+        self.static_challenge = Some(challenge);
+        self
+    }
+
+    /// What the credentials are for, e.g. `Auth`, `Private Key` or `HTTP Proxy`
+    pub fn realm(&self) -> &str {
+        &self.realm
+    }
+
+    /// Whether a username is asked for along with the password
+    pub fn needs_username(&self) -> bool {
+        self.needs_username
+    }
+
+    /// The `SC:` challenge sent with the prompt, if the client uses `static-challenge`
+    pub fn static_challenge(&self) -> Option<&StaticChallenge> {
+        self.static_challenge.as_ref()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
+/// Contains the marshalled `>PASSWORD` notification
+pub enum PasswordNotification {
+    /// `>PASSWORD:Need '<realm>' username/password` or `>PASSWORD:Need '<realm>' password`
+    Need(PasswordRequest),
+    /// `>PASSWORD:Verification Failed: '<realm>'`, the server rejected the credentials, possibly
+    /// with a `['CRV1:...']` dynamic challenge to answer on the next prompt
+    VerificationFailed {
+        realm: String,
+        challenge: Option<DynamicChallenge>,
+    },
+    /// `>PASSWORD:Auth-Token:<token>`, a token pushed by the server to use in place of the
+    /// password on renegotiation
+    AuthToken(String),
+}
+
+impl PasswordNotification {
+    /// Parses the payload of a `>PASSWORD` notification
+    pub fn parse(payload: &str) -> Result<PasswordNotification> {
+        let malformed = || OpenvpnError::MalformedResponse(payload.to_string());
+        if let Some(token) = payload.strip_prefix(AUTH_TOKEN) {
+            return Ok(PasswordNotification::AuthToken(token.to_string()));
+        }
+        if let Some(rest) = payload.strip_prefix(VERIFICATION_FAILED) {
+            let (realm, rest) = split_realm(rest).ok_or_else(malformed)?;
+            let challenge = match rest {
+                "" => None,
+                _ => {
+                    let challenge = rest
+                        .strip_prefix("['")
+                        .and_then(|c| c.strip_suffix("']"))
+                        .ok_or_else(malformed)?;
+                    Some(DynamicChallenge::parse(challenge)?)
+                }
+            };
+            return Ok(PasswordNotification::VerificationFailed {
+                realm: realm.to_string(),
+                challenge,
+            });
+        }
+        let rest = payload.strip_prefix(NEED).ok_or_else(malformed)?;
+        let (realm, rest) = split_realm(rest).ok_or_else(malformed)?;
+        let (what, challenge) = match rest.split_once(' ') {
+            Some((what, challenge)) => (what, Some(challenge.trim_start())),
+            None => (rest, None),
+        };
+        let needs_username = match what {
+            USERNAME_PASSWORD => true,
+            "password" => false,
+            _ => return Err(malformed()),
+        };
+        let request = PasswordRequest::new(realm.to_string(), needs_username);
+        match challenge {
+            Some(challenge) => Ok(PasswordNotification::Need(
+                request.with_static_challenge(StaticChallenge::parse(challenge)?),
+            )),
+            None => Ok(PasswordNotification::Need(request)),
+        }
+    }
+}
+
+/// Splits `'<realm>' rest` into the realm and the rest
+pub(crate) fn split_realm(text: &str) -> Option<(&str, &str)> {
+    let text = text.strip_prefix('\'')?;
+    let (realm, rest) = text.split_once('\'')?;
+    Some((realm, rest.trim_start()))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// What openvpn does after an authentication failure, set with the `auth-retry` command
+pub enum AuthRetry {
+    /// `none`, the client exits
+    None,
+    /// `nointeract`, the client retries with the credentials it already has
+    NoInteract,
+    /// `interact`, the client asks for the credentials again
+    Interact,
+}
+
+impl fmt::Display for AuthRetry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AuthRetry::None => f.write_str("none"),
+            AuthRetry::NoInteract => f.write_str("nointeract"),
+            AuthRetry::Interact => f.write_str("interact"),
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+/// The answer to a [`PasswordRequest`]
+pub struct Credentials {
+    username: Option<String>,
+    password: String,
+}
+
+impl Credentials {
+    pub fn new(username: Option<String>, password: String) -> Credentials {
+        Credentials { username, password }
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    pub fn password(&self) -> &str {
+        &self.password
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("username", &self.username)
+            .field("password", &REDACTED)
+            .finish()
+    }
+}
+
+/// Supplies the credentials an openvpn client asks for over its management interface
+pub trait CredentialProvider {
+    /// Returns the credentials for the request. A username must be returned when
+    /// [`PasswordRequest::needs_username`] is true
+    fn credentials(&mut self, request: &PasswordRequest) -> Result<Credentials>;
+
+    /// Returns the response to the static challenge of a request. Fails by default
+    fn static_challenge(
+        &mut self,
+        _request: &PasswordRequest,
+        challenge: &StaticChallenge,
+    ) -> Result<String> {
+        Err(OpenvpnError::ChallengeUnanswered(
+            challenge.text().to_string(),
+        ))
+    }
+
+    /// Returns the response to a dynamic challenge sent by the server. Fails by default
+    fn dynamic_challenge(&mut self, challenge: &DynamicChallenge) -> Result<String> {
+        Err(OpenvpnError::ChallengeUnanswered(
+            challenge.text().to_string(),
+        ))
+    }
+
+    /// Called when the server pushes an auth token. Does nothing by default
+    fn auth_token(&mut self, _token: &str) {}
+
+    /// Called when the server rejects the credentials of a realm, e.g. to drop credentials
+    /// which should not be offered again. Does nothing by default
+    fn verification_failed(&mut self, _realm: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_need_username_password() {
+        let notification = PasswordNotification::parse("Need 'Auth' username/password").unwrap();
+
+        assert_eq!(
+            PasswordNotification::Need(PasswordRequest::new("Auth".to_string(), true)),
+            notification
+        );
+    }
+
+    #[test]
+    fn test_parse_need_private_key_password() {
+        let notification = PasswordNotification::parse("Need 'Private Key' password").unwrap();
+
+        assert_eq!(
+            PasswordNotification::Need(PasswordRequest::new("Private Key".to_string(), false)),
+            notification
+        );
+    }
+
+    #[test]
+    fn test_parse_verification_failed_and_auth_token() {
+        assert_eq!(
+            PasswordNotification::VerificationFailed {
+                realm: "Auth".to_string(),
+                challenge: None
+            },
+            PasswordNotification::parse("Verification Failed: 'Auth'").unwrap()
+        );
+        assert_eq!(
+            PasswordNotification::AuthToken("SESS_ID_abc".to_string()),
+            PasswordNotification::parse("Auth-Token:SESS_ID_abc").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_need_with_static_challenge() {
+        let notification =
+            PasswordNotification::parse("Need 'Auth' username/password SC:1,Please enter PIN")
+                .unwrap();
+
+        let expected = PasswordRequest::new("Auth".to_string(), true).with_static_challenge(
+            StaticChallenge::new(true, false, "Please enter PIN".to_string()),
+        );
+        assert_eq!(PasswordNotification::Need(expected), notification);
+    }
+
+    #[test]
+    fn test_parse_verification_failed_with_dynamic_challenge() {
+        let notification = PasswordNotification::parse(
+            "Verification Failed: 'Auth' ['CRV1:R,E:Om01u7Fh4LrGBS7uh0SWmzwabUiGiW6l:Y3Ix:Please enter token PIN']",
+        )
+        .unwrap();
+
+        match notification {
+            PasswordNotification::VerificationFailed {
+                realm,
+                challenge: Some(challenge),
+            } => {
+                assert_eq!("Auth", realm);
+                assert_eq!("cr1", challenge.username());
+                assert_eq!("Please enter token PIN", challenge.text());
+            }
+            _ => panic!("was expecting a dynamic challenge"),
+        }
+    }
+
+    #[test]
+    fn test_parse_malformed_password_notification() {
+        assert!(PasswordNotification::parse("Need Auth username/password").is_err());
+        assert!(PasswordNotification::parse("Need 'Auth' pin").is_err());
+    }
+
+    #[test]
+    fn test_auth_retry_modes() {
+        assert_eq!("none", AuthRetry::None.to_string());
+        assert_eq!("nointeract", AuthRetry::NoInteract.to_string());
+        assert_eq!("interact", AuthRetry::Interact.to_string());
+    }
+
+    #[test]
+    fn test_password_redacted_in_debug() {
+        let credentials = Credentials::new(Some("alice".to_string()), "hunter2".to_string());
+
+        let debug = format!("{:?}", credentials);
+        assert!(debug.contains("alice"));
+        assert!(!debug.contains("hunter2"));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::client::Client;
+use crate::Status;
+use chrono::prelude::{DateTime, Utc};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A client listed in two status snapshots whose address or traffic counters changed
+pub struct ClientChange {
+    previous: Client,
+    current: Client,
+}
+
+impl ClientChange {
+    /// The client in the earlier snapshot
+    pub fn previous(&self) -> &Client {
+        &self.previous
+    }
+
+    /// The client in the later snapshot
+    pub fn current(&self) -> &Client {
+        &self.current
+    }
+
+    /// Whether the client floated to another real address
+    pub fn address_changed(&self) -> bool {
+        self.previous.ip_address() != self.current.ip_address()
+    }
+
+    /// Bytes received from the client between the two snapshots
+    pub fn bytes_received_delta(&self) -> f64 {
+        self.current.bytes_received() - self.previous.bytes_received()
+    }
+
+    /// Bytes sent to the client between the two snapshots
+    pub fn bytes_sent_delta(&self) -> f64 {
+        self.current.bytes_sent() - self.previous.bytes_sent()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The clients which connected, disconnected or changed between two status snapshots, as
+/// returned by [`Status::diff`]
+pub struct StatusDiff {
+    connected: Vec<Client>,
+    disconnected: Vec<Client>,
+    changed: Vec<ClientChange>,
+}
+
+impl StatusDiff {
+    /// Clients listed only in the later snapshot, in its order
+    pub fn connected(&self) -> &[Client] {
+        &self.connected
+    }
+
+    /// Clients listed only in the earlier snapshot, with the last byte counts seen for them
+    pub fn disconnected(&self) -> &[Client] {
+        &self.disconnected
+    }
+
+    /// Clients listed in both snapshots whose address or byte counts changed
+    pub fn changed(&self) -> &[ClientChange] {
+        &self.changed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connected.is_empty() && self.disconnected.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// Identifies a client session across snapshots. A restarted server reuses client IDs, so they
+/// are only trusted along with the connection time
+#[derive(PartialEq, Eq, Hash)]
+enum ClientKey<'a> {
+    Cid(u64, &'a DateTime<Utc>),
+    Session(&'a str, &'a str, &'a DateTime<Utc>),
+}
+
+impl<'a> ClientKey<'a> {
+    fn of(client: &'a Client) -> ClientKey<'a> {
+        match client.cid() {
+            Some(cid) => ClientKey::Cid(cid, client.connected_since()),
+            None => {
+                ClientKey::Session(client.name(), client.ip_address(), client.connected_since())
+            }
+        }
+    }
+}
+
+impl Status {
+    /// Compares this status with an earlier one. Clients are matched by client ID, or by common
+    /// name, real address and connection time when the server does not list client IDs
+    pub fn diff(&self, previous: &Status) -> StatusDiff {
+        let mut earlier: HashMap<ClientKey, &Client> = previous
+            .clients()
+            .iter()
+            .map(|client| (ClientKey::of(client), client))
+            .collect();
+        let mut diff = StatusDiff::default();
+        for client in self.clients() {
+            match earlier.remove(&ClientKey::of(client)) {
+                None => diff.connected.push(client.clone()),
+                Some(before) if before != client => diff.changed.push(ClientChange {
+                    previous: before.clone(),
+                    current: client.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        diff.disconnected = previous
+            .clients()
+            .iter()
+            .filter(|client| earlier.contains_key(&ClientKey::of(client)))
+            .cloned()
+            .collect();
+        diff
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{self, status};
+
+    fn client(name: &str, address: &str, since: i64, bytes: f64, cid: Option<u64>) -> Client {
+        let client = fixtures::client(name, address, since, bytes, bytes * 2.0);
+        match cid {
+            Some(cid) => client.with_cid(cid),
+            None => client,
+        }
+    }
+
+    #[test]
+    fn test_diff_by_cid() {
+        let previous = status(vec![
+            client("alice", "1.1.1.1", 100, 10.0, Some(1)),
+            client("bob", "2.2.2.2", 200, 20.0, Some(2)),
+            client("carol", "3.3.3.3", 300, 30.0, Some(3)),
+        ]);
+        let current = status(vec![
+            client("alice", "1.1.1.1", 100, 10.0, Some(1)),
+            client("bob", "4.4.4.4", 200, 25.0, Some(2)),
+            client("dave", "5.5.5.5", 400, 0.0, Some(4)),
+        ]);
+        let diff = current.diff(&previous);
+
+        assert_eq!(&current.clients()[2..], diff.connected());
+        assert_eq!(&previous.clients()[2..], diff.disconnected());
+        assert_eq!(1, diff.changed().len());
+        let change = &diff.changed()[0];
+        assert!(change.address_changed());
+        assert_eq!(5.0, change.bytes_received_delta());
+        assert_eq!(10.0, change.bytes_sent_delta());
+    }
+
+    #[test]
+    fn test_reused_cid_is_a_new_client() {
+        let previous = status(vec![client("alice", "1.1.1.1", 100, 10.0, Some(0))]);
+        let current = status(vec![client("bob", "2.2.2.2", 500, 0.0, Some(0))]);
+        let diff = current.diff(&previous);
+
+        assert_eq!(current.clients(), diff.connected());
+        assert_eq!(previous.clients(), diff.disconnected());
+        assert!(diff.changed().is_empty());
+    }
+
+    #[test]
+    fn test_diff_without_cid() {
+        let previous = status(vec![
+            client("alice", "1.1.1.1", 100, 10.0, None),
+            client("alice", "2.2.2.2", 100, 10.0, None),
+        ]);
+        let current = status(vec![
+            client("alice", "1.1.1.1", 100, 15.0, None),
+            client("alice", "2.2.2.2", 300, 0.0, None),
+        ]);
+        let diff = current.diff(&previous);
+
+        assert_eq!(&current.clients()[1..], diff.connected());
+        assert_eq!(&previous.clients()[1..], diff.disconnected());
+        assert_eq!(&current.clients()[0], diff.changed()[0].current());
+        assert!(!diff.changed()[0].address_changed());
+        assert!(current.diff(&current).is_empty());
+    }
+}
@@ -1,6 +1,7 @@
 use std::error::Error;
 use std::fmt;
 use std::io;
+use std::net;
 use std::num;
 
 pub type OpenvpnResult<T> = Result<T, OpenvpnError>;
@@ -10,8 +11,17 @@ pub enum OpenvpnError {
     Io(io::Error),
     ParseInt(num::ParseIntError),
     ParseFloat(num::ParseFloatError),
+    ParseAddr(net::AddrParseError),
     MalformedResponse(String),
     MissingURLInput(String),
+    CommandFailed(String),
+    InvalidArgument(String),
+    PoolExhausted(String),
+    InvalidPolicy(String),
+    VerificationFailed(String),
+    ChallengeUnanswered(String),
+    #[cfg(feature = "history")]
+    History(rusqlite::Error),
 }
 
 impl fmt::Display for OpenvpnError {
@@ -20,6 +30,7 @@ impl fmt::Display for OpenvpnError {
             OpenvpnError::Io(ref err) => err.fmt(f),
             OpenvpnError::ParseInt(ref err) => err.fmt(f),
             OpenvpnError::ParseFloat(ref err) => err.fmt(f),
+            OpenvpnError::ParseAddr(ref err) => err.fmt(f),
             OpenvpnError::MalformedResponse(ref response) => write!(
                 f,
                 "could not parse '{}' response from openvpn server",
@@ -28,18 +39,45 @@ impl fmt::Display for OpenvpnError {
             OpenvpnError::MissingURLInput(ref url) => {
                 write!(f, "could not parse '{}' as a URL", url)
             }
+            OpenvpnError::CommandFailed(ref message) => {
+                write!(f, "openvpn server rejected the command: {}", message)
+            }
+            OpenvpnError::InvalidArgument(ref argument) => {
+                write!(f, "'{}' cannot be sent to the openvpn server", argument)
+            }
+            OpenvpnError::PoolExhausted(ref group) => {
+                write!(f, "no address left in the '{}' pool", group)
+            }
+            OpenvpnError::InvalidPolicy(ref message) => write!(f, "invalid policy: {}", message),
+            OpenvpnError::VerificationFailed(ref realm) => {
+                write!(f, "the server rejected the '{}' credentials", realm)
+            }
+            OpenvpnError::ChallengeUnanswered(ref text) => {
+                write!(f, "no response to the '{}' challenge", text)
+            }
+            #[cfg(feature = "history")]
+            OpenvpnError::History(ref err) => write!(f, "session history: {}", err),
         }
     }
 }
 
 impl Error for OpenvpnError {
-    fn description(&self) -> &str {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
         match *self {
-            OpenvpnError::Io(ref err) => err.description(),
-            OpenvpnError::ParseInt(ref err) => err.description(),
-            OpenvpnError::ParseFloat(ref err) => err.description(),
-            OpenvpnError::MalformedResponse(ref _response) => "malformed response",
-            OpenvpnError::MissingURLInput(ref _url) => "missing url",
+            OpenvpnError::Io(ref err) => Some(err),
+            OpenvpnError::ParseInt(ref err) => Some(err),
+            OpenvpnError::ParseFloat(ref err) => Some(err),
+            OpenvpnError::ParseAddr(ref err) => Some(err),
+            OpenvpnError::MalformedResponse(ref _response) => None,
+            OpenvpnError::MissingURLInput(ref _url) => None,
+            OpenvpnError::CommandFailed(ref _message) => None,
+            OpenvpnError::InvalidArgument(ref _argument) => None,
+            OpenvpnError::PoolExhausted(ref _group) => None,
+            OpenvpnError::InvalidPolicy(ref _message) => None,
+            OpenvpnError::VerificationFailed(ref _realm) => None,
+            OpenvpnError::ChallengeUnanswered(ref _text) => None,
+            #[cfg(feature = "history")]
+            OpenvpnError::History(ref err) => Some(err),
         }
     }
 }
@@ -61,3 +99,16 @@ impl From<num::ParseFloatError> for OpenvpnError {
         OpenvpnError::ParseFloat(err)
     }
 }
+
+impl From<net::AddrParseError> for OpenvpnError {
+    fn from(err: net::AddrParseError) -> OpenvpnError {
+        OpenvpnError::ParseAddr(err)
+    }
+}
+
+#[cfg(feature = "history")]
+impl From<rusqlite::Error> for OpenvpnError {
+    fn from(err: rusqlite::Error) -> OpenvpnError {
+        OpenvpnError::History(err)
+    }
+}
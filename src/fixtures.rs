@@ -0,0 +1,29 @@
+//! Clients and statuses for the tests
+use crate::{Client, Status};
+use chrono::prelude::{TimeZone, Utc};
+
+/// A client connected since the given Unix time
+pub fn client(
+    name: &str,
+    address: &str,
+    since: i64,
+    bytes_received: f64,
+    bytes_sent: f64,
+) -> Client {
+    Client::new(
+        name.to_string(),
+        address.to_string(),
+        Utc.timestamp_opt(since, 0).unwrap(),
+        bytes_received,
+        bytes_sent,
+    )
+}
+
+/// A status taken at 2019-01-19T16:04:53Z
+pub fn status(clients: Vec<Client>) -> Status {
+    Status::new(
+        "OpenVPN 2.6.8".to_string(),
+        Utc.timestamp_opt(1547913893, 0).unwrap(),
+        clients,
+    )
+}
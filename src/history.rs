@@ -0,0 +1,482 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::notification::Notification;
+use crate::tracker::{SessionRecord, SessionTracker};
+use crate::watcher::WatchEvent;
+use chrono::prelude::{DateTime, TimeZone, Utc};
+use rusqlite::types::{FromSqlError, Type, Value};
+use rusqlite::{params, params_from_iter, Connection, Row};
+use std::convert::TryFrom;
+use std::net::IpAddr;
+use std::path::Path;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS sessions (
+        id INTEGER PRIMARY KEY,
+        cid INTEGER,
+        common_name TEXT,
+        username TEXT,
+        real_address TEXT,
+        real_port INTEGER,
+        virtual_address TEXT,
+        virtual_address6 TEXT,
+        connected_since INTEGER NOT NULL,
+        disconnected_at INTEGER NOT NULL,
+        bytes_received REAL NOT NULL,
+        bytes_sent REAL NOT NULL,
+        reason TEXT
+    );
+    CREATE INDEX IF NOT EXISTS sessions_common_name ON sessions (common_name);
+    CREATE INDEX IF NOT EXISTS sessions_username ON sessions (username);
+    CREATE INDEX IF NOT EXISTS sessions_virtual_address ON sessions (virtual_address);
+    CREATE INDEX IF NOT EXISTS sessions_real_address ON sessions (real_address);
+    CREATE INDEX IF NOT EXISTS sessions_time ON sessions (connected_since, disconnected_at);
+    PRAGMA user_version = 1;
+";
+
+const COLUMNS: &str = "id, cid, common_name, username, real_address, real_port, virtual_address, \
+    virtual_address6, connected_since, disconnected_at, bytes_received, bytes_sent, reason";
+
+/// The reason stored for the sessions ended by a `>CLIENT:DISCONNECT` notification
+pub const REASON_DISCONNECTED: &str = "disconnected";
+/// The reason stored for the clients which left the status of a
+/// [`StatusWatcher`](crate::StatusWatcher)
+pub const REASON_LEFT_STATUS: &str = "left the status";
+
+#[derive(Clone, Debug, PartialEq)]
+/// A finished session read from the history
+pub struct HistoryEntry {
+    id: i64,
+    record: SessionRecord,
+    reason: Option<String>,
+}
+
+impl HistoryEntry {
+    /// Row ID in the database
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    pub fn record(&self) -> &SessionRecord {
+        &self.record
+    }
+
+    /// Why the session ended, as given when it was stored
+    pub fn reason(&self) -> Option<&str> {
+        self.reason.as_deref()
+    }
+
+    fn from_row(row: &Row) -> rusqlite::Result<HistoryEntry> {
+        let time = |index| -> rusqlite::Result<DateTime<Utc>> {
+            let timestamp = row.get(index)?;
+            Utc.timestamp_opt(timestamp, 0).single().ok_or_else(|| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    index,
+                    Type::Integer,
+                    Box::new(FromSqlError::OutOfRange(timestamp)),
+                )
+            })
+        };
+        let address = |index| -> rusqlite::Result<Option<IpAddr>> {
+            row.get::<_, Option<String>>(index)?
+                .map(|address| address.parse())
+                .transpose()
+                .map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e))
+                })
+        };
+        Ok(HistoryEntry {
+            id: row.get(0)?,
+            record: SessionRecord {
+                cid: row.get(1)?,
+                common_name: row.get(2)?,
+                username: row.get(3)?,
+                real_address: address(4)?,
+                real_port: row.get(5)?,
+                virtual_address: address(6)?,
+                virtual_address6: address(7)?,
+                connected_since: time(8)?,
+                disconnected_at: time(9)?,
+                bytes_received: row.get(10)?,
+                bytes_sent: row.get(11)?,
+            },
+            reason: row.get(12)?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Selects the sessions read by [`HistoryStore::query`]. The conditions add up, and no
+/// condition selects every session
+pub struct HistoryQuery {
+    user: Option<String>,
+    address: Option<IpAddr>,
+    from: Option<DateTime<Utc>>,
+    to: Option<DateTime<Utc>>,
+    limit: Option<u32>,
+}
+
+impl HistoryQuery {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// the sessions of a common name or username
+    pub fn user(&mut self, user: &str) -> &mut HistoryQuery {
+        self.user = Some(user.to_owned());
+        self
+    }
+
+    /// the sessions with this real or virtual address
+    pub fn address(&mut self, address: IpAddr) -> &mut HistoryQuery {
+        self.address = Some(address);
+        self
+    }
+
+    /// the sessions connected at any time between the two dates
+    pub fn between(&mut self, from: DateTime<Utc>, to: DateTime<Utc>) -> &mut HistoryQuery {
+        self.from = Some(from);
+        self.to = Some(to);
+        self
+    }
+
+    /// the sessions connected at the date
+    pub fn at(&mut self, time: DateTime<Utc>) -> &mut HistoryQuery {
+        self.between(time, time)
+    }
+
+    /// the most sessions returned, the earliest first
+    pub fn limit(&mut self, limit: u32) -> &mut HistoryQuery {
+        self.limit = Some(limit);
+        self
+    }
+
+    fn to_sql(&self) -> (String, Vec<Value>) {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        if let Some(ref user) = self.user {
+            conditions.push("(common_name = ? OR username = ?)");
+            values.push(Value::Text(user.clone()));
+            values.push(Value::Text(user.clone()));
+        }
+        if let Some(address) = self.address {
+            conditions.push("? IN (real_address, virtual_address, virtual_address6)");
+            values.push(Value::Text(address.to_string()));
+        }
+        if let Some(to) = self.to {
+            conditions.push("connected_since <= ?");
+            values.push(Value::Integer(to.timestamp()));
+        }
+        if let Some(from) = self.from {
+            conditions.push("disconnected_at >= ?");
+            values.push(Value::Integer(from.timestamp()));
+        }
+        let mut sql = format!("SELECT {} FROM sessions", COLUMNS);
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(" ORDER BY connected_since, id");
+        if let Some(limit) = self.limit {
+            sql.push_str(" LIMIT ?");
+            values.push(Value::Integer(limit.into()));
+        }
+        (sql, values)
+    }
+}
+
+/// Keeps the finished client sessions in a SQLite database, so that past sessions can be
+/// looked up by user, date and address
+pub struct HistoryStore {
+    connection: Connection,
+}
+
+impl HistoryStore {
+    /// Opens the database file, creating it if needed
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<HistoryStore> {
+        HistoryStore::init(Connection::open(path)?)
+    }
+
+    /// Opens a database which only lives as long as the store
+    pub fn open_in_memory() -> Result<HistoryStore> {
+        HistoryStore::init(Connection::open_in_memory()?)
+    }
+
+    fn init(connection: Connection) -> Result<HistoryStore> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(HistoryStore { connection })
+    }
+
+    /// Stores a finished session with the reason it ended, and returns its row ID
+    pub fn insert(&self, record: &SessionRecord, reason: Option<&str>) -> Result<i64> {
+        let address = |address: Option<IpAddr>| address.map(|a| a.to_string());
+        let cid = record
+            .cid()
+            .map(|cid| {
+                i64::try_from(cid).map_err(|_| OpenvpnError::InvalidArgument(cid.to_string()))
+            })
+            .transpose()?;
+        self.connection.execute(
+            &format!(
+                "INSERT INTO sessions ({}) VALUES (NULL, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+                COLUMNS
+            ),
+            params![
+                cid,
+                record.common_name(),
+                record.username(),
+                address(record.real_address()),
+                record.real_port(),
+                address(record.virtual_address()),
+                address(record.virtual_address6()),
+                record.connected_since().timestamp(),
+                record.disconnected_at().timestamp(),
+                record.bytes_received(),
+                record.bytes_sent(),
+                reason,
+            ],
+        )?;
+        Ok(self.connection.last_insert_rowid())
+    }
+
+    /// Updates the tracker from a notification and stores the session ended by a
+    /// `>CLIENT:DISCONNECT`
+    pub fn record_notification(
+        &self,
+        tracker: &mut SessionTracker,
+        notification: &Notification,
+    ) -> Result<Option<SessionRecord>> {
+        match tracker.apply(notification) {
+            Some(record) => {
+                self.insert(&record, Some(REASON_DISCONNECTED))?;
+                Ok(Some(record))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Stores the session of a client which left the status, the other events are ignored.
+    /// The session is taken to end when the event is handled
+    pub fn record_watch_event(&self, event: &WatchEvent) -> Result<Option<SessionRecord>> {
+        match *event {
+            WatchEvent::Disconnected(ref client) => {
+                let record = SessionRecord::from_client(client, Utc::now());
+                self.insert(&record, Some(REASON_LEFT_STATUS))?;
+                Ok(Some(record))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Reads the sessions selected by the query, the earliest connected first
+    pub fn query(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>> {
+        let (sql, values) = query.to_sql();
+        let mut statement = self.connection.prepare(&sql)?;
+        let entries = statement
+            .query_map(params_from_iter(values), HistoryEntry::from_row)?
+            .collect::<rusqlite::Result<_>>()?;
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::Client;
+    use crate::client_env::ClientEnv;
+    use crate::fixtures;
+    use crate::notification::{ClientEvent, ClientEventKind};
+
+    fn time(seconds: i64) -> DateTime<Utc> {
+        Utc.timestamp_opt(seconds, 0).unwrap()
+    }
+
+    fn client(name: &str, virtual_address: &str, since: i64) -> Client {
+        fixtures::client(name, "192.168.0.3", since, 100.0, 200.0)
+            .with_virtual_address(virtual_address.parse().unwrap())
+            .with_cid(7)
+    }
+
+    fn store() -> HistoryStore {
+        let store = HistoryStore::open_in_memory().unwrap();
+        let sessions = [
+            ("alice", "10.8.0.17", 1_000, 2_000),
+            ("bob", "10.8.0.17", 3_000, 4_000),
+            ("alice", "10.8.0.18", 5_000, 6_000),
+        ];
+        for &(name, address, since, until) in &sessions {
+            let record = SessionRecord::from_client(&client(name, address, since), time(until));
+            store.insert(&record, None).unwrap();
+        }
+        store
+    }
+
+    fn names(entries: &[HistoryEntry]) -> Vec<(&str, i64)> {
+        entries
+            .iter()
+            .map(|entry| {
+                let record = entry.record();
+                (
+                    record.common_name().unwrap(),
+                    record.connected_since().timestamp(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_query_sessions() {
+        let store = store();
+
+        assert_eq!(3, store.query(&HistoryQuery::new()).unwrap().len());
+        assert_eq!(
+            vec![("alice", 1_000), ("alice", 5_000)],
+            names(&store.query(HistoryQuery::new().user("alice")).unwrap())
+        );
+        assert_eq!(
+            vec![("bob", 3_000)],
+            names(
+                &store
+                    .query(
+                        HistoryQuery::new()
+                            .at(time(3_500))
+                            .address("10.8.0.17".parse().unwrap())
+                    )
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            vec![("alice", 1_000), ("bob", 3_000)],
+            names(
+                &store
+                    .query(HistoryQuery::new().between(time(2_000), time(3_000)))
+                    .unwrap()
+            )
+        );
+        assert_eq!(
+            vec![("alice", 1_000)],
+            names(
+                &store
+                    .query(
+                        HistoryQuery::new()
+                            .address("192.168.0.3".parse().unwrap())
+                            .limit(1)
+                    )
+                    .unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn test_stored_record_is_read_back() {
+        let store = HistoryStore::open_in_memory().unwrap();
+        let record = SessionRecord::from_client(&client("alice", "10.8.0.17", 1_000), time(2_000));
+
+        let id = store.insert(&record, Some("killed")).unwrap();
+        let entries = store.query(&HistoryQuery::new()).unwrap();
+
+        assert_eq!(1, entries.len());
+        assert_eq!(id, entries[0].id());
+        assert_eq!(&record, entries[0].record());
+        assert_eq!(Some("killed"), entries[0].reason());
+    }
+
+    #[test]
+    fn test_missing_cid() {
+        let store = HistoryStore::open_in_memory().unwrap();
+        let client = fixtures::client("alice", "192.168.0.3", 1_000, 100.0, 200.0);
+        store
+            .insert(&SessionRecord::from_client(&client, time(2_000)), None)
+            .unwrap();
+
+        let entries = store.query(&HistoryQuery::new()).unwrap();
+        assert_eq!(None, entries[0].record().cid());
+    }
+
+    #[test]
+    fn test_out_of_range_time() {
+        let store = store();
+        store
+            .connection
+            .execute("UPDATE sessions SET disconnected_at = 99999999999999", [])
+            .unwrap();
+
+        assert!(store.query(&HistoryQuery::new()).is_err());
+    }
+
+    #[test]
+    fn test_unparsable_address() {
+        let store = store();
+        store
+            .connection
+            .execute("UPDATE sessions SET real_address = 'nowhere'", [])
+            .unwrap();
+
+        assert!(store.query(&HistoryQuery::new()).is_err());
+    }
+
+    #[test]
+    fn test_out_of_range_cid() {
+        let store = HistoryStore::open_in_memory().unwrap();
+        let client = client("alice", "10.8.0.17", 1_000).with_cid(u64::MAX);
+        let record = SessionRecord::from_client(&client, time(2_000));
+
+        let result = store.insert(&record, None);
+
+        assert!(matches!(result, Err(OpenvpnError::InvalidArgument(_))));
+        store
+            .connection
+            .execute(
+                "INSERT INTO sessions (cid, connected_since, disconnected_at, bytes_received, \
+                 bytes_sent) VALUES (-1, 0, 0, 0, 0)",
+                [],
+            )
+            .unwrap();
+        assert!(store.query(&HistoryQuery::new()).is_err());
+    }
+
+    #[test]
+    fn test_record_events() {
+        let store = HistoryStore::open_in_memory().unwrap();
+        let mut tracker = SessionTracker::new();
+        let env = ClientEnv::parse(
+            [
+                ">CLIENT:ENV,common_name=bob",
+                ">CLIENT:ENV,username=bob@example.com",
+                ">CLIENT:ENV,trusted_ip=192.168.0.4",
+                ">CLIENT:ENV,ifconfig_pool_remote_ip=10.8.0.20",
+                ">CLIENT:ENV,time_unix=1000",
+                ">CLIENT:ENV,time_duration=60",
+            ]
+            .iter()
+            .cloned(),
+        )
+        .unwrap();
+        let disconnect =
+            Notification::Client(ClientEvent::new(ClientEventKind::Disconnect, 4, None, env));
+
+        assert!(store
+            .record_notification(&mut tracker, &disconnect)
+            .unwrap()
+            .is_some());
+        assert!(store
+            .record_watch_event(&WatchEvent::Connected(client("alice", "10.8.0.17", 0)))
+            .unwrap()
+            .is_none());
+        store
+            .record_watch_event(&WatchEvent::Disconnected(client("alice", "10.8.0.17", 0)))
+            .unwrap();
+
+        let entries = store
+            .query(HistoryQuery::new().user("bob@example.com"))
+            .unwrap();
+        assert_eq!(1, entries.len());
+        assert_eq!(Some(REASON_DISCONNECTED), entries[0].reason());
+        assert_eq!(
+            Some("10.8.0.20".parse().unwrap()),
+            entries[0].record().virtual_address()
+        );
+        assert_eq!(1_060, entries[0].record().disconnected_at().timestamp());
+        let entries = store.query(HistoryQuery::new().user("alice")).unwrap();
+        assert_eq!(Some(REASON_LEFT_STATUS), entries[0].reason());
+    }
+}
@@ -0,0 +1,167 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+
+const WEB_AUTH: &str = "WEB_AUTH";
+const OPEN_URL: &str = "OPEN_URL";
+const CR_TEXT: &str = "CR_TEXT";
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The flags of a `WEB_AUTH` message
+pub struct WebAuthFlags {
+    hidden: bool,
+    external: bool,
+}
+
+impl WebAuthFlags {
+    /// Parses the comma separated flags, ignoring unknown ones
+    pub fn parse(raw_flags: &str) -> WebAuthFlags {
+        let mut flags = WebAuthFlags::default();
+        for flag in raw_flags.split(',') {
+            match flag {
+                "hidden" => flags.hidden = true,
+                "external" => flags.external = true,
+                _ => {}
+            }
+        }
+        flags
+    }
+
+    /// Whether the page may be loaded without showing it, i.e. it completes on its own
+    pub fn hidden(&self) -> bool {
+        self.hidden
+    }
+
+    /// Whether the page must be opened in an external browser rather than an embedded one
+    pub fn external(&self) -> bool {
+        self.external
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
+/// Contains the marshalled `>INFOMSG` notification, sent to clients during pending
+/// authentication
+pub enum InfoMessage {
+    /// `>INFOMSG:WEB_AUTH:<flags>:<url>`, the user must complete authentication on a web page
+    WebAuth { flags: WebAuthFlags, url: String },
+    /// `>INFOMSG:OPEN_URL:<url>`, the former form of `WEB_AUTH`
+    OpenUrl { url: String },
+    /// `>INFOMSG:CR_TEXT:<flags>:<text>`, a challenge to answer with `cr-response`
+    CrText {
+        echo: bool,
+        response_required: bool,
+        text: String,
+    },
+    /// Any other informational message
+    Other(String),
+}
+
+impl InfoMessage {
+    /// Parses the payload of a `>INFOMSG` notification
+    pub fn parse(payload: &str) -> Result<InfoMessage> {
+        let malformed = || OpenvpnError::MalformedResponse(payload.to_string());
+        let (kind, rest) = match payload.split_once(':') {
+            Some(split) => split,
+            None => return Ok(InfoMessage::Other(payload.to_string())),
+        };
+        match kind {
+            WEB_AUTH => {
+                let (flags, url) = rest.split_once(':').ok_or_else(malformed)?;
+                Ok(InfoMessage::WebAuth {
+                    flags: WebAuthFlags::parse(flags),
+                    url: url.to_string(),
+                })
+            }
+            OPEN_URL => Ok(InfoMessage::OpenUrl {
+                url: rest.to_string(),
+            }),
+            CR_TEXT => {
+                let (flags, text) = rest.split_once(':').ok_or_else(malformed)?;
+                let flags: Vec<_> = flags.split(',').collect();
+                Ok(InfoMessage::CrText {
+                    echo: flags.contains(&"E"),
+                    response_required: flags.contains(&"R"),
+                    text: text.to_string(),
+                })
+            }
+            _ => Ok(InfoMessage::Other(payload.to_string())),
+        }
+    }
+}
+
+/// Reacts to the `>INFOMSG` notifications of a client waiting for pending authentication
+pub trait InfoMessageHandler {
+    /// Opens the page of a `WEB_AUTH` or `OPEN_URL` message, e.g. in a browser. `OPEN_URL`
+    /// messages come with default flags
+    fn open_url(&mut self, url: &str, flags: &WebAuthFlags) -> Result<()>;
+
+    /// Returns the response to a `CR_TEXT` challenge, sent with `cr-response`
+    fn challenge_text(&mut self, text: &str, echo: bool) -> Result<String>;
+
+    /// Called for the other messages, and for `CR_TEXT` messages which need no response.
+    /// Does nothing by default
+    fn info(&mut self, _message: &str) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_web_auth() {
+        let message =
+            InfoMessage::parse("WEB_AUTH:hidden,unknown:https://auth.example.com/login?state=abc")
+                .unwrap();
+
+        match message {
+            InfoMessage::WebAuth { flags, url } => {
+                assert!(flags.hidden());
+                assert!(!flags.external());
+                assert_eq!("https://auth.example.com/login?state=abc", url);
+            }
+            _ => panic!("was expecting web auth"),
+        }
+    }
+
+    #[test]
+    fn test_parse_web_auth_without_flags() {
+        assert_eq!(
+            InfoMessage::WebAuth {
+                flags: WebAuthFlags::default(),
+                url: "https://auth.example.com".to_string()
+            },
+            InfoMessage::parse("WEB_AUTH::https://auth.example.com").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_open_url_and_cr_text() {
+        assert_eq!(
+            InfoMessage::OpenUrl {
+                url: "https://auth.example.com".to_string()
+            },
+            InfoMessage::parse("OPEN_URL:https://auth.example.com").unwrap()
+        );
+        assert_eq!(
+            InfoMessage::CrText {
+                echo: true,
+                response_required: true,
+                text: "Enter the code: sent by SMS".to_string()
+            },
+            InfoMessage::parse("CR_TEXT:R,E:Enter the code: sent by SMS").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_other_and_malformed() {
+        assert_eq!(
+            InfoMessage::Other("Waiting for the server".to_string()),
+            InfoMessage::parse("Waiting for the server").unwrap()
+        );
+        assert!(InfoMessage::parse("WEB_AUTH:https").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::signing::{SignRequest, SignatureAlgorithm, Signer};
+use rsa::pkcs1::DecodeRsaPrivateKey;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::rand_core::OsRng;
+use rsa::traits::PublicKeyParts;
+use rsa::{BigUint, Pkcs1v15Sign, Pss, RsaPrivateKey};
+use sha2::{Sha256, Sha384, Sha512};
+use std::fs;
+use std::path::Path;
+
+/// A [`Signer`] backed by an RSA private key read from a PEM file (PKCS#1 or PKCS#8). Meant for
+/// tests and for setups where the key is merely kept away from the openvpn process
+pub struct KeyFileSigner {
+    key: RsaPrivateKey,
+}
+
+impl KeyFileSigner {
+    /// Reads the private key from a PEM file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<KeyFileSigner> {
+        KeyFileSigner::from_pem(&fs::read_to_string(path)?)
+    }
+
+    /// Parses a PEM encoded private key
+    pub fn from_pem(pem: &str) -> Result<KeyFileSigner> {
+        let key = RsaPrivateKey::from_pkcs1_pem(pem)
+            .or_else(|_| RsaPrivateKey::from_pkcs8_pem(pem))
+            .map_err(|e| OpenvpnError::InvalidArgument(format!("invalid private key: {}", e)))?;
+        Ok(KeyFileSigner { key })
+    }
+
+    fn sign_pss(&self, request: &SignRequest) -> Result<Vec<u8>> {
+        let hash_length = match request.hash_algorithm() {
+            Some("SHA256") => 32,
+            Some("SHA384") => 48,
+            Some("SHA512") => 64,
+            algorithm => {
+                return Err(OpenvpnError::InvalidArgument(format!(
+                    "unsupported hash algorithm for a PSS signature: {}",
+                    algorithm.unwrap_or("none")
+                )))
+            }
+        };
+        let salt_length =
+            match request.salt_length() {
+                Some("max") => Some(self.key.size().checked_sub(hash_length + 2).ok_or_else(
+                    || {
+                        OpenvpnError::InvalidArgument(
+                            "the key is too small for the hash algorithm".to_string(),
+                        )
+                    },
+                )?),
+                _ => None,
+            };
+        let pss = match (hash_length, salt_length) {
+            (32, Some(salt)) => Pss::new_with_salt::<Sha256>(salt),
+            (32, None) => Pss::new::<Sha256>(),
+            (48, Some(salt)) => Pss::new_with_salt::<Sha384>(salt),
+            (48, None) => Pss::new::<Sha384>(),
+            (_, Some(salt)) => Pss::new_with_salt::<Sha512>(salt),
+            (_, None) => Pss::new::<Sha512>(),
+        };
+        self.key
+            .sign_with_rng(&mut OsRng, pss, request.data())
+            .map_err(signing_failed)
+    }
+
+    fn sign_raw(&self, data: &[u8]) -> rsa::Result<Vec<u8>> {
+        let signature = rsa::hazmat::rsa_decrypt_and_check(
+            &self.key,
+            Some(&mut OsRng),
+            &BigUint::from_bytes_be(data),
+        )?
+        .to_bytes_be();
+        let mut padded = vec![0; self.key.size().saturating_sub(signature.len())];
+        padded.extend(signature);
+        Ok(padded)
+    }
+}
+
+impl Signer for KeyFileSigner {
+    fn sign(&mut self, request: &SignRequest) -> Result<Vec<u8>> {
+        let signature = match *request.algorithm() {
+            SignatureAlgorithm::RsaPkcs1 => self
+                .key
+                .sign(Pkcs1v15Sign::new_unprefixed(), request.data()),
+            SignatureAlgorithm::RsaPss => return self.sign_pss(request),
+            SignatureAlgorithm::RsaNoPadding => self.sign_raw(request.data()),
+            ref algorithm => {
+                return Err(OpenvpnError::InvalidArgument(format!(
+                    "unsupported signature algorithm for an RSA key: {:?}",
+                    algorithm
+                )))
+            }
+        };
+        signature.map_err(signing_failed)
+    }
+}
+
+fn signing_failed(error: rsa::Error) -> OpenvpnError {
+    OpenvpnError::InvalidArgument(format!("signing failed: {}", error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use rsa::RsaPublicKey;
+    use sha2::Digest;
+
+    const KEY_FILE: &str = "tests/data/signing_key.pem";
+
+    #[test]
+    fn test_pkcs1_signature_verifies() {
+        let mut signer = KeyFileSigner::from_file(KEY_FILE).unwrap();
+        let digest = Sha256::digest(b"handshake");
+        let mut digest_info = vec![
+            0x30, 0x31, 0x30, 0x0d, 0x06, 0x09, 0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x02,
+            0x01, 0x05, 0x00, 0x04, 0x20,
+        ];
+        digest_info.extend_from_slice(&digest);
+        let request = SignRequest::new(digest_info, SignatureAlgorithm::RsaPkcs1);
+
+        let signature = signer.sign(&request).unwrap();
+
+        let public_key = RsaPublicKey::from(&signer.key);
+        public_key
+            .verify(Pkcs1v15Sign::new::<Sha256>(), &digest, &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_pss_signature_verifies() {
+        let mut signer = KeyFileSigner::from_file(KEY_FILE).unwrap();
+        let digest = Sha256::digest(b"handshake");
+        let request = SignRequest::parse_pk_sign(&format!(
+            "{},RSA_PKCS1_PSS_PADDING,hashalg=SHA256,saltlen=digest",
+            STANDARD.encode(digest)
+        ))
+        .unwrap();
+
+        let signature = signer.sign(&request).unwrap();
+
+        let public_key = RsaPublicKey::from(&signer.key);
+        public_key
+            .verify(Pss::new::<Sha256>(), &digest, &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn test_pss_unsupported_hash() {
+        let mut signer = KeyFileSigner::from_file(KEY_FILE).unwrap();
+        let request =
+            SignRequest::parse_pk_sign("AQID,RSA_PKCS1_PSS_PADDING,hashalg=SHA1,saltlen=max")
+                .unwrap();
+
+        assert!(matches!(
+            signer.sign(&request),
+            Err(OpenvpnError::InvalidArgument(_))
+        ));
+    }
+
+    #[test]
+    fn test_ecdsa_unsupported() {
+        let mut signer = KeyFileSigner::from_file(KEY_FILE).unwrap();
+        let request = SignRequest::new(vec![1, 2, 3], SignatureAlgorithm::Ecdsa);
+
+        assert!(signer.sign(&request).is_err());
+    }
+}
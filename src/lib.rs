@@ -6,7 +6,43 @@
 //! openvpn-management = "*"
 //! ```
 //! # Features:
-//! - Getting all connected client information
+//! - Getting all connected client information, and the clients which connected, disconnected
+//!   or changed between two snapshots with [`Status::diff`]
+//! - Polling the status with a [`StatusWatcher`] which reports the client changes to callbacks
+//!   or channels, for servers without a persistent management connection
+//! - Keeping the finished sessions in a SQLite database, queried by user, date and address
+//!   (`history` feature)
+//! - Parsing the `>CLIENT:ENV` blocks sent for client authentication into a [`ClientEnv`]
+//! - Receiving real-time notifications over a persistent [`Session`], such as the addresses
+//!   learned for each client
+//! - Tracking client sessions from connect to disconnect with a [`SessionTracker`]
+//! - Assigning tunnel addresses from per-group pools during client authentication with a
+//!   [`PoolAllocator`]
+//! - Enforcing daily or monthly traffic quotas per user with a [`QuotaEnforcer`], which warns,
+//!   kills the sessions over the allowance and denies them until the period resets
+//! - Deciding client authentication from a rules file with a `Policy` (`policy` feature)
+//! - Answering the `>PASSWORD` prompts of an openvpn client with a [`CredentialProvider`]
+//! - Static (`SC`) and dynamic (`CRV1`) challenge/response authentication
+//! - Retrying rejected credentials with `auth-retry interact` up to a limit, and clearing
+//!   cached passwords with `forget-passwords`
+//! - Handling the `>INFOMSG` web authentication and `CR_TEXT` challenges of pending
+//!   authentication with an [`InfoMessageHandler`]
+//! - Answering `>NEED-OK` and `>NEED-STR` requests with a [`PromptHandler`]
+//! - Signing `>PK_SIGN` and `>RSA_SIGN` requests with an external key through a [`Signer`],
+//!   e.g. a `KeyFileSigner` (`key-file` feature)
+//! - Supplying the client certificate for `>NEED-CERTIFICATE` with a [`CertificateProvider`]
+//! - Choosing the remote to connect to on `>REMOTE` with a [`RemotePolicy`], and listing the
+//!   configured remotes with [`Session::get_remote_entries`]
+//! - Choosing how to reach each remote on `>PROXY` with a [`ProxyPolicy`]
+//! - Following the openvpn state, log and byte counts, reading the global statistics, killing
+//!   clients and sending signals
+//! - Connecting over a unix socket and with a management password
+//! - The `ovpn-mgmt` command line client with an interactive REPL (`cli` feature)
+//! - The `ovpn-exporter` Prometheus exporter (`exporter` feature)
+//! - The `ovpn-top` terminal dashboard (`tui` feature)
+//! - The `ovpn-gateway` REST gateway (`gateway` feature)
+//! - Serializing the status, notifications and statistics with a versioned schema, e.g. as
+//!   JSON (`serde` feature)
 //! # Basic usage:
 //! ```rust
 //! use openvpn_management::EventManager;
@@ -38,18 +74,91 @@
 //! // get client information:
 //! let clients = status.clients();
 //! ```
+mod address;
+mod bytecount;
+mod certificate;
+mod challenge;
 mod client;
+mod client_env;
+mod command;
+mod credentials;
+mod diff;
 mod error;
-
+#[cfg(test)]
+mod fixtures;
+#[cfg(feature = "history")]
+mod history;
+mod info_message;
+#[cfg(feature = "key-file")]
+mod key_file;
+mod log;
+mod notification;
+#[cfg(feature = "policy")]
+mod policy;
+mod pool;
+mod prompt;
+mod proxy;
+mod quota;
+mod remote;
+#[cfg(feature = "serde")]
+mod schema;
+mod session;
+mod signal;
+mod signing;
+mod state;
+mod stats;
+mod stream;
+mod tracker;
+mod watcher;
+
+pub use crate::address::{AddressMap, ClientAddress, VirtualAddress};
+pub use crate::bytecount::ByteCount;
+pub use crate::certificate::{CertificateProvider, CertificateRequest};
+pub use crate::challenge::{DynamicChallenge, StaticChallenge};
 pub use crate::client::Client;
+pub use crate::client_env::{CertificateEntry, ClientEnv};
+pub use crate::credentials::{
+    AuthRetry, CredentialProvider, Credentials, PasswordNotification, PasswordRequest,
+};
+pub use crate::diff::{ClientChange, StatusDiff};
 pub use crate::error::{OpenvpnError, OpenvpnResult as Result};
+#[cfg(feature = "history")]
+pub use crate::history::{
+    HistoryEntry, HistoryQuery, HistoryStore, REASON_DISCONNECTED, REASON_LEFT_STATUS,
+};
+pub use crate::info_message::{InfoMessage, InfoMessageHandler, WebAuthFlags};
+#[cfg(feature = "key-file")]
+pub use crate::key_file::KeyFileSigner;
+pub use crate::log::LogEntry;
+pub use crate::notification::{ClientEvent, ClientEventKind, Notification, NotificationParser};
+#[cfg(feature = "policy")]
+pub use crate::policy::{Decision, Policy};
+pub use crate::pool::{AddressPool, Lease, PoolAllocator};
+pub use crate::prompt::{PromptHandler, PromptRequest};
+pub use crate::proxy::{ProxyAction, ProxyPolicy, ProxyRequest};
+pub use crate::quota::{Quota, QuotaEnforcer, QuotaEvent, QuotaIdentity, QuotaPeriod};
+pub use crate::remote::{RemoteAction, RemoteEntry, RemotePolicy, RemoteRequest};
+#[cfg(feature = "serde")]
+pub use crate::schema::{Versioned, SCHEMA_VERSION};
+pub use crate::session::Session;
+pub use crate::signal::Signal;
+pub use crate::signing::{SignRequest, SignatureAlgorithm, Signer};
+pub use crate::state::StateRecord;
+pub use crate::stats::LoadStats;
+use crate::stream::Stream;
+pub use crate::tracker::{ActiveSession, SessionRecord, SessionState, SessionTracker};
+pub use crate::watcher::{StatusWatcher, WatchEvent};
 use chrono::prelude::{DateTime, TimeZone, Utc};
 use std::io::{BufRead, BufReader, Write};
 use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 const DEFAULT_MANAGEMENT_URL: &str = "localhost:5555";
-const ENDING: &str = "END";
+pub(crate) const ENDING: &str = "END";
 const START_CLIENT_LIST: &str = "CLIENT_LIST";
 const START_TITLE: &str = "TITLE";
 const START_TIME: &str = "TIME";
@@ -57,6 +166,7 @@ const HEADER_START_LINE: &str = "HEADER\tCLIENT_LIST";
 const UNDEF: &str = "UNDEF";
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Contains the marshalled response from the `status` command sent to the openvpn server
 pub struct Status {
     title: String,
@@ -88,10 +198,41 @@ impl Status {
     }
 }
 
+enum ManagementAddress {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
 pub struct CommandManager {
-    management_address: SocketAddr,
+    management_address: ManagementAddress,
     connect_timeout: Option<Duration>,
     read_timeout: Option<Duration>,
+    password: Option<String>,
+}
+
+impl CommandManager {
+    /// Opens a persistent connection to the management interface, which receives the real-time
+    /// notifications sent by the server
+    pub fn open_session(&self) -> Result<Session> {
+        Session::new(self.connect()?)
+    }
+
+    fn connect(&self) -> Result<Stream> {
+        let mut stream = match self.management_address {
+            ManagementAddress::Tcp(ref address) => match self.connect_timeout {
+                Some(ct) => Stream::Tcp(TcpStream::connect_timeout(address, ct)?),
+                None => Stream::Tcp(TcpStream::connect(address)?),
+            },
+            #[cfg(unix)]
+            ManagementAddress::Unix(ref path) => Stream::Unix(UnixStream::connect(path)?),
+        };
+        stream.set_read_timeout(self.read_timeout)?;
+        if let Some(ref password) = self.password {
+            stream.authenticate(password)?;
+        }
+        Ok(stream)
+    }
 }
 
 pub trait EventManager {
@@ -103,13 +244,9 @@ impl EventManager for CommandManager {
     /// The response is then parsed into the status response with the client information. This
     /// can be used by applications which are polling the management interface for status updates
     fn get_status(&mut self) -> Result<Status> {
-        let mut stream = match self.connect_timeout {
-            Some(ct) => TcpStream::connect_timeout(&self.management_address, ct)?,
-            None => TcpStream::connect(&self.management_address)?,
-        };
-        stream.set_read_timeout(self.read_timeout)?;
+        let mut stream = self.connect()?;
         stream.write_all(b"status\n")?;
-        let mut reader = BufReader::new(&stream);
+        let mut reader = BufReader::new(stream);
 
         let mut output = String::new();
         while !output.trim().ends_with(ENDING) {
@@ -123,8 +260,11 @@ impl EventManager for CommandManager {
 
 pub struct CommandManagerBuilder {
     management_url: String,
+    #[cfg(unix)]
+    unix_socket: Option<PathBuf>,
     connect_timeout: Option<Duration>,
     read_timeout: Option<Duration>,
+    password: Option<String>,
 }
 
 impl CommandManagerBuilder {
@@ -138,6 +278,20 @@ impl CommandManagerBuilder {
         self
     }
 
+    /// the path of the management unix socket (`--management <path> unix`), used in place of
+    /// the management url
+    #[cfg(unix)]
+    pub fn unix_socket<P: AsRef<Path>>(&mut self, path: P) -> &mut CommandManagerBuilder {
+        self.unix_socket = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// the password of the management interface, when openvpn is started with a password file
+    pub fn password(&mut self, password: &str) -> &mut CommandManagerBuilder {
+        self.password = Some(password.to_owned());
+        self
+    }
+
     /// the TCP connection timeout. Default value is no connection timeout (`None`)
     pub fn connect_timeout(
         &mut self,
@@ -155,6 +309,17 @@ impl CommandManagerBuilder {
 
     /// builds the connection manager. Returns an error if the management url is malformed or does not resolve
     pub fn build(&mut self) -> Result<CommandManager> {
+        #[cfg(unix)]
+        {
+            if let Some(ref path) = self.unix_socket {
+                return Ok(CommandManager {
+                    management_address: ManagementAddress::Unix(path.clone()),
+                    read_timeout: self.read_timeout,
+                    connect_timeout: self.connect_timeout,
+                    password: self.password.clone(),
+                });
+            }
+        }
         let mut addrs_iter = self.management_url.to_socket_addrs()?;
 
         let management_address: SocketAddr = match addrs_iter.next() {
@@ -167,9 +332,10 @@ impl CommandManagerBuilder {
         };
 
         Ok(CommandManager {
-            management_address,
+            management_address: ManagementAddress::Tcp(management_address),
             read_timeout: self.read_timeout,
             connect_timeout: self.connect_timeout,
+            password: self.password.clone(),
         })
     }
 }
@@ -178,13 +344,16 @@ impl Default for CommandManagerBuilder {
     fn default() -> Self {
         CommandManagerBuilder {
             management_url: DEFAULT_MANAGEMENT_URL.to_owned(),
+            #[cfg(unix)]
+            unix_socket: None,
             connect_timeout: None,
             read_timeout: None,
+            password: None,
         }
     }
 }
 
-fn parse_status_output(output: String) -> Result<Status> {
+pub(crate) fn parse_status_output(output: String) -> Result<Status> {
     let split = output.split("\r\n");
     let mut clients = Vec::new();
     let mut has_client_list = false;
@@ -223,7 +392,7 @@ fn parse_title(raw_title: &str) -> Result<String> {
 fn parse_timestamp(raw_timestamp: &str) -> Result<DateTime<Utc>> {
     let vec: Vec<_> = split_line_by_tabs(raw_timestamp, 3)?;
     let timestamp = vec[2].parse()?;
-    Ok(get_utc_start_time(timestamp))
+    get_utc_start_time(timestamp)
 }
 
 fn parse_client(raw_client: &str) -> Result<Client> {
@@ -236,13 +405,21 @@ fn parse_client(raw_client: &str) -> Result<Client> {
     let timestamp: i64 = vec[8].parse()?;
     let bytes_received: f64 = vec[5].parse()?;
     let bytes_sent: f64 = vec[6].parse()?;
-    Ok(Client::new(
+    let client = Client::new(
         String::from(name),
         String::from(address),
-        get_utc_start_time(timestamp),
+        get_utc_start_time(timestamp)?,
         bytes_received,
         bytes_sent,
-    ))
+    );
+    let client = match vec[3].parse() {
+        Ok(virtual_address) => client.with_virtual_address(virtual_address),
+        Err(_) => client,
+    };
+    match vec.get(10) {
+        Some(cid) => Ok(client.with_cid(cid.parse()?)),
+        None => Ok(client),
+    }
 }
 
 fn split_line_by_tabs(raw_line: &str, expected_length: usize) -> Result<Vec<&str>> {
@@ -253,8 +430,10 @@ fn split_line_by_tabs(raw_line: &str, expected_length: usize) -> Result<Vec<&str
     Ok(vec)
 }
 
-fn get_utc_start_time(timestamp: i64) -> DateTime<Utc> {
-    Utc.timestamp(timestamp, 0)
+pub(crate) fn get_utc_start_time(timestamp: i64) -> Result<DateTime<Utc>> {
+    Utc.timestamp_opt(timestamp, 0)
+        .single()
+        .ok_or_else(|| OpenvpnError::MalformedResponse(timestamp.to_string()))
 }
 
 #[cfg(test)]
@@ -313,4 +492,13 @@ mod tests {
 
         assert_eq!(io::ErrorKind::InvalidInput, expected_error.kind());
     }
+
+    #[test]
+    fn test_out_of_range_client_time() {
+        let result = parse_client(
+            "CLIENT_LIST\tfoo\t1.2.3.4:1194\t10.8.0.2\t\t100\t200\tfoo\t99999999999999",
+        );
+
+        assert!(matches!(result, Err(OpenvpnError::MalformedResponse(_))));
+    }
 }
@@ -0,0 +1,76 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::get_utc_start_time;
+use chrono::prelude::{DateTime, Utc};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A line of the openvpn log, as returned by the `log` command or sent in a `>LOG`
+/// notification, e.g. `1547913893,I,Initialization Sequence Completed`
+pub struct LogEntry {
+    timestamp: DateTime<Utc>,
+    flags: String,
+    message: String,
+}
+
+impl LogEntry {
+    pub fn new(timestamp: DateTime<Utc>, flags: String, message: String) -> LogEntry {
+        LogEntry {
+            timestamp,
+            flags,
+            message,
+        }
+    }
+
+    /// Parses a log line, the message may itself contain commas
+    pub fn parse(line: &str) -> Result<LogEntry> {
+        let fields: Vec<_> = line.splitn(3, ',').collect();
+        if fields.len() != 3 {
+            return Err(OpenvpnError::MalformedResponse(line.to_string()));
+        }
+        Ok(LogEntry::new(
+            get_utc_start_time(fields[0].parse()?)?,
+            fields[1].to_string(),
+            fields[2].to_string(),
+        ))
+    }
+
+    pub fn timestamp(&self) -> &DateTime<Utc> {
+        &self.timestamp
+    }
+
+    /// The message flags: `I` informational, `F` fatal error, `N` non-fatal error, `W` warning
+    /// and `D` debug
+    pub fn flags(&self) -> &str {
+        &self.flags
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Whether the entry is a fatal or non-fatal error
+    pub fn is_error(&self) -> bool {
+        self.flags.contains(['F', 'N'])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log_entry() {
+        let entry = LogEntry::parse("1547913893,N,TLS Error: bad packet, dropping").unwrap();
+
+        assert_eq!(&get_utc_start_time(1547913893).unwrap(), entry.timestamp());
+        assert_eq!("N", entry.flags());
+        assert_eq!("TLS Error: bad packet, dropping", entry.message());
+        assert!(entry.is_error());
+    }
+
+    #[test]
+    fn test_parse_malformed_log_entry() {
+        assert!(LogEntry::parse("1547913893,I").is_err());
+        assert!(LogEntry::parse("yesterday,I,message").is_err());
+    }
+}
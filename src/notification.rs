@@ -0,0 +1,495 @@
+use crate::address::{ClientAddress, VirtualAddress};
+use crate::bytecount::ByteCount;
+use crate::certificate::CertificateRequest;
+use crate::client_env::ClientEnv;
+use crate::credentials::PasswordNotification;
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::info_message::InfoMessage;
+use crate::log::LogEntry;
+use crate::prompt::PromptRequest;
+use crate::proxy::ProxyRequest;
+use crate::remote::RemoteRequest;
+use crate::signing::SignRequest;
+use crate::state::StateRecord;
+
+const NOTIFICATION_START: char = '>';
+const CLIENT: &str = "CLIENT";
+const CLIENT_ADDRESS: &str = "ADDRESS";
+const CLIENT_ENV: &str = "ENV";
+const CLIENT_ENV_END: &str = ">CLIENT:ENV,END";
+const INFO: &str = "INFO";
+const INFO_MESSAGE: &str = "INFOMSG";
+const PASSWORD: &str = "PASSWORD";
+const NEED_OK: &str = "NEED-OK";
+const NEED_STR: &str = "NEED-STR";
+const PK_SIGN: &str = "PK_SIGN";
+const RSA_SIGN: &str = "RSA_SIGN";
+const NEED_CERTIFICATE: &str = "NEED-CERTIFICATE";
+const REMOTE: &str = "REMOTE";
+const PROXY: &str = "PROXY";
+const STATE: &str = "STATE";
+const LOG: &str = "LOG";
+const BYTECOUNT: &str = "BYTECOUNT";
+const BYTECOUNT_CLIENT: &str = "BYTECOUNT_CLI";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+/// The client notifications which are followed by a `>CLIENT:ENV` block
+pub enum ClientEventKind {
+    /// `>CLIENT:CONNECT`, a new client is waiting for authentication
+    Connect,
+    /// `>CLIENT:REAUTH`, an existing client is renegotiating its TLS session
+    Reauth,
+    /// `>CLIENT:ESTABLISHED`, the client was authenticated and its session is up
+    Established,
+    /// `>CLIENT:DISCONNECT`, the client session ended
+    Disconnect,
+}
+
+impl ClientEventKind {
+    fn parse(raw_kind: &str) -> Option<ClientEventKind> {
+        match raw_kind {
+            "CONNECT" => Some(ClientEventKind::Connect),
+            "REAUTH" => Some(ClientEventKind::Reauth),
+            "ESTABLISHED" => Some(ClientEventKind::Established),
+            "DISCONNECT" => Some(ClientEventKind::Disconnect),
+            _ => None,
+        }
+    }
+
+    fn has_kid(self) -> bool {
+        matches!(self, ClientEventKind::Connect | ClientEventKind::Reauth)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Contains a marshalled client notification along with its environment
+pub struct ClientEvent {
+    kind: ClientEventKind,
+    cid: u64,
+    kid: Option<u64>,
+    env: ClientEnv,
+}
+
+impl ClientEvent {
+    pub fn new(kind: ClientEventKind, cid: u64, kid: Option<u64>, env: ClientEnv) -> ClientEvent {
+        ClientEvent {
+            kind,
+            cid,
+            kid,
+            env,
+        }
+    }
+
+    pub fn kind(&self) -> ClientEventKind {
+        self.kind
+    }
+
+    /// Client ID
+    pub fn cid(&self) -> u64 {
+        self.cid
+    }
+
+    /// Key ID, only sent with `CONNECT` and `REAUTH`
+    pub fn kid(&self) -> Option<u64> {
+        self.kid
+    }
+
+    pub fn env(&self) -> &ClientEnv {
+        &self.env
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
+/// A real-time message sent by the openvpn server, i.e. a line starting with `>`
+pub enum Notification {
+    /// `>CLIENT:CONNECT`, `REAUTH`, `ESTABLISHED` or `DISCONNECT` with the client environment
+    Client(ClientEvent),
+    /// `>CLIENT:ADDRESS`, a virtual address was learned for a client
+    ClientAddress(ClientAddress),
+    /// `>INFO`, informational messages such as the management interface banner
+    Info(String),
+    /// `>INFOMSG`, e.g. the web page or challenge text of a pending authentication
+    InfoMessage(InfoMessage),
+    /// `>PASSWORD`, the client needs credentials or reports an authentication outcome
+    Password(PasswordNotification),
+    /// `>NEED-OK`, openvpn waits for a `needok` confirmation, e.g. to insert a token
+    NeedOk(PromptRequest),
+    /// `>NEED-STR`, openvpn waits for a `needstr` string, e.g. a PKCS#11 ID
+    NeedStr(PromptRequest),
+    /// `>PK_SIGN` or the legacy `>RSA_SIGN`, openvpn waits for a signature made with the
+    /// external key
+    Sign(SignRequest),
+    /// `>NEED-CERTIFICATE`, openvpn waits for the client certificate
+    NeedCertificate(CertificateRequest),
+    /// `>REMOTE`, openvpn waits for a `remote` command before trying the next remote
+    Remote(RemoteRequest),
+    /// `>PROXY`, openvpn waits for a `proxy` command before connecting to a remote
+    Proxy(ProxyRequest),
+    /// `>STATE`, openvpn changed state, sent after `state on`
+    State(StateRecord),
+    /// `>LOG`, a new log line, sent after `log on`
+    Log(LogEntry),
+    /// `>BYTECOUNT` or `>BYTECOUNT_CLI`, the traffic counters sent after `bytecount n`
+    ByteCount(ByteCount),
+    /// Any notification without a typed representation
+    Other { kind: String, payload: String },
+}
+
+#[derive(Debug, Default)]
+/// Turns the lines read from the management interface into notifications. Client notifications
+/// span several lines and are only returned once their `>CLIENT:ENV,END` line is read
+pub struct NotificationParser {
+    pending_client: Option<PendingClientEvent>,
+}
+
+#[derive(Debug)]
+struct PendingClientEvent {
+    kind: ClientEventKind,
+    cid: u64,
+    kid: Option<u64>,
+    env_lines: Vec<String>,
+}
+
+impl NotificationParser {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Whether the line is part of a notification rather than a command reply
+    pub fn is_notification(line: &str) -> bool {
+        line.starts_with(NOTIFICATION_START)
+    }
+
+    /// Feeds a notification line to the parser. Returns the notification once complete
+    pub fn push_line(&mut self, line: &str) -> Result<Option<Notification>> {
+        let line = line.trim_end_matches(['\r', '\n']);
+        let (kind, payload) = line
+            .strip_prefix(NOTIFICATION_START)
+            .and_then(|l| l.split_once(':'))
+            .ok_or_else(|| OpenvpnError::MalformedResponse(line.to_string()))?;
+        if let Some(pending) = self.pending_client.as_mut() {
+            if kind != CLIENT || !payload.starts_with(CLIENT_ENV) {
+                self.pending_client = None;
+                return Err(OpenvpnError::MalformedResponse(line.to_string()));
+            }
+            pending.env_lines.push(line.to_string());
+            if line != CLIENT_ENV_END {
+                return Ok(None);
+            }
+            let pending = self.pending_client.take().unwrap();
+            let env = ClientEnv::parse(pending.env_lines.iter().map(String::as_str))?;
+            let event = ClientEvent::new(pending.kind, pending.cid, pending.kid, env);
+            return Ok(Some(Notification::Client(event)));
+        }
+        let notification = match kind {
+            CLIENT => self.parse_client_notification(line, payload)?,
+            INFO => Some(Notification::Info(payload.to_string())),
+            INFO_MESSAGE => Some(Notification::InfoMessage(InfoMessage::parse(payload)?)),
+            PASSWORD => Some(Notification::Password(PasswordNotification::parse(
+                payload,
+            )?)),
+            NEED_OK => Some(Notification::NeedOk(PromptRequest::parse(payload)?)),
+            NEED_STR => Some(Notification::NeedStr(PromptRequest::parse(payload)?)),
+            PK_SIGN => Some(Notification::Sign(SignRequest::parse_pk_sign(payload)?)),
+            RSA_SIGN => Some(Notification::Sign(SignRequest::parse_rsa_sign(payload)?)),
+            NEED_CERTIFICATE => Some(Notification::NeedCertificate(CertificateRequest::new(
+                payload.to_string(),
+            ))),
+            REMOTE => Some(Notification::Remote(RemoteRequest::parse(payload)?)),
+            PROXY => Some(Notification::Proxy(ProxyRequest::parse(payload)?)),
+            STATE => Some(Notification::State(StateRecord::parse(payload)?)),
+            LOG => Some(Notification::Log(LogEntry::parse(payload)?)),
+            BYTECOUNT => Some(Notification::ByteCount(ByteCount::parse(payload)?)),
+            BYTECOUNT_CLIENT => Some(Notification::ByteCount(ByteCount::parse_client(payload)?)),
+            _ => Some(other(kind, payload)),
+        };
+        Ok(notification)
+    }
+
+    fn parse_client_notification(
+        &mut self,
+        line: &str,
+        payload: &str,
+    ) -> Result<Option<Notification>> {
+        let mut fields = payload.split(',');
+        let raw_kind = fields.next().unwrap_or_default();
+        if raw_kind == CLIENT_ADDRESS {
+            return parse_client_address(line, fields.collect()).map(Some);
+        }
+        let kind = match ClientEventKind::parse(raw_kind) {
+            Some(k) => k,
+            None => return Ok(Some(other(CLIENT, payload))),
+        };
+        let fields: Vec<_> = fields.collect();
+        let expected_length = if kind.has_kid() { 2 } else { 1 };
+        if fields.len() < expected_length {
+            return Err(OpenvpnError::MalformedResponse(line.to_string()));
+        }
+        let cid: u64 = fields[0].parse()?;
+        let kid: Option<u64> = if kind.has_kid() {
+            Some(fields[1].parse()?)
+        } else {
+            None
+        };
+        self.pending_client = Some(PendingClientEvent {
+            kind,
+            cid,
+            kid,
+            env_lines: Vec::new(),
+        });
+        Ok(None)
+    }
+}
+
+fn parse_client_address(line: &str, fields: Vec<&str>) -> Result<Notification> {
+    if fields.len() != 3 {
+        return Err(OpenvpnError::MalformedResponse(line.to_string()));
+    }
+    let cid: u64 = fields[0].parse()?;
+    let address = VirtualAddress::parse(fields[1])?;
+    let primary = match fields[2] {
+        "1" => true,
+        "0" => false,
+        _ => return Err(OpenvpnError::MalformedResponse(line.to_string())),
+    };
+    Ok(Notification::ClientAddress(ClientAddress::new(
+        cid, address, primary,
+    )))
+}
+
+fn other(kind: &str, payload: &str) -> Notification {
+    Notification::Other {
+        kind: kind.to_string(),
+        payload: payload.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::SignatureAlgorithm;
+
+    fn parse(line: &str) -> Result<Option<Notification>> {
+        NotificationParser::new().push_line(line)
+    }
+
+    #[test]
+    fn test_client_address_notification() {
+        let notification = parse(">CLIENT:ADDRESS,3,10.8.0.6,1\r\n").unwrap();
+
+        let expected = ClientAddress::new(3, VirtualAddress::parse("10.8.0.6").unwrap(), true);
+        assert_eq!(Some(Notification::ClientAddress(expected)), notification);
+    }
+
+    #[test]
+    fn test_client_address_subnet_not_primary() {
+        let notification = parse(">CLIENT:ADDRESS,3,192.168.1.0/24,0").unwrap();
+
+        match notification {
+            Some(Notification::ClientAddress(address)) => {
+                assert!(!address.primary());
+                assert_eq!("192.168.1.0/24", address.address().to_string());
+            }
+            _ => panic!("was expecting client address"),
+        }
+    }
+
+    #[test]
+    fn test_malformed_client_address() {
+        let result = parse(">CLIENT:ADDRESS,3,10.8.0.6");
+
+        match result {
+            Err(OpenvpnError::MalformedResponse(line)) => {
+                assert_eq!(">CLIENT:ADDRESS,3,10.8.0.6", line)
+            }
+            _ => panic!("was expecting malformed response"),
+        }
+    }
+
+    #[test]
+    fn test_info_and_unknown_notifications() {
+        assert_eq!(
+            Some(Notification::Info(
+                "OpenVPN Management Interface Version 5".to_string()
+            )),
+            parse(">INFO:OpenVPN Management Interface Version 5").unwrap()
+        );
+        assert_eq!(
+            Some(Notification::Other {
+                kind: "HOLD".to_string(),
+                payload: "Waiting for hold release:0".to_string()
+            }),
+            parse(">HOLD:Waiting for hold release:0").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_need_ok_and_need_str_notifications() {
+        assert_eq!(
+            Some(Notification::NeedOk(PromptRequest::new(
+                "token-insertion-request".to_string(),
+                "Please insert your token".to_string()
+            ))),
+            parse(
+                ">NEED-OK:Need 'token-insertion-request' confirmation MSG:Please insert your token"
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            Some(Notification::NeedStr(PromptRequest::new(
+                "pkcs11-id-request".to_string(),
+                "Enter the PKCS#11 ID".to_string()
+            ))),
+            parse(">NEED-STR:Need 'pkcs11-id-request' input MSG:Enter the PKCS#11 ID").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sign_notifications() {
+        match parse(">PK_SIGN:AQID,ECDSA").unwrap() {
+            Some(Notification::Sign(request)) => {
+                assert_eq!(&SignatureAlgorithm::Ecdsa, request.algorithm());
+                assert!(!request.is_legacy());
+            }
+            _ => panic!("was expecting sign request"),
+        }
+        match parse(">RSA_SIGN:AQID").unwrap() {
+            Some(Notification::Sign(request)) => assert!(request.is_legacy()),
+            _ => panic!("was expecting sign request"),
+        }
+    }
+
+    #[test]
+    fn test_need_certificate_notification() {
+        assert_eq!(
+            Some(Notification::NeedCertificate(CertificateRequest::new(
+                "macosx-keychain:subject:o=OpenVPN-TEST".to_string()
+            ))),
+            parse(">NEED-CERTIFICATE:macosx-keychain:subject:o=OpenVPN-TEST").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_remote_notification() {
+        assert_eq!(
+            Some(Notification::Remote(RemoteRequest::new(
+                "vpn.example.com".to_string(),
+                1194,
+                "udp".to_string()
+            ))),
+            parse(">REMOTE:vpn.example.com,1194,udp").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_proxy_notification() {
+        assert_eq!(
+            Some(Notification::Proxy(ProxyRequest::new(
+                2,
+                "TCP".to_string(),
+                "vpn.example.com".to_string()
+            ))),
+            parse(">PROXY:2,TCP,vpn.example.com").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_info_message_notification() {
+        assert_eq!(
+            Some(Notification::InfoMessage(InfoMessage::OpenUrl {
+                url: "https://auth.example.com".to_string()
+            })),
+            parse(">INFOMSG:OPEN_URL:https://auth.example.com").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_state_and_log_notifications() {
+        match parse(">STATE:1547913893,CONNECTED,SUCCESS,10.8.0.6,198.51.100.1,1194,,").unwrap() {
+            Some(Notification::State(state)) => assert_eq!("CONNECTED", state.name()),
+            _ => panic!("was expecting state"),
+        }
+        match parse(">LOG:1547913893,I,Initialization Sequence Completed").unwrap() {
+            Some(Notification::Log(entry)) => {
+                assert_eq!("Initialization Sequence Completed", entry.message())
+            }
+            _ => panic!("was expecting log"),
+        }
+    }
+
+    #[test]
+    fn test_bytecount_notifications() {
+        assert_eq!(
+            Some(Notification::ByteCount(ByteCount::new(
+                Some(4),
+                1024.0,
+                2048.0
+            ))),
+            parse(">BYTECOUNT_CLI:4,1024,2048").unwrap()
+        );
+        assert_eq!(
+            Some(Notification::ByteCount(ByteCount::new(
+                None, 1024.0, 2048.0
+            ))),
+            parse(">BYTECOUNT:1024,2048").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_client_connect_with_env() {
+        let mut parser = NotificationParser::new();
+        let lines = [
+            ">CLIENT:CONNECT,7,1",
+            ">CLIENT:ENV,common_name=test-client",
+            ">CLIENT:ENV,untrusted_ip=192.168.0.3",
+        ];
+        for line in lines.iter() {
+            assert_eq!(None, parser.push_line(line).unwrap());
+        }
+
+        match parser.push_line(">CLIENT:ENV,END").unwrap() {
+            Some(Notification::Client(event)) => {
+                assert_eq!(ClientEventKind::Connect, event.kind());
+                assert_eq!(7, event.cid());
+                assert_eq!(Some(1), event.kid());
+                assert_eq!(Some("test-client"), event.env().common_name());
+            }
+            _ => panic!("was expecting client connect"),
+        }
+    }
+
+    #[test]
+    fn test_client_disconnect_without_kid() {
+        let mut parser = NotificationParser::new();
+        assert_eq!(None, parser.push_line(">CLIENT:DISCONNECT,7").unwrap());
+
+        match parser.push_line(">CLIENT:ENV,END").unwrap() {
+            Some(Notification::Client(event)) => {
+                assert_eq!(ClientEventKind::Disconnect, event.kind());
+                assert_eq!(None, event.kid());
+            }
+            _ => panic!("was expecting client disconnect"),
+        }
+    }
+
+    #[test]
+    fn test_interrupted_client_env() {
+        let mut parser = NotificationParser::new();
+        parser.push_line(">CLIENT:ESTABLISHED,7").unwrap();
+
+        assert!(parser.push_line(">INFO:interrupted").is_err());
+    }
+
+    #[test]
+    fn test_not_a_notification() {
+        assert!(parse("SUCCESS: pid=42").is_err());
+    }
+}
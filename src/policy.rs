@@ -0,0 +1,557 @@
+use crate::address::VirtualAddress;
+use crate::client_env::ClientEnv;
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::notification::ClientEvent;
+use crate::session::Session;
+use crate::tracker::SessionTracker;
+use chrono::prelude::{DateTime, Datelike, FixedOffset, Timelike, Utc, Weekday};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+const DEFAULT_MESSAGE: &str = "access denied";
+const DAYS: [(&str, Weekday); 7] = [
+    ("mon", Weekday::Mon),
+    ("tue", Weekday::Tue),
+    ("wed", Weekday::Wed),
+    ("thu", Weekday::Thu),
+    ("fri", Weekday::Fri),
+    ("sat", Weekday::Sat),
+    ("sun", Weekday::Sun),
+];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PolicyFile {
+    utc_offset: Option<String>,
+    default_message: Option<String>,
+    #[serde(default)]
+    groups: HashMap<String, Vec<String>>,
+    #[serde(default)]
+    profiles: HashMap<String, Vec<String>>,
+    #[serde(default, rename = "rule")]
+    rules: Vec<RuleFile>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    name: String,
+    common_name: Option<String>,
+    username: Option<String>,
+    group: Option<String>,
+    #[serde(default)]
+    source: Vec<String>,
+    #[serde(default)]
+    hours: Vec<String>,
+    #[serde(default)]
+    days: Vec<String>,
+    max_sessions: Option<usize>,
+    action: Action,
+    profile: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Allow,
+    Deny,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    name: String,
+    common_name: Option<String>,
+    username: Option<String>,
+    group: Option<String>,
+    sources: Vec<VirtualAddress>,
+    hours: Vec<(u32, u32)>,
+    days: Vec<Weekday>,
+    max_sessions: Option<usize>,
+    action: Action,
+    profile: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// The outcome of evaluating a policy for a connecting client
+pub enum Decision {
+    /// Authorize the client, pushing the config lines of the rule's profile
+    Allow { rule: String, config: Vec<String> },
+    /// Deny the client with a message sent back to it. No rule is set when nothing matched
+    Deny {
+        rule: Option<String>,
+        message: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+/// Admission rules loaded from a TOML file. Rules are evaluated in order and the first one
+/// matching the client decides, clients matching no rule being denied. For example:
+///
+/// ```toml
+/// utc_offset = "+01:00"
+///
+/// [groups]
+/// staff = ["alice", "*.staff.example.com"]
+///
+/// [profiles]
+/// staff = ["push \"route 10.1.0.0 255.255.0.0\""]
+///
+/// [[rule]]
+/// name = "staff-office-hours"
+/// group = "staff"
+/// source = ["192.168.0.0/16"]
+/// hours = ["08:00-18:00"]
+/// days = ["mon", "tue", "wed", "thu", "fri"]
+/// max_sessions = 2
+/// action = "allow"
+/// profile = "staff"
+///
+/// [[rule]]
+/// name = "contractors"
+/// common_name = "*.contractor.example.com"
+/// action = "deny"
+/// message = "contractor access is suspended"
+/// ```
+///
+/// Rules match on the `common_name` and `username` globs (`*` and `?`), membership of a group
+/// (by common name), the untrusted source address and time-of-day windows in the `utc_offset`
+/// timezone. `max_sessions` caps the concurrent sessions of a common name. The username is sent
+/// by the client and not verified, so `username` rules can only deny
+pub struct Policy {
+    rules: Vec<Rule>,
+    groups: HashMap<String, Vec<String>>,
+    profiles: HashMap<String, Vec<String>>,
+    utc_offset: FixedOffset,
+    default_message: String,
+}
+
+impl Policy {
+    /// Loads the rules file
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Policy> {
+        Policy::parse(&fs::read_to_string(path)?)
+    }
+
+    /// Parses rules in the TOML format
+    pub fn parse(rules: &str) -> Result<Policy> {
+        let file: PolicyFile =
+            toml::from_str(rules).map_err(|e| OpenvpnError::InvalidPolicy(e.to_string()))?;
+        let utc_offset = match file.utc_offset {
+            Some(ref offset) => parse_utc_offset(offset)?,
+            None => FixedOffset::east_opt(0).unwrap(),
+        };
+        let (groups, profiles) = (file.groups, file.profiles);
+        let rules = file
+            .rules
+            .into_iter()
+            .map(|r| compile_rule(r, &groups, &profiles))
+            .collect::<Result<_>>()?;
+        Ok(Policy {
+            rules,
+            groups,
+            profiles,
+            utc_offset,
+            default_message: file
+                .default_message
+                .unwrap_or_else(|| DEFAULT_MESSAGE.to_string()),
+        })
+    }
+
+    /// Decides the outcome for a client environment, given the number of sessions its common
+    /// name already has open
+    pub fn evaluate(
+        &self,
+        env: &ClientEnv,
+        active_sessions: usize,
+        now: DateTime<Utc>,
+    ) -> Decision {
+        let now = now.with_timezone(&self.utc_offset);
+        let rule = match self.rules.iter().find(|r| self.matches(r, env, &now)) {
+            Some(rule) => rule,
+            None => {
+                return Decision::Deny {
+                    rule: None,
+                    message: self.default_message.clone(),
+                }
+            }
+        };
+        let message = || {
+            rule.message
+                .clone()
+                .unwrap_or_else(|| self.default_message.clone())
+        };
+        match rule.action {
+            Action::Deny => Decision::Deny {
+                rule: Some(rule.name.clone()),
+                message: message(),
+            },
+            Action::Allow if rule.max_sessions.is_some_and(|max| active_sessions >= max) => {
+                Decision::Deny {
+                    rule: Some(rule.name.clone()),
+                    message: format!(
+                        "maximum of {} concurrent sessions reached",
+                        rule.max_sessions.unwrap_or_default()
+                    ),
+                }
+            }
+            Action::Allow => Decision::Allow {
+                rule: rule.name.clone(),
+                config: rule
+                    .profile
+                    .as_ref()
+                    .and_then(|p| self.profiles.get(p))
+                    .cloned()
+                    .unwrap_or_default(),
+            },
+        }
+    }
+
+    /// Decides the outcome for a `>CLIENT:CONNECT` or `REAUTH` notification, counting the
+    /// other sessions of the same common name in the tracker
+    pub fn decide(&self, event: &ClientEvent, tracker: &SessionTracker) -> Decision {
+        let active_sessions = match event.env().common_name() {
+            Some(cn) => tracker
+                .sessions()
+                .filter(|s| s.cid() != event.cid() && s.common_name() == Some(cn))
+                .count(),
+            None => 0,
+        };
+        self.evaluate(event.env(), active_sessions, Utc::now())
+    }
+
+    /// Decides the outcome for a client and answers the server with `client-auth` or
+    /// `client-deny`
+    pub fn authorize(
+        &self,
+        session: &mut Session,
+        event: &ClientEvent,
+        tracker: &SessionTracker,
+    ) -> Result<Decision> {
+        let kid = event
+            .kid()
+            .ok_or_else(|| OpenvpnError::InvalidArgument(format!("client {}", event.cid())))?;
+        let decision = self.decide(event, tracker);
+        match decision {
+            Decision::Allow { ref config, .. } => session.client_auth(event.cid(), kid, config)?,
+            Decision::Deny {
+                ref rule,
+                ref message,
+            } => {
+                let reason = match *rule {
+                    Some(ref rule) => format!("denied by rule {}", rule),
+                    None => "no matching rule".to_string(),
+                };
+                session.client_deny(event.cid(), kid, &reason, Some(message))?
+            }
+        }
+        Ok(decision)
+    }
+
+    fn matches<T: Datelike + Timelike>(&self, rule: &Rule, env: &ClientEnv, now: &T) -> bool {
+        if let Some(ref pattern) = rule.common_name {
+            if !env
+                .common_name()
+                .is_some_and(|cn| glob_matches(pattern, cn))
+            {
+                return false;
+            }
+        }
+        if let Some(ref pattern) = rule.username {
+            if !env.username().is_some_and(|u| glob_matches(pattern, u)) {
+                return false;
+            }
+        }
+        if let Some(ref group) = rule.group {
+            let members = &self.groups[group];
+            let is_member = env
+                .common_name()
+                .is_some_and(|cn| members.iter().any(|m| glob_matches(m, cn)));
+            if !is_member {
+                return false;
+            }
+        }
+        if !rule.sources.is_empty() {
+            let source = match env.untrusted_ip() {
+                Some(ip) => ip,
+                None => return false,
+            };
+            if !rule.sources.iter().any(|s| s.contains(&source)) {
+                return false;
+            }
+        }
+        if !rule.days.is_empty() && !rule.days.contains(&now.weekday()) {
+            return false;
+        }
+        if !rule.hours.is_empty() {
+            let minute = now.hour() * 60 + now.minute();
+            let in_window = rule.hours.iter().any(|&(start, end)| {
+                if start <= end {
+                    start <= minute && minute < end
+                } else {
+                    minute >= start || minute < end
+                }
+            });
+            if !in_window {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn compile_rule(
+    rule: RuleFile,
+    groups: &HashMap<String, Vec<String>>,
+    profiles: &HashMap<String, Vec<String>>,
+) -> Result<Rule> {
+    let invalid =
+        |what: &str| OpenvpnError::InvalidPolicy(format!("rule '{}': {}", rule.name, what));
+    if rule.username.is_some() && rule.action == Action::Allow {
+        return Err(invalid(
+            "the username is not verified, username rules can only deny",
+        ));
+    }
+    if let Some(ref group) = rule.group {
+        if !groups.contains_key(group) {
+            return Err(invalid(&format!("unknown group '{}'", group)));
+        }
+    }
+    if let Some(ref profile) = rule.profile {
+        if !profiles.contains_key(profile) {
+            return Err(invalid(&format!("unknown profile '{}'", profile)));
+        }
+    }
+    let sources = rule
+        .source
+        .iter()
+        .map(|s| match VirtualAddress::parse(s) {
+            Ok(VirtualAddress::Other(_)) | Err(_) => {
+                Err(invalid(&format!("invalid source '{}'", s)))
+            }
+            Ok(address) => Ok(address),
+        })
+        .collect::<Result<_>>()?;
+    let hours = rule
+        .hours
+        .iter()
+        .map(|h| parse_window(h).ok_or_else(|| invalid(&format!("invalid hours '{}'", h))))
+        .collect::<Result<_>>()?;
+    let days = rule
+        .days
+        .iter()
+        .map(|d| {
+            DAYS.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(d))
+                .map(|&(_, day)| day)
+                .ok_or_else(|| invalid(&format!("invalid day '{}'", d)))
+        })
+        .collect::<Result<_>>()?;
+    Ok(Rule {
+        sources,
+        hours,
+        days,
+        name: rule.name,
+        common_name: rule.common_name,
+        username: rule.username,
+        group: rule.group,
+        max_sessions: rule.max_sessions,
+        action: rule.action,
+        profile: rule.profile,
+        message: rule.message,
+    })
+}
+
+/// Parses `HH:MM-HH:MM` into minutes since midnight
+fn parse_window(window: &str) -> Option<(u32, u32)> {
+    let (start, end) = window.split_once('-')?;
+    Some((parse_minutes(start)?, parse_minutes(end)?))
+}
+
+fn parse_minutes(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let hours: u32 = hours.parse().ok()?;
+    let minutes: u32 = minutes.parse().ok()?;
+    if hours > 24 || minutes > 59 || (hours == 24 && minutes != 0) {
+        return None;
+    }
+    Some(hours * 60 + minutes)
+}
+
+fn parse_utc_offset(offset: &str) -> Result<FixedOffset> {
+    let invalid = || OpenvpnError::InvalidPolicy(format!("invalid utc_offset '{}'", offset));
+    let (sign, time) = match (offset.get(..1), offset.get(1..)) {
+        (Some("+"), Some(time)) => (1, time),
+        (Some("-"), Some(time)) => (-1, time),
+        _ => return Err(invalid()),
+    };
+    let minutes = parse_minutes(time).ok_or_else(invalid)?;
+    FixedOffset::east_opt(sign * minutes as i32 * 60).ok_or_else(invalid)
+}
+
+/// Matches `*` (any run of characters) and `?` (any single character)
+fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    const RULES: &str = r#"
+utc_offset = "+01:00"
+default_message = "not on the list"
+
+[groups]
+staff = ["alice", "*.staff.example.com"]
+
+[profiles]
+staff = ["push \"route 10.1.0.0 255.255.0.0\""]
+
+[[rule]]
+name = "banned"
+username = "mallory"
+action = "deny"
+message = "account suspended"
+
+[[rule]]
+name = "staff-office-hours"
+group = "staff"
+source = ["192.168.0.0/16", "10.0.0.1"]
+hours = ["08:00-18:00"]
+days = ["mon", "tue", "wed", "thu", "fri"]
+max_sessions = 2
+action = "allow"
+profile = "staff"
+"#;
+
+    fn env(username: &str, common_name: &str, ip: &str) -> ClientEnv {
+        let lines = [
+            format!(">CLIENT:ENV,username={}", username),
+            format!(">CLIENT:ENV,common_name={}", common_name),
+            format!(">CLIENT:ENV,untrusted_ip={}", ip),
+        ];
+        ClientEnv::parse(lines.iter().map(String::as_str)).unwrap()
+    }
+
+    fn monday_at(hour: u32) -> DateTime<Utc> {
+        // 2019-01-07 was a Monday, the policy runs at UTC+1
+        Utc.with_ymd_and_hms(2019, 1, 7, hour - 1, 30, 0).unwrap()
+    }
+
+    #[test]
+    fn test_allow_with_profile() {
+        let policy = Policy::parse(RULES).unwrap();
+
+        let decision = policy.evaluate(
+            &env("bob", "bob.staff.example.com", "192.168.4.2"),
+            0,
+            monday_at(9),
+        );
+
+        assert_eq!(
+            Decision::Allow {
+                rule: "staff-office-hours".to_string(),
+                config: vec!["push \"route 10.1.0.0 255.255.0.0\"".to_string()]
+            },
+            decision
+        );
+    }
+
+    #[test]
+    fn test_deny_rule_message() {
+        let policy = Policy::parse(RULES).unwrap();
+
+        let decision = policy.evaluate(&env("mallory", "alice", "192.168.4.2"), 0, monday_at(9));
+
+        assert_eq!(
+            Decision::Deny {
+                rule: Some("banned".to_string()),
+                message: "account suspended".to_string()
+            },
+            decision
+        );
+    }
+
+    #[test]
+    fn test_default_deny_outside_conditions() {
+        let policy = Policy::parse(RULES).unwrap();
+        let default_deny = Decision::Deny {
+            rule: None,
+            message: "not on the list".to_string(),
+        };
+
+        let after_hours = policy.evaluate(&env("alice", "alice", "192.168.4.2"), 0, monday_at(19));
+        let wrong_source = policy.evaluate(&env("alice", "alice", "172.16.0.1"), 0, monday_at(9));
+        let not_staff = policy.evaluate(&env("eve", "laptop", "192.168.4.2"), 0, monday_at(9));
+        let claimed_username =
+            policy.evaluate(&env("alice", "laptop", "192.168.4.2"), 0, monday_at(9));
+
+        assert_eq!(default_deny, after_hours);
+        assert_eq!(default_deny, wrong_source);
+        assert_eq!(default_deny, not_staff);
+        assert_eq!(default_deny, claimed_username);
+    }
+
+    #[test]
+    fn test_max_sessions() {
+        let policy = Policy::parse(RULES).unwrap();
+
+        let decision = policy.evaluate(&env("bob", "alice", "10.0.0.1"), 2, monday_at(9));
+
+        assert_eq!(
+            Decision::Deny {
+                rule: Some("staff-office-hours".to_string()),
+                message: "maximum of 2 concurrent sessions reached".to_string()
+            },
+            decision
+        );
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let unknown_group = "[[rule]]\nname = \"r\"\ngroup = \"nobody\"\naction = \"allow\"";
+        let bad_hours = "[[rule]]\nname = \"r\"\nhours = [\"8-18\"]\naction = \"allow\"";
+        let bad_action = "[[rule]]\nname = \"r\"\naction = \"maybe\"";
+        let username_allow = "[[rule]]\nname = \"r\"\nusername = \"admin\"\naction = \"allow\"";
+
+        for rules in &[unknown_group, bad_hours, bad_action, username_allow] {
+            assert!(matches!(
+                Policy::parse(rules),
+                Err(OpenvpnError::InvalidPolicy(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_glob_matches() {
+        assert!(glob_matches("*.staff.example.com", "bob.staff.example.com"));
+        assert!(glob_matches("user-??", "user-42"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("a*b*c", "aXXbYYbc"));
+        assert!(!glob_matches("*.staff.example.com", "staff.example.com"));
+        assert!(!glob_matches("user-??", "user-4"));
+    }
+}
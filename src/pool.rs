@@ -0,0 +1,655 @@
+use crate::client::Client;
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::get_utc_start_time;
+use crate::notification::{ClientEvent, ClientEventKind, Notification};
+use crate::Status;
+use chrono::prelude::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+const NO_ADDRESS: &str = "-";
+
+#[derive(Clone, Debug, PartialEq)]
+/// A range of tunnel addresses handed out to the clients of one group. Addresses are pushed with
+/// `ifconfig-push` and `ifconfig-ipv6-push`, which assumes the server runs with `topology subnet`
+pub struct AddressPool {
+    ipv4: Option<(u32, u32, u8)>,
+    ipv6: Option<(u128, u128, u8)>,
+}
+
+impl AddressPool {
+    pub fn new() -> Self {
+        AddressPool {
+            ipv4: None,
+            ipv6: None,
+        }
+    }
+
+    /// Hands out IPv4 addresses from `first` to `last` inclusive, within a subnet of the given
+    /// prefix length. The range must be inside a single subnet and exclude the server's own
+    /// address
+    pub fn ipv4(mut self, first: Ipv4Addr, last: Ipv4Addr, prefix_len: u8) -> Result<Self> {
+        let mask = u32::MAX
+            .checked_shl(32 - u32::from(prefix_len.min(32)))
+            .unwrap_or(0);
+        if first > last || prefix_len > 32 || u32::from(first) & mask != u32::from(last) & mask {
+            return Err(OpenvpnError::InvalidArgument(format!(
+                "{}-{}/{}",
+                first, last, prefix_len
+            )));
+        }
+        self.ipv4 = Some((first.into(), last.into(), prefix_len));
+        Ok(self)
+    }
+
+    /// Hands out IPv6 addresses from `first` to `last` inclusive, within a subnet of the given
+    /// prefix length. The range must be inside a single subnet and exclude the server's own
+    /// address
+    pub fn ipv6(mut self, first: Ipv6Addr, last: Ipv6Addr, prefix_len: u8) -> Result<Self> {
+        let mask = u128::MAX
+            .checked_shl(128 - u32::from(prefix_len.min(128)))
+            .unwrap_or(0);
+        if first > last || prefix_len > 128 || u128::from(first) & mask != u128::from(last) & mask {
+            return Err(OpenvpnError::InvalidArgument(format!(
+                "{}-{}/{}",
+                first, last, prefix_len
+            )));
+        }
+        self.ipv6 = Some((first.into(), last.into(), prefix_len));
+        Ok(self)
+    }
+
+    fn contains_ipv4(&self, ip: Ipv4Addr) -> bool {
+        let ip = u32::from(ip);
+        self.ipv4
+            .is_some_and(|(first, last, _)| first <= ip && ip <= last)
+    }
+
+    fn contains_ipv6(&self, ip: Ipv6Addr) -> bool {
+        let ip = u128::from(ip);
+        self.ipv6
+            .is_some_and(|(first, last, _)| first <= ip && ip <= last)
+    }
+
+    /// The config lines assigning the lease addresses to the client
+    pub fn client_config(&self, lease: &Lease) -> Vec<String> {
+        let mut config = Vec::new();
+        if let (Some(ip), Some((_, _, prefix_len))) = (lease.ipv4, self.ipv4) {
+            let netmask = Ipv4Addr::from(
+                u32::MAX
+                    .checked_shl(32 - u32::from(prefix_len))
+                    .unwrap_or(0),
+            );
+            config.push(format!("ifconfig-push {} {}", ip, netmask));
+        }
+        if let (Some(ip), Some((_, _, prefix_len))) = (lease.ipv6, self.ipv6) {
+            config.push(format!("ifconfig-ipv6-push {}/{}", ip, prefix_len));
+        }
+        config
+    }
+}
+
+impl Default for AddressPool {
+    fn default() -> Self {
+        AddressPool::new()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// Addresses reserved for a common name. A lease outlives the session that created it so the
+/// client gets the same addresses when it reconnects
+pub struct Lease {
+    group: String,
+    common_name: String,
+    ipv4: Option<Ipv4Addr>,
+    ipv6: Option<Ipv6Addr>,
+    cid: Option<u64>,
+    updated: DateTime<Utc>,
+}
+
+impl Lease {
+    pub fn group(&self) -> &str {
+        &self.group
+    }
+
+    /// Common Name
+    pub fn common_name(&self) -> &str {
+        &self.common_name
+    }
+
+    pub fn ipv4(&self) -> Option<Ipv4Addr> {
+        self.ipv4
+    }
+
+    pub fn ipv6(&self) -> Option<Ipv6Addr> {
+        self.ipv6
+    }
+
+    /// Client ID of the session using the lease, if the client is connected
+    pub fn cid(&self) -> Option<u64> {
+        self.cid
+    }
+
+    /// Date time the lease was last allocated or released
+    pub fn updated(&self) -> &DateTime<Utc> {
+        &self.updated
+    }
+
+    fn to_line(&self) -> Result<String> {
+        for field in &[&self.group, &self.common_name] {
+            if field.is_empty() || field.contains(['\t', '\r', '\n']) {
+                return Err(OpenvpnError::InvalidArgument(field.to_string()));
+            }
+        }
+        Ok(format!(
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            self.group,
+            self.common_name,
+            self.ipv4
+                .map_or(NO_ADDRESS.to_string(), |ip| ip.to_string()),
+            self.ipv6
+                .map_or(NO_ADDRESS.to_string(), |ip| ip.to_string()),
+            self.updated.timestamp(),
+            self.cid
+                .map_or(NO_ADDRESS.to_string(), |cid| cid.to_string())
+        ))
+    }
+
+    /// Parses a lease line. Lines written before the client ID was persisted have 5 fields
+    fn from_line(line: &str) -> Result<Lease> {
+        let vec: Vec<_> = line.split('\t').collect();
+        if vec.len() != 5 && vec.len() != 6 {
+            return Err(OpenvpnError::MalformedResponse(line.to_string()));
+        }
+        let cid = match vec.get(5) {
+            None | Some(&NO_ADDRESS) => None,
+            Some(cid) => Some(cid.parse()?),
+        };
+        let ipv4 = match vec[2] {
+            NO_ADDRESS => None,
+            ip => Some(ip.parse()?),
+        };
+        let ipv6 = match vec[3] {
+            NO_ADDRESS => None,
+            ip => Some(ip.parse()?),
+        };
+        Ok(Lease {
+            group: vec[0].to_string(),
+            common_name: vec[1].to_string(),
+            ipv4,
+            ipv6,
+            cid,
+            updated: get_utc_start_time(vec[4].parse()?)?,
+        })
+    }
+}
+
+#[derive(Debug, Default)]
+/// Assigns tunnel addresses from per-group pools when clients connect, as an alternative to
+/// the server's `ifconfig-pool`. A common name keeps its addresses across reconnects unless the
+/// pool runs out, in which case the least recently released lease is reclaimed. Leases are
+/// persisted to the lease file, if any, on every change
+pub struct PoolAllocator {
+    pools: HashMap<String, AddressPool>,
+    leases: Vec<Lease>,
+    lease_file: Option<PathBuf>,
+}
+
+impl PoolAllocator {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Adds the pool for a group of clients
+    pub fn add_pool(&mut self, group: &str, pool: AddressPool) -> &mut PoolAllocator {
+        self.pools.insert(group.to_owned(), pool);
+        self
+    }
+
+    /// Persists the leases to the given file, loading the leases it already holds. Leases
+    /// loaded from the file stay bound to the client ID they had, so they are not reclaimed
+    /// while openvpn may still have the client connected; call [`PoolAllocator::reconcile`]
+    /// with the current status to release the leases of the clients gone meanwhile
+    pub fn lease_file<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut PoolAllocator> {
+        let path = path.as_ref().to_path_buf();
+        self.leases = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter(|l| !l.is_empty())
+                .map(Lease::from_line)
+                .collect::<Result<_>>()?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        self.lease_file = Some(path);
+        Ok(self)
+    }
+
+    /// All the known leases, including the ones of disconnected clients
+    pub fn leases(&self) -> &[Lease] {
+        &self.leases
+    }
+
+    /// The lease used by a connected client
+    pub fn lease(&self, cid: u64) -> Option<&Lease> {
+        self.leases.iter().find(|l| l.cid == Some(cid))
+    }
+
+    /// Allocates addresses from the group's pool for a connecting client. Returns the config
+    /// lines to send with `client-auth`
+    pub fn connect(&mut self, event: &ClientEvent, group: &str) -> Result<Vec<String>> {
+        let common_name = event
+            .env()
+            .common_name()
+            .or_else(|| event.env().username())
+            .ok_or_else(|| OpenvpnError::InvalidArgument(format!("client {}", event.cid())))?
+            .to_owned();
+        let lease = self.allocate(group, event.cid(), &common_name)?;
+        Ok(self.pools[group].client_config(&lease))
+    }
+
+    /// Matches the leases with the clients of the status, e.g. after loading the lease file.
+    /// A lease stays bound when its client is listed with the same client ID and, if the
+    /// status lists it, the same tunnel address. The other leases are released, unless a
+    /// listed client uses their address, in which case they are bound to that client
+    pub fn reconcile(&mut self, status: &Status) -> Result<()> {
+        let now = Utc::now();
+        let mut changed = false;
+        for lease in &mut self.leases {
+            let uses_lease = |client: &&Client| {
+                client.virtual_address().map_or(true, |address| {
+                    lease.ipv4.is_some_and(|ip| address == ip)
+                        || lease.ipv6.is_some_and(|ip| address == ip)
+                })
+            };
+            let bound = lease.cid.is_some()
+                && status
+                    .clients()
+                    .iter()
+                    .filter(|client| client.cid() == lease.cid)
+                    .any(|client| uses_lease(&client));
+            if bound {
+                continue;
+            }
+            let cid = status
+                .clients()
+                .iter()
+                .filter(|client| client.virtual_address().is_some())
+                .find(|client| uses_lease(client))
+                .and_then(|client| client.cid());
+            if lease.cid != cid {
+                lease.cid = cid;
+                lease.updated = now;
+                changed = true;
+            }
+        }
+        if changed {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Releases the addresses of disconnected clients
+    pub fn apply(&mut self, notification: &Notification) -> Result<Option<Lease>> {
+        match *notification {
+            Notification::Client(ref event) if event.kind() == ClientEventKind::Disconnect => {
+                self.release(event.cid())
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Binds a lease from the group's pool to the client, reusing the common name's previous
+    /// lease when it is free. The leases are left as they were if the lease file cannot be
+    /// written
+    pub fn allocate(&mut self, group: &str, cid: u64, common_name: &str) -> Result<Lease> {
+        let previous = self.leases.clone();
+        let result = self
+            .allocate_without_saving(group, cid, common_name)
+            .and_then(|lease| self.save().map(|_| lease));
+        if result.is_err() {
+            self.leases = previous;
+        }
+        result
+    }
+
+    fn allocate_without_saving(
+        &mut self,
+        group: &str,
+        cid: u64,
+        common_name: &str,
+    ) -> Result<Lease> {
+        let pool = self
+            .pools
+            .get(group)
+            .cloned()
+            .ok_or_else(|| OpenvpnError::InvalidArgument(group.to_string()))?;
+        if let Some(lease) = self.lease(cid) {
+            if lease.group == group && lease.common_name == common_name {
+                return Ok(lease.clone());
+            }
+        }
+        self.release_without_saving(cid);
+        let now = Utc::now();
+        let sticky = self.leases.iter().position(|l| {
+            l.cid.is_none()
+                && l.group == group
+                && l.common_name == common_name
+                && l.ipv4.map_or(true, |ip| pool.contains_ipv4(ip))
+                && l.ipv6.map_or(true, |ip| pool.contains_ipv6(ip))
+        });
+        let index = match sticky {
+            Some(index) => index,
+            None => {
+                let (ipv4, ipv6) = self.free_addresses(group, &pool)?;
+                self.leases.push(Lease {
+                    group: group.to_owned(),
+                    common_name: common_name.to_owned(),
+                    ipv4,
+                    ipv6,
+                    cid: None,
+                    updated: now,
+                });
+                self.leases.len() - 1
+            }
+        };
+        let lease = &mut self.leases[index];
+        lease.cid = Some(cid);
+        lease.updated = now;
+        Ok(lease.clone())
+    }
+
+    /// Unbinds the lease of a disconnected client, keeping it reserved for its common name
+    pub fn release(&mut self, cid: u64) -> Result<Option<Lease>> {
+        let lease = self.release_without_saving(cid);
+        if lease.is_some() {
+            self.save()?;
+        }
+        Ok(lease)
+    }
+
+    fn release_without_saving(&mut self, cid: u64) -> Option<Lease> {
+        let lease = self.leases.iter_mut().find(|l| l.cid == Some(cid))?;
+        lease.cid = None;
+        lease.updated = Utc::now();
+        Some(lease.clone())
+    }
+
+    fn free_addresses(
+        &mut self,
+        group: &str,
+        pool: &AddressPool,
+    ) -> Result<(Option<Ipv4Addr>, Option<Ipv6Addr>)> {
+        loop {
+            let used_ipv4: HashSet<u32> = self
+                .leases
+                .iter()
+                .filter_map(|l| l.ipv4)
+                .map(u32::from)
+                .collect();
+            let used_ipv6: HashSet<u128> = self
+                .leases
+                .iter()
+                .filter_map(|l| l.ipv6)
+                .map(u128::from)
+                .collect();
+            let ipv4 = pool
+                .ipv4
+                .map(|(first, last, _)| (first..=last).find(|ip| !used_ipv4.contains(ip)));
+            let ipv6 = pool
+                .ipv6
+                .map(|(first, last, _)| (first..=last).find(|ip| !used_ipv6.contains(ip)));
+            if ipv4 != Some(None) && ipv6 != Some(None) {
+                return Ok((
+                    ipv4.flatten().map(Ipv4Addr::from),
+                    ipv6.flatten().map(Ipv6Addr::from),
+                ));
+            }
+            // only the leases holding an address of the exhausted family are worth reclaiming
+            let frees_address = |l: &Lease| {
+                (ipv4 == Some(None) && l.ipv4.is_some_and(|ip| pool.contains_ipv4(ip)))
+                    || (ipv6 == Some(None) && l.ipv6.is_some_and(|ip| pool.contains_ipv6(ip)))
+            };
+            let reclaimable = self
+                .leases
+                .iter()
+                .enumerate()
+                .filter(|(_, l)| l.cid.is_none() && l.group == group && frees_address(l))
+                .min_by_key(|(_, l)| l.updated)
+                .map(|(index, _)| index);
+            match reclaimable {
+                Some(index) => {
+                    self.leases.remove(index);
+                }
+                None => return Err(OpenvpnError::PoolExhausted(group.to_string())),
+            }
+        }
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = match self.lease_file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let mut contents = String::new();
+        for lease in &self.leases {
+            contents.push_str(&lease.to_line()?);
+            contents.push('\n');
+        }
+        let mut temporary_path = path.clone().into_os_string();
+        temporary_path.push(".tmp");
+        let mut file = fs::File::create(&temporary_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client_env::ClientEnv;
+    use crate::fixtures;
+    use std::env;
+
+    fn pool() -> AddressPool {
+        AddressPool::new()
+            .ipv4(
+                "10.8.0.10".parse().unwrap(),
+                "10.8.0.11".parse().unwrap(),
+                24,
+            )
+            .unwrap()
+            .ipv6("fd00::10".parse().unwrap(), "fd00::ff".parse().unwrap(), 64)
+            .unwrap()
+    }
+
+    fn allocator() -> PoolAllocator {
+        let mut allocator = PoolAllocator::new();
+        allocator.add_pool("staff", pool());
+        allocator
+    }
+
+    fn client_event(kind: ClientEventKind, cid: u64, common_name: &str) -> ClientEvent {
+        let env_line = format!(">CLIENT:ENV,common_name={}", common_name);
+        let env = ClientEnv::parse(vec![env_line.as_str()]).unwrap();
+        ClientEvent::new(kind, cid, Some(0), env)
+    }
+
+    #[test]
+    fn test_connect_pushes_ipv4_and_ipv6_config() {
+        let mut allocator = allocator();
+
+        let config = allocator
+            .connect(&client_event(ClientEventKind::Connect, 1, "alice"), "staff")
+            .unwrap();
+
+        assert_eq!(
+            vec![
+                "ifconfig-push 10.8.0.10 255.255.255.0".to_string(),
+                "ifconfig-ipv6-push fd00::10/64".to_string()
+            ],
+            config
+        );
+    }
+
+    #[test]
+    fn test_sticky_lease_after_disconnect() {
+        let mut allocator = allocator();
+        allocator.allocate("staff", 1, "alice").unwrap();
+        allocator.allocate("staff", 2, "bob").unwrap();
+
+        let disconnect = client_event(ClientEventKind::Disconnect, 1, "alice");
+        let released = allocator
+            .apply(&Notification::Client(disconnect))
+            .unwrap()
+            .unwrap();
+        assert_eq!(None, released.cid());
+        let lease = allocator.allocate("staff", 3, "alice").unwrap();
+
+        assert_eq!(Some("10.8.0.10".parse().unwrap()), lease.ipv4());
+        assert_eq!(Some(3), lease.cid());
+    }
+
+    #[test]
+    fn test_exhausted_pool_reclaims_released_leases() {
+        let mut allocator = allocator();
+        allocator.allocate("staff", 1, "alice").unwrap();
+        allocator.allocate("staff", 2, "bob").unwrap();
+
+        let result = allocator.allocate("staff", 3, "carol");
+        assert!(matches!(result, Err(OpenvpnError::PoolExhausted(_))));
+
+        allocator.release(2).unwrap();
+        let lease = allocator.allocate("staff", 3, "carol").unwrap();
+        assert_eq!(Some("10.8.0.11".parse().unwrap()), lease.ipv4());
+        assert_eq!(2, allocator.leases().len());
+    }
+
+    #[test]
+    fn test_exhausted_family_reclaims_only_its_leases() {
+        let path = env::temp_dir().join(format!(
+            "openvpn-management-leases-family-{}",
+            std::process::id()
+        ));
+        fs::write(
+            &path,
+            "staff\tolder\t-\tfd00::10\t100\t-\nstaff\talice\t10.8.0.10\tfd00::11\t200\t-\n",
+        )
+        .unwrap();
+        let mut allocator = PoolAllocator::new();
+        allocator.add_pool(
+            "staff",
+            AddressPool::new()
+                .ipv4(
+                    "10.8.0.10".parse().unwrap(),
+                    "10.8.0.10".parse().unwrap(),
+                    24,
+                )
+                .unwrap()
+                .ipv6("fd00::10".parse().unwrap(), "fd00::ff".parse().unwrap(), 64)
+                .unwrap(),
+        );
+        allocator.lease_file(&path).unwrap();
+
+        let lease = allocator.allocate("staff", 1, "carol").unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(Some("10.8.0.10".parse().unwrap()), lease.ipv4());
+        assert_eq!(2, allocator.leases().len());
+        assert_eq!("older", allocator.leases()[0].common_name());
+    }
+
+    #[test]
+    fn test_range_outside_prefix() {
+        let result = AddressPool::new().ipv4(
+            "10.8.0.250".parse().unwrap(),
+            "10.8.1.5".parse().unwrap(),
+            24,
+        );
+        assert!(matches!(result, Err(OpenvpnError::InvalidArgument(_))));
+
+        let result =
+            AddressPool::new().ipv6("fd00::10".parse().unwrap(), "fd01::10".parse().unwrap(), 64);
+        assert!(matches!(result, Err(OpenvpnError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_unknown_group() {
+        let result = allocator().allocate("contractors", 1, "alice");
+
+        assert!(matches!(result, Err(OpenvpnError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn test_leases_survive_restart() {
+        let path =
+            env::temp_dir().join(format!("openvpn-management-leases-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let mut allocator = allocator();
+            allocator.lease_file(&path).unwrap();
+            allocator.allocate("staff", 1, "alice").unwrap();
+            allocator.allocate("staff", 2, "bob").unwrap();
+            allocator.release(2).unwrap();
+        }
+
+        let mut allocator = allocator();
+        allocator.lease_file(&path).unwrap();
+        assert_eq!(2, allocator.leases().len());
+        assert_eq!(Some(1), allocator.leases()[0].cid());
+        assert_eq!(None, allocator.leases()[1].cid());
+        let lease = allocator.allocate("staff", 7, "bob").unwrap();
+        let result = allocator.allocate("staff", 8, "carol");
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(OpenvpnError::PoolExhausted(_))));
+
+        assert_eq!(Some("10.8.0.11".parse().unwrap()), lease.ipv4());
+        assert_eq!(Some("fd00::11".parse().unwrap()), lease.ipv6());
+    }
+
+    #[test]
+    fn test_reconcile_with_status() {
+        let mut allocator = allocator();
+        allocator.allocate("staff", 1, "alice").unwrap();
+        allocator.allocate("staff", 2, "bob").unwrap();
+        let client = |cid, address: &str| {
+            fixtures::client("client", "1.2.3.4", 1_700_000_000, 0.0, 0.0)
+                .with_cid(cid)
+                .with_virtual_address(address.parse().unwrap())
+        };
+        let status = fixtures::status(vec![client(1, "10.8.0.10"), client(5, "10.8.0.11")]);
+
+        allocator.reconcile(&status).unwrap();
+
+        assert_eq!(Some(1), allocator.leases()[0].cid());
+        assert_eq!(Some(5), allocator.leases()[1].cid());
+        let status = fixtures::status(vec![client(1, "10.8.0.11")]);
+        allocator.reconcile(&status).unwrap();
+        assert_eq!(None, allocator.leases()[0].cid());
+        assert_eq!(Some(1), allocator.leases()[1].cid());
+    }
+
+    #[test]
+    fn test_failed_save_keeps_leases() {
+        let path = env::temp_dir().join(format!(
+            "openvpn-management-leases-rollback-{}",
+            std::process::id()
+        ));
+        let mut allocator = allocator();
+        allocator.lease_file(&path).unwrap();
+        allocator.allocate("staff", 1, "alice").unwrap();
+
+        let result = allocator.allocate("staff", 2, "bad\tname");
+        fs::remove_file(&path).unwrap();
+
+        assert!(matches!(result, Err(OpenvpnError::InvalidArgument(_))));
+        assert_eq!(1, allocator.leases().len());
+        assert_eq!(None, allocator.lease(2));
+    }
+}
@@ -0,0 +1,81 @@
+use crate::credentials::split_realm;
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+
+const NEED: &str = "Need ";
+const MESSAGE: &str = "MSG:";
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A question asked by openvpn in a `>NEED-OK` or `>NEED-STR` notification, e.g.
+/// `Need 'token-insertion-request' confirmation MSG:Please insert your cryptographic token`
+pub struct PromptRequest {
+    name: String,
+    message: String,
+}
+
+impl PromptRequest {
+    pub fn new(name: String, message: String) -> PromptRequest {
+        PromptRequest { name, message }
+    }
+
+    /// Parses the payload of a `>NEED-OK` or `>NEED-STR` notification
+    pub fn parse(payload: &str) -> Result<PromptRequest> {
+        let malformed = || OpenvpnError::MalformedResponse(payload.to_string());
+        let rest = payload.strip_prefix(NEED).ok_or_else(malformed)?;
+        let (name, rest) = split_realm(rest).ok_or_else(malformed)?;
+        let message = rest
+            .find(MESSAGE)
+            .map(|start| &rest[start + MESSAGE.len()..])
+            .unwrap_or_default();
+        Ok(PromptRequest::new(name.to_string(), message.to_string()))
+    }
+
+    /// Identifies the question in the reply, e.g. `token-insertion-request` or `pkcs11-id-request`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The text to show the user
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Answers the questions openvpn asks over its management interface
+pub trait PromptHandler {
+    /// Answers a `>NEED-OK` request, `true` to confirm and `false` to cancel
+    fn confirm(&mut self, request: &PromptRequest) -> Result<bool>;
+
+    /// Answers a `>NEED-STR` request with the string openvpn asks for
+    fn input(&mut self, request: &PromptRequest) -> Result<String>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_confirmation_request() {
+        let request = PromptRequest::parse(
+            "Need 'token-insertion-request' confirmation MSG:Please insert your cryptographic token",
+        )
+        .unwrap();
+
+        assert_eq!("token-insertion-request", request.name());
+        assert_eq!("Please insert your cryptographic token", request.message());
+    }
+
+    #[test]
+    fn test_parse_input_request_without_message() {
+        let request = PromptRequest::parse("Need 'pkcs11-id-request' input").unwrap();
+
+        assert_eq!("pkcs11-id-request", request.name());
+        assert_eq!("", request.message());
+    }
+
+    #[test]
+    fn test_parse_malformed_request() {
+        assert!(PromptRequest::parse("Need token-insertion-request confirmation").is_err());
+        assert!(PromptRequest::parse("Want 'name' input").is_err());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::command::quote;
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A remote openvpn asks how to reach, sent in a `>PROXY:index,proto,host` notification with
+/// `--management-query-proxy`
+pub struct ProxyRequest {
+    index: u32,
+    protocol: String,
+    host: String,
+}
+
+impl ProxyRequest {
+    pub fn new(index: u32, protocol: String, host: String) -> ProxyRequest {
+        ProxyRequest {
+            index,
+            protocol,
+            host,
+        }
+    }
+
+    /// Parses the payload of a `>PROXY` notification
+    pub fn parse(payload: &str) -> Result<ProxyRequest> {
+        let fields: Vec<_> = payload.splitn(3, ',').collect();
+        if fields.len() != 3 {
+            return Err(OpenvpnError::MalformedResponse(payload.to_string()));
+        }
+        Ok(ProxyRequest::new(
+            fields[0].parse()?,
+            fields[1].to_string(),
+            fields[2].to_string(),
+        ))
+    }
+
+    /// The position of the remote in the connection list, starting at 1
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    /// The transport protocol, e.g. `UDP` or `TCP`
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// The remote host to reach
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// The answer to a [`ProxyRequest`]
+pub enum ProxyAction {
+    /// Connects to the remote directly
+    None,
+    /// Connects through an HTTP proxy. `non_cleartext_only` (`nct`) forbids basic
+    /// authentication with the proxy
+    Http {
+        host: String,
+        port: u16,
+        non_cleartext_only: bool,
+    },
+    /// Connects through a SOCKS proxy
+    Socks { host: String, port: u16 },
+}
+
+impl ProxyAction {
+    /// The `proxy` command sending this action
+    pub(crate) fn command(&self) -> Result<String> {
+        match *self {
+            ProxyAction::None => Ok("proxy NONE".to_string()),
+            ProxyAction::Http {
+                ref host,
+                port,
+                non_cleartext_only,
+            } => {
+                let mut command = format!("proxy HTTP {} {}", quote(host)?, port);
+                if non_cleartext_only {
+                    command.push_str(" nct");
+                }
+                Ok(command)
+            }
+            ProxyAction::Socks { ref host, port } => {
+                Ok(format!("proxy SOCKS {} {}", quote(host)?, port))
+            }
+        }
+    }
+}
+
+/// Chooses how an openvpn client reaches its remotes
+pub trait ProxyPolicy {
+    /// Returns the proxy to use for the remote, or [`ProxyAction::None`] to connect directly
+    fn proxy(&mut self, request: &ProxyRequest) -> Result<ProxyAction>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_proxy_request() {
+        let request = ProxyRequest::parse("1,TCP,vpn.example.com").unwrap();
+
+        assert_eq!(
+            ProxyRequest::new(1, "TCP".to_string(), "vpn.example.com".to_string()),
+            request
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed_proxy_request() {
+        assert!(ProxyRequest::parse("1,TCP").is_err());
+        assert!(ProxyRequest::parse("first,TCP,vpn.example.com").is_err());
+    }
+
+    #[test]
+    fn test_proxy_commands() {
+        assert_eq!("proxy NONE", ProxyAction::None.command().unwrap());
+        assert_eq!(
+            r#"proxy HTTP "proxy.corp" 3128 nct"#,
+            ProxyAction::Http {
+                host: "proxy.corp".to_string(),
+                port: 3128,
+                non_cleartext_only: true
+            }
+            .command()
+            .unwrap()
+        );
+        assert_eq!(
+            r#"proxy SOCKS "127.0.0.1" 1080"#,
+            ProxyAction::Socks {
+                host: "127.0.0.1".to_string(),
+                port: 1080
+            }
+            .command()
+            .unwrap()
+        );
+    }
+}
@@ -0,0 +1,793 @@
+use crate::client_env::ClientEnv;
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::notification::{ClientEventKind, Notification};
+use crate::session::Session;
+use crate::{get_utc_start_time, Status};
+use chrono::prelude::{DateTime, Datelike, FixedOffset, NaiveDate, TimeZone, Utc};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The fraction of the allowance after which a warning is sent, by default
+const DEFAULT_WARNING: f64 = 0.9;
+/// The `client-kill` message of the clients over their allowance, which stops them from
+/// reconnecting on their own
+const KILL_MESSAGE: &str = "HALT";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum QuotaPeriod {
+    /// Resets at midnight
+    Daily,
+    /// Resets at midnight on the first day of the month
+    Monthly,
+}
+
+impl QuotaPeriod {
+    /// The start of the period the date falls in
+    fn start(&self, now: &DateTime<Utc>, offset: &FixedOffset) -> DateTime<Utc> {
+        let local = now.with_timezone(offset).date_naive();
+        let day = match *self {
+            QuotaPeriod::Daily => local,
+            QuotaPeriod::Monthly => local.with_day(1).unwrap_or(local),
+        };
+        local_midnight(day, offset)
+    }
+
+    /// The start of the next period
+    fn end(&self, now: &DateTime<Utc>, offset: &FixedOffset) -> DateTime<Utc> {
+        let local = now.with_timezone(offset).date_naive();
+        let day = match *self {
+            QuotaPeriod::Daily => local.succ_opt(),
+            QuotaPeriod::Monthly if local.month() == 12 => {
+                NaiveDate::from_ymd_opt(local.year() + 1, 1, 1)
+            }
+            QuotaPeriod::Monthly => NaiveDate::from_ymd_opt(local.year(), local.month() + 1, 1),
+        };
+        day.map_or(DateTime::<Utc>::MAX_UTC, |day| local_midnight(day, offset))
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+/// What identifies the user of a client
+pub enum QuotaIdentity {
+    /// The common name of the client certificate
+    #[default]
+    CommonName,
+    /// The username the client authenticated with, which the client chooses itself
+    Username,
+}
+
+impl QuotaIdentity {
+    fn of<'a>(&self, env: &'a ClientEnv) -> Option<&'a str> {
+        match *self {
+            QuotaIdentity::CommonName => env.common_name(),
+            QuotaIdentity::Username => env.username(),
+        }
+    }
+}
+
+fn local_midnight(day: NaiveDate, offset: &FixedOffset) -> DateTime<Utc> {
+    let midnight = day.and_hms_opt(0, 0, 0).unwrap_or_default();
+    offset.from_local_datetime(&midnight).single().map_or_else(
+        || Utc.from_utc_datetime(&midnight),
+        |d| d.with_timezone(&Utc),
+    )
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The bytes a user may transfer in each period, counting both directions
+pub struct Quota {
+    period: QuotaPeriod,
+    limit: f64,
+    warning: f64,
+}
+
+impl Quota {
+    pub fn new(period: QuotaPeriod, limit: f64) -> Quota {
+        Quota {
+            period,
+            limit,
+            warning: DEFAULT_WARNING,
+        }
+    }
+
+    /// Sets the fraction of the allowance after which a warning is sent, 0.9 by default. The
+    /// fraction must be between 0 and 1
+    pub fn with_warning(mut self, warning: f64) -> Result<Quota> {
+        if !(0.0..=1.0).contains(&warning) {
+            return Err(OpenvpnError::InvalidArgument(warning.to_string()));
+        }
+        self.warning = warning;
+        Ok(self)
+    }
+
+    pub fn period(&self) -> QuotaPeriod {
+        self.period
+    }
+
+    /// Bytes allowed in each period
+    pub fn limit(&self) -> f64 {
+        self.limit
+    }
+
+    pub fn warning(&self) -> f64 {
+        self.warning
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
+/// What a [`QuotaEnforcer`] found when applying a notification or a status
+pub enum QuotaEvent {
+    /// The user went past the warning fraction of the allowance
+    Warning {
+        identity: String,
+        used: f64,
+        limit: f64,
+    },
+    /// The user went past the allowance, the sessions listed are to be killed
+    Exceeded {
+        identity: String,
+        cids: Vec<u64>,
+        used: f64,
+        limit: f64,
+    },
+    /// A client of a user over the allowance tried to authenticate, and is to be denied until
+    /// the period resets
+    Denied {
+        identity: String,
+        cid: u64,
+        resets_at: DateTime<Utc>,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Usage {
+    period_start: DateTime<Utc>,
+    bytes: f64,
+    warned: bool,
+    exceeded: bool,
+}
+
+impl Usage {
+    fn to_line(&self, identity: &str) -> Result<String> {
+        if identity.contains(['\t', '\n']) {
+            return Err(OpenvpnError::InvalidArgument(identity.to_string()));
+        }
+        Ok(format!(
+            "{}\t{}\t{}\t{}\t{}",
+            identity,
+            self.period_start.timestamp(),
+            self.bytes,
+            self.warned,
+            self.exceeded
+        ))
+    }
+
+    fn from_line(line: &str) -> Result<(String, Usage)> {
+        let malformed = || OpenvpnError::MalformedResponse(line.to_string());
+        match line.split('\t').collect::<Vec<_>>()[..] {
+            [identity, period_start, bytes, warned, exceeded] => Ok((
+                identity.to_string(),
+                Usage {
+                    period_start: get_utc_start_time(period_start.parse()?)?,
+                    bytes: bytes.parse()?,
+                    warned: warned.parse().map_err(|_| malformed())?,
+                    exceeded: exceeded.parse().map_err(|_| malformed())?,
+                },
+            )),
+            _ => Err(malformed()),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct SessionUsage {
+    identity: String,
+    /// Bytes of the session already counted
+    counted: f64,
+}
+
+#[derive(Debug, Default)]
+/// Counts the bytes of each user, identified by common name or by username as set with
+/// [`QuotaEnforcer::identity`], across their sessions and reconnects, and enforces the quota of
+/// each user. Users without a quota of their own get the default quota, if any, and the users
+/// without any quota are not counted. The byte counts come from the `>BYTECOUNT_CLI`
+/// notifications, sent after `bytecount n`, from the status, and from the final counts of the
+/// `>CLIENT:DISCONNECT` notifications. Usage is persisted to the usage file, if any, when a
+/// session ends or a threshold is reached
+pub struct QuotaEnforcer {
+    quotas: HashMap<String, Quota>,
+    default_quota: Option<Quota>,
+    utc_offset: Option<FixedOffset>,
+    usage: HashMap<String, Usage>,
+    sessions: HashMap<u64, SessionUsage>,
+    usage_file: Option<PathBuf>,
+    identity: QuotaIdentity,
+}
+
+impl QuotaEnforcer {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Sets the quota of a user
+    pub fn set_quota(&mut self, identity: &str, quota: Quota) -> &mut QuotaEnforcer {
+        self.quotas.insert(identity.to_owned(), quota);
+        self
+    }
+
+    /// Sets the quota of the users without one of their own
+    pub fn default_quota(&mut self, quota: Quota) -> &mut QuotaEnforcer {
+        self.default_quota = Some(quota);
+        self
+    }
+
+    /// what identifies the user of a client. Default value is the common name
+    pub fn identity(&mut self, identity: QuotaIdentity) -> &mut QuotaEnforcer {
+        self.identity = identity;
+        self
+    }
+
+    /// the time zone in which the periods start at midnight. Default value is UTC
+    pub fn utc_offset(&mut self, offset: FixedOffset) -> &mut QuotaEnforcer {
+        self.utc_offset = Some(offset);
+        self
+    }
+
+    /// Persists the usage to the given file, loading the usage it already holds
+    pub fn usage_file<P: AsRef<Path>>(&mut self, path: P) -> Result<&mut QuotaEnforcer> {
+        let path = path.as_ref().to_path_buf();
+        self.usage = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .lines()
+                .filter(|l| !l.is_empty())
+                .map(Usage::from_line)
+                .collect::<Result<_>>()?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        self.usage_file = Some(path);
+        Ok(self)
+    }
+
+    /// The quota of a user
+    pub fn quota(&self, identity: &str) -> Option<&Quota> {
+        self.quotas.get(identity).or(self.default_quota.as_ref())
+    }
+
+    /// Bytes used by a user in the current period, 0 for the users without a quota
+    pub fn used(&self, identity: &str) -> f64 {
+        self.current_usage(identity, &Utc::now())
+            .map_or(0.0, |usage| usage.bytes)
+    }
+
+    /// Whether a user went past the allowance in the current period
+    pub fn is_exceeded(&self, identity: &str) -> bool {
+        self.current_usage(identity, &Utc::now())
+            .is_some_and(|usage| usage.exceeded)
+    }
+
+    /// Counts the bytes of a notification. A client of a user over the allowance is reported
+    /// as [`QuotaEvent::Denied`] when it authenticates, and as [`QuotaEvent::Exceeded`] if it
+    /// gets established anyway
+    pub fn apply(&mut self, notification: &Notification) -> Result<Vec<QuotaEvent>> {
+        self.apply_at(notification, Utc::now())
+    }
+
+    /// Counts the bytes of the clients listed in a status. Clients not seen in a notification
+    /// are counted by common name, or skipped when users are identified by username
+    pub fn apply_status(&mut self, status: &Status) -> Result<Vec<QuotaEvent>> {
+        let now = Utc::now();
+        let mut events = Vec::new();
+        for client in status.clients() {
+            let cid = match client.cid() {
+                Some(cid) => cid,
+                None => continue,
+            };
+            let identity = match self.sessions.get(&cid) {
+                Some(session) => session.identity.clone(),
+                None if self.identity == QuotaIdentity::CommonName => client.name().to_string(),
+                None => continue,
+            };
+            let total = client.bytes_received() + client.bytes_sent();
+            events.extend(self.count(cid, &identity, total, &now));
+        }
+        self.save_if_needed(&events)?;
+        Ok(events)
+    }
+
+    /// Applies a notification and acts on its events: kills the sessions of the users over
+    /// their allowance with `client-kill`, and denies their new clients with `client-deny`.
+    /// The other clients are left for the caller to authorize
+    pub fn enforce(
+        &mut self,
+        session: &mut Session,
+        notification: &Notification,
+    ) -> Result<Vec<QuotaEvent>> {
+        let events = self.apply(notification)?;
+        for event in &events {
+            match *event {
+                QuotaEvent::Exceeded { ref cids, .. } => {
+                    for &cid in cids {
+                        match session.client_kill(cid, Some(KILL_MESSAGE)) {
+                            // the client may have disconnected in the meantime
+                            Ok(()) | Err(OpenvpnError::CommandFailed(_)) => {}
+                            Err(e) => return Err(e),
+                        }
+                    }
+                }
+                QuotaEvent::Denied { cid, resets_at, .. } => {
+                    let kid = match *notification {
+                        Notification::Client(ref event) => event.kid(),
+                        _ => None,
+                    };
+                    if let Some(kid) = kid {
+                        let message =
+                            format!("traffic quota exceeded until {}", resets_at.to_rfc3339());
+                        session.client_deny(cid, kid, "traffic quota exceeded", Some(&message))?;
+                    }
+                }
+                QuotaEvent::Warning { .. } => {}
+            }
+        }
+        Ok(events)
+    }
+
+    fn apply_at(
+        &mut self,
+        notification: &Notification,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<QuotaEvent>> {
+        let events = match *notification {
+            Notification::Client(ref event) => {
+                let cid = event.cid();
+                let identity = match self.identity.of(event.env()) {
+                    Some(identity) => identity.to_string(),
+                    None => match self.sessions.get(&cid) {
+                        Some(session) => session.identity.clone(),
+                        None => return Ok(Vec::new()),
+                    },
+                };
+                match event.kind() {
+                    ClientEventKind::Connect | ClientEventKind::Reauth
+                        if self.exceeded_at(&identity, &now) =>
+                    {
+                        vec![QuotaEvent::Denied {
+                            resets_at: self.reset_time(&identity, &now),
+                            identity,
+                            cid,
+                        }]
+                    }
+                    ClientEventKind::Connect | ClientEventKind::Reauth => {
+                        self.track(cid, identity);
+                        Vec::new()
+                    }
+                    ClientEventKind::Established if self.exceeded_at(&identity, &now) => {
+                        self.track(cid, identity.clone());
+                        vec![self.exceeded(&identity, vec![cid], &now)]
+                    }
+                    ClientEventKind::Established => {
+                        self.track(cid, identity);
+                        Vec::new()
+                    }
+                    ClientEventKind::Disconnect => {
+                        let env = event.env();
+                        let total = env.bytes_received().unwrap_or_default()
+                            + env.bytes_sent().unwrap_or_default();
+                        // the session is over, so it is not among the ones to kill
+                        let counted = self
+                            .sessions
+                            .remove(&cid)
+                            .map_or(0.0, |session| session.counted);
+                        let events = self.add(&identity, (total - counted).max(0.0), &now);
+                        self.save()?;
+                        return Ok(events);
+                    }
+                }
+            }
+            Notification::ByteCount(ref count) => match count.cid() {
+                Some(cid) => match self.sessions.get(&cid) {
+                    Some(session) => {
+                        let identity = session.identity.clone();
+                        let total = count.bytes_received() + count.bytes_sent();
+                        self.count(cid, &identity, total, &now)
+                    }
+                    None => Vec::new(),
+                },
+                None => Vec::new(),
+            },
+            _ => Vec::new(),
+        };
+        self.save_if_needed(&events)?;
+        Ok(events)
+    }
+
+    fn track(&mut self, cid: u64, identity: String) {
+        let session = self.sessions.entry(cid).or_insert(SessionUsage {
+            identity: identity.clone(),
+            counted: 0.0,
+        });
+        session.identity = identity;
+    }
+
+    /// Adds the bytes of the session not counted yet, given its total since it connected
+    fn count(
+        &mut self,
+        cid: u64,
+        identity: &str,
+        total: f64,
+        now: &DateTime<Utc>,
+    ) -> Vec<QuotaEvent> {
+        let session = self.sessions.entry(cid).or_insert(SessionUsage {
+            identity: identity.to_string(),
+            counted: 0.0,
+        });
+        let added = (total - session.counted).max(0.0);
+        session.counted = total;
+        self.add(identity, added, now)
+    }
+
+    /// Adds bytes to the usage of a user
+    fn add(&mut self, identity: &str, added: f64, now: &DateTime<Utc>) -> Vec<QuotaEvent> {
+        let quota = match self.quota(identity) {
+            Some(quota) => *quota,
+            None => return Vec::new(),
+        };
+        let period_start = quota.period.start(now, &self.offset());
+        let usage = self.usage.entry(identity.to_string()).or_insert(Usage {
+            period_start,
+            bytes: 0.0,
+            warned: false,
+            exceeded: false,
+        });
+        if usage.period_start != period_start {
+            *usage = Usage {
+                period_start,
+                bytes: 0.0,
+                warned: false,
+                exceeded: false,
+            };
+        }
+        usage.bytes += added;
+        let used = usage.bytes;
+        if used > quota.limit && !usage.exceeded {
+            usage.exceeded = true;
+            usage.warned = true;
+            let cids = self.cids(identity);
+            return vec![self.exceeded(identity, cids, now)];
+        }
+        if used >= quota.limit * quota.warning && !usage.warned {
+            usage.warned = true;
+            return vec![QuotaEvent::Warning {
+                identity: identity.to_string(),
+                used,
+                limit: quota.limit,
+            }];
+        }
+        Vec::new()
+    }
+
+    fn exceeded(&self, identity: &str, cids: Vec<u64>, now: &DateTime<Utc>) -> QuotaEvent {
+        QuotaEvent::Exceeded {
+            identity: identity.to_string(),
+            cids,
+            used: self
+                .current_usage(identity, now)
+                .map_or(0.0, |usage| usage.bytes),
+            limit: self.quota(identity).map_or(0.0, |quota| quota.limit),
+        }
+    }
+
+    /// The connected clients of a user, in client ID order
+    fn cids(&self, identity: &str) -> Vec<u64> {
+        let mut cids: Vec<u64> = self
+            .sessions
+            .iter()
+            .filter(|&(_, session)| session.identity == identity)
+            .map(|(&cid, _)| cid)
+            .collect();
+        cids.sort_unstable();
+        cids
+    }
+
+    /// The usage of a user, if it belongs to the current period
+    fn current_usage(&self, identity: &str, now: &DateTime<Utc>) -> Option<&Usage> {
+        let usage = self.usage.get(identity)?;
+        match self.quota(identity) {
+            Some(quota) if quota.period.start(now, &self.offset()) != usage.period_start => None,
+            _ => Some(usage),
+        }
+    }
+
+    fn exceeded_at(&self, identity: &str, now: &DateTime<Utc>) -> bool {
+        self.current_usage(identity, now)
+            .is_some_and(|usage| usage.exceeded)
+    }
+
+    fn reset_time(&self, identity: &str, now: &DateTime<Utc>) -> DateTime<Utc> {
+        self.quota(identity)
+            .map_or(*now, |quota| quota.period.end(now, &self.offset()))
+    }
+
+    fn offset(&self) -> FixedOffset {
+        self.utc_offset
+            .unwrap_or_else(|| FixedOffset::east_opt(0).expect("UTC is a valid offset"))
+    }
+
+    fn save_if_needed(&self, events: &[QuotaEvent]) -> Result<()> {
+        if events
+            .iter()
+            .any(|e| !matches!(*e, QuotaEvent::Denied { .. }))
+        {
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// Writes the usage to the usage file, if any. Usage is only written when a session ends or
+    /// a threshold is reached, so this can be called periodically to keep the file current
+    pub fn save(&self) -> Result<()> {
+        let path = match self.usage_file {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let mut identities: Vec<_> = self.usage.keys().collect();
+        identities.sort();
+        let mut contents = String::new();
+        for identity in identities {
+            contents.push_str(&self.usage[identity].to_line(identity)?);
+            contents.push('\n');
+        }
+        let mut temporary_path = path.clone().into_os_string();
+        temporary_path.push(".tmp");
+        let mut file = fs::File::create(&temporary_path)?;
+        file.write_all(contents.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(&temporary_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bytecount::ByteCount;
+    use crate::notification::ClientEvent;
+    use std::env;
+
+    const GB: f64 = 1_000_000_000.0;
+
+    fn time(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn client_event(kind: ClientEventKind, cid: u64, env: &[&str]) -> Notification {
+        let env = ClientEnv::parse(env.iter().cloned()).unwrap();
+        Notification::Client(ClientEvent::new(kind, cid, Some(1), env))
+    }
+
+    fn connect(cid: u64) -> Notification {
+        client_event(
+            ClientEventKind::Connect,
+            cid,
+            &[">CLIENT:ENV,common_name=partner"],
+        )
+    }
+
+    fn bytes(cid: u64, received: f64, sent: f64) -> Notification {
+        Notification::ByteCount(ByteCount::new(Some(cid), received, sent))
+    }
+
+    fn disconnect(cid: u64, received: f64, sent: f64) -> Notification {
+        client_event(
+            ClientEventKind::Disconnect,
+            cid,
+            &[
+                ">CLIENT:ENV,common_name=partner",
+                &format!(">CLIENT:ENV,bytes_received={}", received),
+                &format!(">CLIENT:ENV,bytes_sent={}", sent),
+            ],
+        )
+    }
+
+    fn enforcer() -> QuotaEnforcer {
+        let mut enforcer = QuotaEnforcer::new();
+        enforcer.set_quota("partner", Quota::new(QuotaPeriod::Daily, 10.0 * GB));
+        enforcer
+    }
+
+    #[test]
+    fn test_periods() {
+        let utc = FixedOffset::east_opt(0).unwrap();
+        let paris = FixedOffset::east_opt(3600).unwrap();
+        let now = time("2024-12-31T23:30:00Z");
+
+        assert_eq!(
+            time("2024-12-31T00:00:00Z"),
+            QuotaPeriod::Daily.start(&now, &utc)
+        );
+        assert_eq!(
+            time("2025-01-01T00:00:00Z"),
+            QuotaPeriod::Daily.end(&now, &utc)
+        );
+        assert_eq!(
+            time("2024-12-31T23:00:00Z"),
+            QuotaPeriod::Daily.start(&now, &paris)
+        );
+        assert_eq!(
+            time("2024-12-01T00:00:00Z"),
+            QuotaPeriod::Monthly.start(&now, &utc)
+        );
+        assert_eq!(
+            time("2025-01-01T00:00:00Z"),
+            QuotaPeriod::Monthly.end(&now, &utc)
+        );
+    }
+
+    #[test]
+    fn test_warn_then_exceed_across_reconnects() {
+        let mut enforcer = enforcer();
+        let now = time("2024-06-01T10:00:00Z");
+
+        enforcer.apply_at(&connect(1), now).unwrap();
+        assert!(enforcer
+            .apply_at(&bytes(1, 3.0 * GB, 1.0 * GB), now)
+            .unwrap()
+            .is_empty());
+        enforcer
+            .apply_at(&disconnect(1, 4.0 * GB, 2.0 * GB), now)
+            .unwrap();
+
+        enforcer.apply_at(&connect(2), now).unwrap();
+        let events = enforcer
+            .apply_at(&bytes(2, 2.0 * GB, 1.0 * GB), now)
+            .unwrap();
+        assert_eq!(
+            vec![QuotaEvent::Warning {
+                identity: "partner".to_string(),
+                used: 9.0 * GB,
+                limit: 10.0 * GB
+            }],
+            events
+        );
+        assert!(enforcer
+            .apply_at(&bytes(2, 2.5 * GB, 1.0 * GB), now)
+            .unwrap()
+            .is_empty());
+
+        let events = enforcer
+            .apply_at(&bytes(2, 3.0 * GB, 2.0 * GB), now)
+            .unwrap();
+        assert_eq!(
+            vec![QuotaEvent::Exceeded {
+                identity: "partner".to_string(),
+                cids: vec![2],
+                used: 11.0 * GB,
+                limit: 10.0 * GB
+            }],
+            events
+        );
+    }
+
+    #[test]
+    fn test_deny_until_period_resets() {
+        let mut enforcer = enforcer();
+        let now = time("2024-06-01T10:00:00Z");
+        enforcer.apply_at(&connect(1), now).unwrap();
+        enforcer
+            .apply_at(&disconnect(1, 10.0 * GB, 1.0 * GB), now)
+            .unwrap();
+
+        assert_eq!(
+            vec![QuotaEvent::Denied {
+                identity: "partner".to_string(),
+                cid: 2,
+                resets_at: time("2024-06-02T00:00:00Z")
+            }],
+            enforcer.apply_at(&connect(2), now).unwrap()
+        );
+        let established = client_event(
+            ClientEventKind::Established,
+            3,
+            &[">CLIENT:ENV,common_name=partner"],
+        );
+        assert!(matches!(
+            enforcer.apply_at(&established, now).unwrap()[..],
+            [QuotaEvent::Exceeded { ref cids, .. }] if *cids == [3]
+        ));
+
+        let tomorrow = time("2024-06-02T00:00:01Z");
+        assert!(enforcer.apply_at(&connect(4), tomorrow).unwrap().is_empty());
+        assert!(enforcer
+            .apply_at(&bytes(4, 1.0 * GB, 0.0), tomorrow)
+            .unwrap()
+            .is_empty());
+        assert_eq!(1.0 * GB, enforcer.usage["partner"].bytes);
+    }
+
+    #[test]
+    fn test_disconnect_leaves_out_its_session() {
+        let mut enforcer = enforcer();
+        let now = time("2024-06-01T10:00:00Z");
+        enforcer.apply_at(&connect(1), now).unwrap();
+        enforcer.apply_at(&connect(2), now).unwrap();
+
+        assert!(matches!(
+            enforcer.apply_at(&disconnect(1, 10.0 * GB, 1.0 * GB), now).unwrap()[..],
+            [QuotaEvent::Exceeded { ref cids, used, .. }] if *cids == [2] && used == 11.0 * GB
+        ));
+    }
+
+    #[test]
+    fn test_identity() {
+        let env = [
+            ">CLIENT:ENV,common_name=alice",
+            ">CLIENT:ENV,username=partner",
+        ];
+        let now = time("2024-06-01T10:00:00Z");
+        let mut by_name = enforcer();
+        by_name
+            .apply_at(&client_event(ClientEventKind::Connect, 1, &env), now)
+            .unwrap();
+        let events = by_name.apply_at(&bytes(1, 11.0 * GB, 0.0), now).unwrap();
+
+        assert!(events.is_empty());
+        assert!(by_name.usage.is_empty());
+
+        let mut enforcer = enforcer();
+        enforcer.identity(QuotaIdentity::Username);
+        enforcer
+            .apply_at(&client_event(ClientEventKind::Connect, 1, &env), now)
+            .unwrap();
+
+        assert!(matches!(
+            enforcer.apply_at(&bytes(1, 11.0 * GB, 0.0), now).unwrap()[..],
+            [QuotaEvent::Exceeded { ref identity, .. }] if identity == "partner"
+        ));
+    }
+
+    #[test]
+    fn test_warning_fraction() {
+        let quota = Quota::new(QuotaPeriod::Daily, 10.0 * GB);
+
+        assert_eq!(0.5, quota.with_warning(0.5).unwrap().warning());
+        for warning in &[-0.1, 1.5, f64::NAN] {
+            assert!(matches!(
+                quota.with_warning(*warning),
+                Err(OpenvpnError::InvalidArgument(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_usage_file() {
+        let path = env::temp_dir().join(format!("openvpn-management-quota-{}", std::process::id()));
+        let _ = fs::remove_file(&path);
+        {
+            let mut enforcer = enforcer();
+            enforcer.usage_file(&path).unwrap();
+            enforcer.apply(&connect(1)).unwrap();
+            enforcer.apply(&disconnect(1, 2.0 * GB, 1.0 * GB)).unwrap();
+        }
+        let mut enforcer = enforcer();
+        enforcer.usage_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(3.0 * GB, enforcer.used("partner"));
+        assert!(!enforcer.is_exceeded("partner"));
+        assert_eq!(0.0, enforcer.used("someone-else"));
+    }
+}
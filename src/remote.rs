@@ -0,0 +1,206 @@
+use crate::command::quote;
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The next remote openvpn is about to try, sent in a `>REMOTE:host,port,proto` notification
+/// with `--management-query-remotes`
+pub struct RemoteRequest {
+    host: String,
+    port: u16,
+    protocol: String,
+}
+
+impl RemoteRequest {
+    pub fn new(host: String, port: u16, protocol: String) -> RemoteRequest {
+        RemoteRequest {
+            host,
+            port,
+            protocol,
+        }
+    }
+
+    /// Parses the payload of a `>REMOTE` notification
+    pub fn parse(payload: &str) -> Result<RemoteRequest> {
+        let fields: Vec<_> = payload.split(',').collect();
+        if fields.len() != 3 {
+            return Err(OpenvpnError::MalformedResponse(payload.to_string()));
+        }
+        Ok(RemoteRequest::new(
+            fields[0].to_string(),
+            fields[1].parse()?,
+            fields[2].to_string(),
+        ))
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The transport protocol, e.g. `udp` or `tcp-client`
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A remote from the client configuration, as listed by `remote-entry-get` (openvpn 2.6)
+pub struct RemoteEntry {
+    index: u32,
+    host: String,
+    port: u16,
+    protocol: String,
+    disabled: bool,
+}
+
+impl RemoteEntry {
+    pub fn new(
+        index: u32,
+        host: String,
+        port: u16,
+        protocol: String,
+        disabled: bool,
+    ) -> RemoteEntry {
+        RemoteEntry {
+            index,
+            host,
+            port,
+            protocol,
+            disabled,
+        }
+    }
+
+    /// Parses a `remote-entry-get` line, i.e. `index,host,port,proto[,enabled|disabled]`
+    pub fn parse(line: &str) -> Result<RemoteEntry> {
+        let malformed = || OpenvpnError::MalformedResponse(line.to_string());
+        let fields: Vec<_> = line.split(',').collect();
+        if fields.len() != 4 && fields.len() != 5 {
+            return Err(malformed());
+        }
+        let disabled = match fields.get(4) {
+            None | Some(&"enabled") => false,
+            Some(&"disabled") => true,
+            Some(_) => return Err(malformed()),
+        };
+        Ok(RemoteEntry::new(
+            fields[0].parse()?,
+            fields[1].to_string(),
+            fields[2].parse()?,
+            fields[3].to_string(),
+            disabled,
+        ))
+    }
+
+    /// The 0-based position of the entry in the configuration
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// The transport protocol, e.g. `udp` or `tcp-client`
+    pub fn protocol(&self) -> &str {
+        &self.protocol
+    }
+
+    /// Whether the entry is skipped because of protocol or proxy restrictions. Always false
+    /// for openvpn versions which do not report the state
+    pub fn disabled(&self) -> bool {
+        self.disabled
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+/// The answer to a [`RemoteRequest`]
+pub enum RemoteAction {
+    /// Connects to the remote as configured
+    Accept,
+    /// Skips the given number of remotes, `remote SKIP n` needs openvpn 2.6 when above 1
+    Skip(u32),
+    /// Connects to another host and port with the same protocol
+    Modify(String, u16),
+}
+
+impl RemoteAction {
+    /// The `remote` command sending this action
+    pub(crate) fn command(&self) -> Result<String> {
+        match *self {
+            RemoteAction::Accept => Ok("remote ACCEPT".to_string()),
+            RemoteAction::Skip(1) => Ok("remote SKIP".to_string()),
+            RemoteAction::Skip(count) => Ok(format!("remote SKIP {}", count)),
+            RemoteAction::Modify(ref host, port) => {
+                Ok(format!("remote MOD {} {}", quote(host)?, port))
+            }
+        }
+    }
+}
+
+/// Chooses the remote an openvpn client connects to
+pub trait RemotePolicy {
+    /// Returns whether to accept, skip or replace the remote openvpn is about to try
+    fn select(&mut self, request: &RemoteRequest) -> Result<RemoteAction>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_remote_request() {
+        let request = RemoteRequest::parse("vpn.example.com,1194,udp").unwrap();
+
+        assert_eq!(
+            RemoteRequest::new("vpn.example.com".to_string(), 1194, "udp".to_string()),
+            request
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed_remote_request() {
+        assert!(RemoteRequest::parse("vpn.example.com,1194").is_err());
+        assert!(RemoteRequest::parse("vpn.example.com,port,udp").is_err());
+    }
+
+    #[test]
+    fn test_parse_remote_entry() {
+        assert_eq!(
+            RemoteEntry::new(
+                1,
+                "vpn.example.net".to_string(),
+                443,
+                "tcp-client".to_string(),
+                true
+            ),
+            RemoteEntry::parse("1,vpn.example.net,443,tcp-client,disabled").unwrap()
+        );
+        assert!(!RemoteEntry::parse("0,vpn.example.com,1194,udp")
+            .unwrap()
+            .disabled());
+        assert!(RemoteEntry::parse("0,vpn.example.com,1194,udp,unknown").is_err());
+        assert!(RemoteEntry::parse("0,vpn.example.com,1194").is_err());
+    }
+
+    #[test]
+    fn test_remote_commands() {
+        assert_eq!("remote ACCEPT", RemoteAction::Accept.command().unwrap());
+        assert_eq!("remote SKIP", RemoteAction::Skip(1).command().unwrap());
+        assert_eq!("remote SKIP 3", RemoteAction::Skip(3).command().unwrap());
+        assert_eq!(
+            r#"remote MOD "eu.example.com" 443"#,
+            RemoteAction::Modify("eu.example.com".to_string(), 443)
+                .command()
+                .unwrap()
+        );
+    }
+}
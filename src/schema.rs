@@ -0,0 +1,134 @@
+use serde::{Deserialize, Deserializer, Serialize};
+
+/// Version of the serialized representation of the library types. It is bumped whenever a
+/// field is renamed or removed, or its format changes; adding a field does not bump it
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+/// Wraps a serializable value with the schema version it was written with, e.g.
+/// `{"schema_version":1,"data":{"title":...}}`. Deserialization fails on versions newer than
+/// [`SCHEMA_VERSION`]
+pub struct Versioned<T> {
+    #[serde(deserialize_with = "supported_version")]
+    schema_version: u32,
+    data: T,
+}
+
+impl<T> Versioned<T> {
+    /// Wraps the value with the current [`SCHEMA_VERSION`]
+    pub fn new(data: T) -> Versioned<T> {
+        Versioned {
+            schema_version: SCHEMA_VERSION,
+            data,
+        }
+    }
+
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    pub fn into_data(self) -> T {
+        self.data
+    }
+}
+
+fn supported_version<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u32, D::Error> {
+    let version = u32::deserialize(deserializer)?;
+    if version == 0 || version > SCHEMA_VERSION {
+        return Err(serde::de::Error::custom(format!(
+            "unsupported schema version {}, expected at most {}",
+            version, SCHEMA_VERSION
+        )));
+    }
+    Ok(version)
+}
+
+/// Serializes binary data, such as the data to sign, as a base64 string
+pub(crate) mod base64_bytes {
+    use base64::engine::general_purpose::STANDARD;
+    use base64::Engine;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&STANDARD.encode(data))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        STANDARD.decode(encoded).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{client, status};
+    use crate::{
+        Client, ClientEnv, ClientEvent, ClientEventKind, Notification, SignRequest, Status,
+    };
+
+    #[test]
+    fn test_status_schema() {
+        let status = status(vec![client(
+            "alice",
+            "192.168.0.3:41954",
+            1546277714,
+            100.0,
+            200.0,
+        )]);
+        let json = serde_json::to_string(&Versioned::new(&status)).unwrap();
+
+        assert_eq!(
+            r#"{"schema_version":1,"data":{"title":"OpenVPN 2.6.8","clients":[{"name":"alice","ip_address":"192.168.0.3:41954","connected_since":"2018-12-31T17:35:14Z","bytes_received":100.0,"bytes_sent":200.0,"cid":null,"virtual_address":null}],"timestamp":"2019-01-19T16:04:53Z"}}"#,
+            json
+        );
+        let parsed: Versioned<Status> = serde_json::from_str(&json).unwrap();
+        assert_eq!(&status, parsed.data());
+        // documents written before the virtual address was added
+        let parsed: Versioned<Status> =
+            serde_json::from_str(&json.replace(r#","virtual_address":null"#, "")).unwrap();
+        assert_eq!(&status, parsed.data());
+    }
+
+    #[test]
+    fn test_unsupported_schema_version() {
+        let error =
+            serde_json::from_str::<Versioned<Vec<Client>>>(r#"{"schema_version":2,"data":[]}"#)
+                .unwrap_err();
+
+        assert!(error.to_string().contains("unsupported schema version 2"));
+    }
+
+    #[test]
+    fn test_notification_schema_redacts_password() {
+        let env = ClientEnv::parse(vec![
+            ">CLIENT:ENV,common_name=alice",
+            ">CLIENT:ENV,password=hunter2",
+            ">CLIENT:ENV,END",
+        ])
+        .unwrap();
+        let notification =
+            Notification::Client(ClientEvent::new(ClientEventKind::Connect, 4, Some(1), env));
+
+        assert_eq!(
+            r#"{"type":"client","data":{"kind":"connect","cid":4,"kid":1,"env":{"common_name":"alice","password":"<redacted>"}}}"#,
+            serde_json::to_string(&notification).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sign_request_data_is_base64() {
+        let request = SignRequest::parse_pk_sign("AAEC").unwrap();
+        let json = serde_json::to_value(&request).unwrap();
+
+        assert_eq!("AAEC", json["data"]);
+        assert_eq!(
+            request,
+            serde_json::from_value::<SignRequest>(json).unwrap()
+        );
+    }
+}
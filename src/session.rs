@@ -0,0 +1,566 @@
+use crate::certificate::{normalize_pem, CertificateProvider, CertificateRequest};
+use crate::command::{check_single_line, quote};
+use crate::credentials::{AuthRetry, CredentialProvider, PasswordNotification};
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::info_message::{InfoMessage, InfoMessageHandler, WebAuthFlags};
+use crate::log::LogEntry;
+use crate::notification::{Notification, NotificationParser};
+use crate::prompt::{PromptHandler, PromptRequest};
+use crate::proxy::{ProxyAction, ProxyPolicy, ProxyRequest};
+use crate::remote::{RemoteAction, RemoteEntry, RemotePolicy, RemoteRequest};
+use crate::signal::Signal;
+use crate::signing::{encode_signature, SignRequest, Signer};
+use crate::state::StateRecord;
+use crate::stats::LoadStats;
+use crate::stream::Stream;
+use crate::{parse_status_output, EventManager, Status, ENDING};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, Write};
+use std::ops::Range;
+use std::time::Duration;
+
+const SUCCESS: &str = "SUCCESS:";
+const ERROR: &str = "ERROR:";
+
+/// A persistent connection to the management interface. Unlike the [`CommandManager`], which
+/// opens a new connection per request, a session receives the real-time notifications sent by
+/// the server. Notifications arriving while a command reply is read are queued and returned by
+/// [`Session::next_notification`]
+///
+/// [`CommandManager`]: struct.CommandManager.html
+pub struct Session {
+    writer: Stream,
+    reader: BufReader<Stream>,
+    parser: NotificationParser,
+    pending: VecDeque<Notification>,
+    partial_line: String,
+    challenge_response: Option<(String, String)>,
+    auth_retry: Option<AuthRetry>,
+    max_auth_retries: u32,
+    auth_failures: u32,
+}
+
+impl Session {
+    pub(crate) fn new(stream: Stream) -> Result<Session> {
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Session {
+            writer: stream,
+            reader,
+            parser: NotificationParser::new(),
+            pending: VecDeque::new(),
+            partial_line: String::new(),
+            challenge_response: None,
+            auth_retry: None,
+            max_auth_retries: 0,
+            auth_failures: 0,
+        })
+    }
+
+    /// Blocks until the server sends a notification, or returns the oldest queued one
+    pub fn next_notification(&mut self) -> Result<Notification> {
+        loop {
+            if let Some(notification) = self.pending.pop_front() {
+                return Ok(notification);
+            }
+            let line = self.read_line()?;
+            if !NotificationParser::is_notification(&line) {
+                return Err(OpenvpnError::MalformedResponse(line));
+            }
+            if let Some(notification) = self.parser.push_line(&line)? {
+                return Ok(notification);
+            }
+        }
+    }
+
+    /// Waits up to the timeout for a notification and returns `None` if none arrived, e.g. to
+    /// interleave the notifications with other work on a single thread
+    pub fn poll_notification(&mut self, timeout: Duration) -> Result<Option<Notification>> {
+        if let Some(notification) = self.pending.pop_front() {
+            return Ok(Some(notification));
+        }
+        let stream = self.reader.get_ref();
+        let read_timeout = stream.read_timeout()?;
+        stream.set_read_timeout(Some(timeout))?;
+        let result = self.next_notification();
+        self.reader.get_ref().set_read_timeout(read_timeout)?;
+        match result {
+            Ok(notification) => Ok(Some(notification)),
+            Err(OpenvpnError::Io(ref e))
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Sends a command answered with a single `SUCCESS:` line and returns the success message
+    pub fn command(&mut self, command: &str) -> Result<String> {
+        self.send(command)?;
+        let line = self.read_reply_line()?;
+        parse_single_line_reply(line)
+    }
+
+    /// Sends a command answered with several lines terminated by `END` and returns those lines
+    pub fn multiline_command(&mut self, command: &str) -> Result<Vec<String>> {
+        self.send(command)?;
+        let mut lines = Vec::new();
+        loop {
+            let line = self.read_reply_line()?;
+            if lines.is_empty() && line.starts_with(ERROR) {
+                return Err(command_failed(&line));
+            }
+            if line == ENDING {
+                return Ok(lines);
+            }
+            lines.push(line);
+        }
+    }
+
+    /// Authorizes a client waiting in `>CLIENT:CONNECT` or `>CLIENT:REAUTH`, pushing the given
+    /// config lines (e.g. `ifconfig-push 10.8.0.10 255.255.255.0`) to it
+    pub fn client_auth<S: AsRef<str>>(&mut self, cid: u64, kid: u64, config: &[S]) -> Result<()> {
+        self.block_command(format!("client-auth {} {}", cid, kid), config)
+    }
+
+    /// Disconnects a client by client ID. The message, e.g. `HALT` or `RESTART`, is sent to
+    /// the client in place of the default `RESTART`
+    pub fn client_kill(&mut self, cid: u64, message: Option<&str>) -> Result<()> {
+        let mut command = format!("client-kill {}", cid);
+        if let Some(message) = message {
+            command.push(' ');
+            command.push_str(&quote(message)?);
+        }
+        self.command(&command).map(|_| ())
+    }
+
+    /// Disconnects the clients matching a common name or a real `address:port`, and returns
+    /// the success message, e.g. `common name 'alice' found, 1 client(s) killed`
+    pub fn kill(&mut self, target: &str) -> Result<String> {
+        self.command(&format!("kill {}", quote(target)?))
+    }
+
+    /// Sends a signal to the openvpn process
+    pub fn signal(&mut self, signal: Signal) -> Result<()> {
+        self.command(&format!("signal {}", signal)).map(|_| ())
+    }
+
+    /// Releases openvpn from the hold state it starts in with `--management-hold`
+    pub fn hold_release(&mut self) -> Result<()> {
+        self.command("hold release").map(|_| ())
+    }
+
+    /// Returns the global server statistics
+    pub fn load_stats(&mut self) -> Result<LoadStats> {
+        let message = self.command("load-stats")?;
+        LoadStats::parse(&message)
+    }
+
+    /// Returns the current openvpn state
+    pub fn state(&mut self) -> Result<StateRecord> {
+        let lines = self.multiline_command("state")?;
+        match lines.first() {
+            Some(line) => StateRecord::parse(line),
+            None => Err(OpenvpnError::MalformedResponse(String::new())),
+        }
+    }
+
+    /// Returns all the states openvpn went through, oldest first
+    pub fn state_history(&mut self) -> Result<Vec<StateRecord>> {
+        self.multiline_command("state all")?
+            .iter()
+            .map(|line| StateRecord::parse(line))
+            .collect()
+    }
+
+    /// Enables the real-time `>STATE` notifications
+    pub fn state_on(&mut self) -> Result<()> {
+        self.command("state on").map(|_| ())
+    }
+
+    /// Returns the last lines of the log, or the whole log history when no count is given
+    pub fn log_history(&mut self, count: Option<usize>) -> Result<Vec<LogEntry>> {
+        let command = match count {
+            Some(count) => format!("log {}", count),
+            None => "log all".to_string(),
+        };
+        self.multiline_command(&command)?
+            .iter()
+            .map(|line| LogEntry::parse(line))
+            .collect()
+    }
+
+    /// Enables the `>BYTECOUNT` notifications every given number of seconds, 0 disabling them
+    pub fn bytecount(&mut self, interval: u32) -> Result<()> {
+        self.command(&format!("bytecount {}", interval)).map(|_| ())
+    }
+
+    /// Enables the real-time `>LOG` notifications
+    pub fn log_on(&mut self) -> Result<()> {
+        self.command("log on").map(|_| ())
+    }
+
+    /// Authorizes a client without pushing any config
+    pub fn client_auth_nt(&mut self, cid: u64, kid: u64) -> Result<()> {
+        self.command(&format!("client-auth-nt {} {}", cid, kid))
+            .map(|_| ())
+    }
+
+    /// Denies a client. The reason is logged by the server, the client reason is sent to the
+    /// client in the `AUTH_FAILED` message
+    pub fn client_deny(
+        &mut self,
+        cid: u64,
+        kid: u64,
+        reason: &str,
+        client_reason: Option<&str>,
+    ) -> Result<()> {
+        let mut command = format!("client-deny {} {} {}", cid, kid, quote(reason)?);
+        if let Some(client_reason) = client_reason {
+            command.push(' ');
+            command.push_str(&quote(client_reason)?);
+        }
+        self.command(&command).map(|_| ())
+    }
+
+    /// Sends the username for a realm, e.g. `Auth`
+    pub fn username(&mut self, realm: &str, username: &str) -> Result<()> {
+        let command = format!("username {} {}", quote(realm)?, quote(username)?);
+        self.command(&command).map(|_| ())
+    }
+
+    /// Sends the password for a realm, e.g. `Auth` or `Private Key`
+    pub fn password(&mut self, realm: &str, password: &str) -> Result<()> {
+        let command = format!("password {} {}", quote(realm)?, quote(password)?);
+        self.command(&command).map(|_| ())
+    }
+
+    /// Sets what the client does after an authentication failure. In `interact` mode, up to
+    /// [`max_auth_retries`](#method.max_auth_retries) failures are absorbed by
+    /// [`respond_to_password`](#method.respond_to_password) so that the provider is asked again
+    pub fn auth_retry(&mut self, mode: AuthRetry) -> Result<()> {
+        self.command(&format!("auth-retry {}", mode))?;
+        self.auth_retry = Some(mode);
+        self.auth_failures = 0;
+        Ok(())
+    }
+
+    /// Sets how many rejected credentials are retried in `interact` mode. Default is 0
+    pub fn max_auth_retries(&mut self, max_auth_retries: u32) {
+        self.max_auth_retries = max_auth_retries;
+    }
+
+    /// The number of rejected credentials since the session was opened or the `auth-retry`
+    /// mode or the passwords were last reset
+    pub fn auth_failures(&self) -> u32 {
+        self.auth_failures
+    }
+
+    /// Makes the client forget the passwords it was given, which are otherwise kept for
+    /// reconnections
+    pub fn forget_passwords(&mut self) -> Result<()> {
+        self.command("forget-passwords")?;
+        self.challenge_response = None;
+        self.auth_failures = 0;
+        Ok(())
+    }
+
+    /// Handles a `>PASSWORD` notification in client mode: prompts are answered with the
+    /// provider's credentials, auth tokens are handed to the provider and rejected credentials
+    /// are reported as [`OpenvpnError::VerificationFailed`], unless they can be retried under
+    /// the `interact` [`AuthRetry`] mode
+    ///
+    /// Static challenges are answered along with the password. A dynamic challenge sent with a
+    /// rejection is answered by the provider right away, and the response is sent on the next
+    /// `username/password` prompt in place of the provider's credentials
+    pub fn respond_to_password(
+        &mut self,
+        notification: &PasswordNotification,
+        provider: &mut dyn CredentialProvider,
+    ) -> Result<()> {
+        match *notification {
+            PasswordNotification::Need(ref request) => {
+                if request.needs_username() {
+                    if let Some((username, password)) = self.challenge_response.take() {
+                        self.username(request.realm(), &username)?;
+                        return self.password(request.realm(), &password);
+                    }
+                }
+                let credentials = provider.credentials(request)?;
+                if request.needs_username() {
+                    let username = credentials.username().ok_or_else(|| {
+                        OpenvpnError::InvalidArgument(format!(
+                            "no username for '{}'",
+                            request.realm()
+                        ))
+                    })?;
+                    self.username(request.realm(), username)?;
+                }
+                match request.static_challenge() {
+                    Some(challenge) => {
+                        let response = provider.static_challenge(request, challenge)?;
+                        let password = challenge.response(credentials.password(), &response);
+                        self.password(request.realm(), &password)
+                    }
+                    None => self.password(request.realm(), credentials.password()),
+                }
+            }
+            PasswordNotification::VerificationFailed {
+                challenge: Some(ref challenge),
+                ..
+            } => {
+                let response = provider.dynamic_challenge(challenge)?;
+                self.challenge_response = Some((
+                    challenge.username().to_string(),
+                    challenge.response(&response),
+                ));
+                Ok(())
+            }
+            PasswordNotification::VerificationFailed { ref realm, .. } => {
+                provider.verification_failed(realm);
+                self.auth_failures += 1;
+                if self.auth_retry == Some(AuthRetry::Interact)
+                    && self.auth_failures <= self.max_auth_retries
+                {
+                    return Ok(());
+                }
+                Err(OpenvpnError::VerificationFailed(realm.clone()))
+            }
+            PasswordNotification::AuthToken(ref token) => {
+                provider.auth_token(token);
+                Ok(())
+            }
+        }
+    }
+
+    /// Confirms (`ok`) or cancels (`cancel`) a `>NEED-OK` request
+    pub fn needok(&mut self, name: &str, confirmed: bool) -> Result<()> {
+        let answer = if confirmed { "ok" } else { "cancel" };
+        self.command(&format!("needok {} {}", quote(name)?, answer))
+            .map(|_| ())
+    }
+
+    /// Sends the string asked for by a `>NEED-STR` request
+    pub fn needstr(&mut self, name: &str, value: &str) -> Result<()> {
+        let command = format!("needstr {} {}", quote(name)?, quote(value)?);
+        self.command(&command).map(|_| ())
+    }
+
+    /// Answers a `>NEED-OK` request with the handler's confirmation
+    pub fn respond_to_need_ok(
+        &mut self,
+        request: &PromptRequest,
+        handler: &mut dyn PromptHandler,
+    ) -> Result<()> {
+        let confirmed = handler.confirm(request)?;
+        self.needok(request.name(), confirmed)
+    }
+
+    /// Answers a `>NEED-STR` request with the handler's input
+    pub fn respond_to_need_str(
+        &mut self,
+        request: &PromptRequest,
+        handler: &mut dyn PromptHandler,
+    ) -> Result<()> {
+        let value = handler.input(request)?;
+        self.needstr(request.name(), &value)
+    }
+
+    /// Sends a signature made with the external key, answering a `>PK_SIGN` request
+    pub fn pk_sig(&mut self, signature: &[u8]) -> Result<()> {
+        self.block_command("pk-sig".to_string(), &encode_signature(signature))
+    }
+
+    /// Answers a `>PK_SIGN` or `>RSA_SIGN` request with the signer's signature
+    pub fn respond_to_sign(
+        &mut self,
+        request: &SignRequest,
+        signer: &mut dyn Signer,
+    ) -> Result<()> {
+        let signature = signer.sign(request)?;
+        self.block_command(
+            request.reply_command().to_string(),
+            &encode_signature(&signature),
+        )
+    }
+
+    /// Sends the client certificate asked for by a `>NEED-CERTIFICATE` request. The PEM is
+    /// validated and normalized first
+    pub fn certificate(&mut self, pem: &str) -> Result<()> {
+        let lines = normalize_pem(pem)?;
+        self.block_command("certificate".to_string(), &lines)
+    }
+
+    /// Answers a `>NEED-CERTIFICATE` request with the provider's certificate
+    pub fn respond_to_certificate(
+        &mut self,
+        request: &CertificateRequest,
+        provider: &mut dyn CertificateProvider,
+    ) -> Result<()> {
+        let pem = provider.certificate(request)?;
+        self.certificate(&pem)
+    }
+
+    /// Accepts, skips or modifies the remote of a `>REMOTE` request
+    pub fn remote(&mut self, action: &RemoteAction) -> Result<()> {
+        self.command(&action.command()?).map(|_| ())
+    }
+
+    /// Answers a `>REMOTE` request with the policy's choice
+    pub fn respond_to_remote(
+        &mut self,
+        request: &RemoteRequest,
+        policy: &mut dyn RemotePolicy,
+    ) -> Result<()> {
+        let action = policy.select(request)?;
+        self.remote(&action)
+    }
+
+    /// Answers a `CR_TEXT` challenge, the response is sent base64 encoded
+    pub fn cr_response(&mut self, response: &str) -> Result<()> {
+        self.command(&format!("cr-response {}", STANDARD.encode(response)))
+            .map(|_| ())
+    }
+
+    /// Hands an `>INFOMSG` notification to the handler, sending its response to `CR_TEXT`
+    /// challenges which require one
+    pub fn respond_to_info_message(
+        &mut self,
+        message: &InfoMessage,
+        handler: &mut dyn InfoMessageHandler,
+    ) -> Result<()> {
+        match *message {
+            InfoMessage::WebAuth { ref flags, ref url } => handler.open_url(url, flags),
+            InfoMessage::OpenUrl { ref url } => handler.open_url(url, &WebAuthFlags::default()),
+            InfoMessage::CrText {
+                echo,
+                response_required: true,
+                ref text,
+            } => {
+                let response = handler.challenge_text(text, echo)?;
+                self.cr_response(&response)
+            }
+            InfoMessage::CrText { ref text, .. } | InfoMessage::Other(ref text) => {
+                handler.info(text);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the number of remotes in the client configuration (openvpn 2.6)
+    pub fn remote_entry_count(&mut self) -> Result<u32> {
+        let lines = self.multiline_command("remote-entry-count")?;
+        match lines.first() {
+            Some(count) => Ok(count.trim().parse()?),
+            None => Err(OpenvpnError::MalformedResponse(String::new())),
+        }
+    }
+
+    /// Lists all the remotes in the client configuration (openvpn 2.6)
+    pub fn get_remote_entries(&mut self) -> Result<Vec<RemoteEntry>> {
+        self.remote_entries("remote-entry-get all")
+    }
+
+    /// Lists the remotes with a 0-based index within the range (openvpn 2.6)
+    pub fn get_remote_entries_range(&mut self, range: Range<u32>) -> Result<Vec<RemoteEntry>> {
+        self.remote_entries(&format!("remote-entry-get {} {}", range.start, range.end))
+    }
+
+    fn remote_entries(&mut self, command: &str) -> Result<Vec<RemoteEntry>> {
+        self.multiline_command(command)?
+            .iter()
+            .map(|line| RemoteEntry::parse(line))
+            .collect()
+    }
+
+    /// Answers a `>PROXY` request with a direct connection or the proxy to go through
+    pub fn proxy(&mut self, action: &ProxyAction) -> Result<()> {
+        self.command(&action.command()?).map(|_| ())
+    }
+
+    /// Answers a `>PROXY` request with the policy's choice
+    pub fn respond_to_proxy(
+        &mut self,
+        request: &ProxyRequest,
+        policy: &mut dyn ProxyPolicy,
+    ) -> Result<()> {
+        let action = policy.proxy(request)?;
+        self.proxy(&action)
+    }
+
+    /// Sends a command followed by lines of input and `END`, answered with a single line
+    fn block_command<S: AsRef<str>>(&mut self, mut command: String, lines: &[S]) -> Result<()> {
+        for line in lines {
+            let line = line.as_ref();
+            check_single_line(line)?;
+            if line == ENDING {
+                return Err(OpenvpnError::InvalidArgument(line.to_string()));
+            }
+            command.push('\n');
+            command.push_str(line);
+        }
+        command.push('\n');
+        command.push_str(ENDING);
+        self.command(&command).map(|_| ())
+    }
+
+    fn send(&mut self, command: &str) -> Result<()> {
+        self.writer.write_all(command.as_bytes())?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn read_reply_line(&mut self) -> Result<String> {
+        loop {
+            let line = self.read_line()?;
+            if !NotificationParser::is_notification(&line) {
+                return Ok(line);
+            }
+            if let Some(notification) = self.parser.push_line(&line)? {
+                self.pending.push_back(notification);
+            }
+        }
+    }
+
+    /// Reads the next line. A line cut by a read timeout is kept and completed by the next read
+    fn read_line(&mut self) -> Result<String> {
+        if self.reader.read_line(&mut self.partial_line)? == 0 {
+            return Err(OpenvpnError::Io(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "the management interface closed the connection",
+            )));
+        }
+        let mut line = std::mem::take(&mut self.partial_line);
+        let length = line.trim_end_matches(['\r', '\n']).len();
+        line.truncate(length);
+        Ok(line)
+    }
+}
+
+impl EventManager for Session {
+    /// Sends a status request over the session and parses the response
+    fn get_status(&mut self) -> Result<Status> {
+        let mut lines = self.multiline_command("status")?;
+        lines.push(ENDING.to_string());
+        parse_status_output(lines.join("\r\n"))
+    }
+}
+
+fn parse_single_line_reply(line: String) -> Result<String> {
+    if let Some(message) = line.strip_prefix(SUCCESS) {
+        return Ok(message.trim().to_string());
+    }
+    if line.starts_with(ERROR) {
+        return Err(command_failed(&line));
+    }
+    Err(OpenvpnError::MalformedResponse(line))
+}
+
+fn command_failed(line: &str) -> OpenvpnError {
+    OpenvpnError::CommandFailed(line[ERROR.len()..].trim().to_string())
+}
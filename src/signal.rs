@@ -0,0 +1,61 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The signals which can be sent to the openvpn process with the `signal` command
+pub enum Signal {
+    /// `SIGHUP`, restarts openvpn and rereads its configuration
+    #[cfg_attr(feature = "serde", serde(rename = "SIGHUP"))]
+    Hup,
+    /// `SIGTERM`, stops openvpn
+    #[cfg_attr(feature = "serde", serde(rename = "SIGTERM"))]
+    Term,
+    /// `SIGUSR1`, restarts the connection without rereading the configuration
+    #[cfg_attr(feature = "serde", serde(rename = "SIGUSR1"))]
+    Usr1,
+    /// `SIGUSR2`, writes the statistics to the log
+    #[cfg_attr(feature = "serde", serde(rename = "SIGUSR2"))]
+    Usr2,
+}
+
+impl fmt::Display for Signal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Signal::Hup => f.write_str("SIGHUP"),
+            Signal::Term => f.write_str("SIGTERM"),
+            Signal::Usr1 => f.write_str("SIGUSR1"),
+            Signal::Usr2 => f.write_str("SIGUSR2"),
+        }
+    }
+}
+
+impl FromStr for Signal {
+    type Err = OpenvpnError;
+
+    /// Parses a signal name, with or without the `SIG` prefix and in any case
+    fn from_str(raw_signal: &str) -> Result<Signal> {
+        let name = raw_signal.to_ascii_uppercase();
+        match name.strip_prefix("SIG").unwrap_or(&name) {
+            "HUP" => Ok(Signal::Hup),
+            "TERM" => Ok(Signal::Term),
+            "USR1" => Ok(Signal::Usr1),
+            "USR2" => Ok(Signal::Usr2),
+            _ => Err(OpenvpnError::InvalidArgument(raw_signal.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signal_names() {
+        assert_eq!(Signal::Hup, "SIGHUP".parse().unwrap());
+        assert_eq!(Signal::Usr1, "usr1".parse().unwrap());
+        assert_eq!("SIGTERM", Signal::Term.to_string());
+        assert!("SIGKILL".parse::<Signal>().is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+
+const HASH_ALGORITHM: &str = "hashalg=";
+const SALT_LENGTH: &str = "saltlen=";
+const SIGNATURE_LINE_LENGTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
+/// The signature scheme openvpn asks for in a `>PK_SIGN` notification
+pub enum SignatureAlgorithm {
+    /// `RSA_PKCS1_PADDING`, the data is a DigestInfo structure to pad with PKCS#1 v1.5
+    RsaPkcs1,
+    /// `RSA_PKCS1_PSS_PADDING`, the data is a digest to sign with PSS
+    RsaPss,
+    /// `RSA_NO_PADDING`, the data is already padded and is signed with the raw RSA operation
+    RsaNoPadding,
+    /// `ECDSA`, the data is a digest and a DER encoded signature is expected
+    Ecdsa,
+    /// Any algorithm without a typed representation, e.g. `ED25519`
+    Other(String),
+}
+
+impl SignatureAlgorithm {
+    fn parse(raw_algorithm: &str) -> SignatureAlgorithm {
+        match raw_algorithm {
+            "RSA_PKCS1_PADDING" => SignatureAlgorithm::RsaPkcs1,
+            "RSA_PKCS1_PSS_PADDING" => SignatureAlgorithm::RsaPss,
+            "RSA_NO_PADDING" => SignatureAlgorithm::RsaNoPadding,
+            "ECDSA" => SignatureAlgorithm::Ecdsa,
+            _ => SignatureAlgorithm::Other(raw_algorithm.to_string()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Data openvpn asks to sign with the key held outside of it (`--management-external-key`)
+pub struct SignRequest {
+    #[cfg_attr(feature = "serde", serde(with = "crate::schema::base64_bytes"))]
+    data: Vec<u8>,
+    algorithm: SignatureAlgorithm,
+    hash_algorithm: Option<String>,
+    salt_length: Option<String>,
+    legacy: bool,
+}
+
+impl SignRequest {
+    pub fn new(data: Vec<u8>, algorithm: SignatureAlgorithm) -> SignRequest {
+        SignRequest {
+            data,
+            algorithm,
+            hash_algorithm: None,
+            salt_length: None,
+            legacy: false,
+        }
+    }
+
+    /// Parses the payload of a `>PK_SIGN` notification, i.e. `<base64 data>[,<algorithm>]`
+    /// where the algorithm may be followed by `hashalg=` and `saltlen=` parameters. The
+    /// algorithm defaults to `RSA_PKCS1_PADDING` when not sent by older openvpn versions
+    pub fn parse_pk_sign(payload: &str) -> Result<SignRequest> {
+        let mut fields = payload.split(',');
+        let data = decode(payload, fields.next().unwrap_or_default())?;
+        let algorithm = fields
+            .next()
+            .map(SignatureAlgorithm::parse)
+            .unwrap_or(SignatureAlgorithm::RsaPkcs1);
+        let mut request = SignRequest::new(data, algorithm);
+        for parameter in fields {
+            if let Some(hash_algorithm) = parameter.strip_prefix(HASH_ALGORITHM) {
+                request.hash_algorithm = Some(hash_algorithm.to_string());
+            } else if let Some(salt_length) = parameter.strip_prefix(SALT_LENGTH) {
+                request.salt_length = Some(salt_length.to_string());
+            }
+        }
+        Ok(request)
+    }
+
+    /// Parses the payload of a legacy `>RSA_SIGN` notification, which is always answered with
+    /// `rsa-sig` and a PKCS#1 v1.5 signature
+    pub fn parse_rsa_sign(payload: &str) -> Result<SignRequest> {
+        let mut request = SignRequest::new(decode(payload, payload)?, SignatureAlgorithm::RsaPkcs1);
+        request.legacy = true;
+        Ok(request)
+    }
+
+    /// The decoded data to sign, usually a digest
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn algorithm(&self) -> &SignatureAlgorithm {
+        &self.algorithm
+    }
+
+    /// The `hashalg` parameter, e.g. `SHA256`, naming the hash the data was computed with
+    pub fn hash_algorithm(&self) -> Option<&str> {
+        self.hash_algorithm.as_deref()
+    }
+
+    /// The `saltlen` parameter of PSS signatures, e.g. `digest` or `max`
+    pub fn salt_length(&self) -> Option<&str> {
+        self.salt_length.as_deref()
+    }
+
+    /// Whether the request came from a `>RSA_SIGN` notification
+    pub fn is_legacy(&self) -> bool {
+        self.legacy
+    }
+
+    /// The command answering the request: `rsa-sig` for legacy requests, `pk-sig` otherwise
+    pub(crate) fn reply_command(&self) -> &'static str {
+        if self.legacy {
+            "rsa-sig"
+        } else {
+            "pk-sig"
+        }
+    }
+}
+
+/// Signs data with a key kept outside of openvpn, e.g. in a signing service or a hardware token
+pub trait Signer {
+    /// Returns the signature of the request's data with the requested algorithm
+    fn sign(&mut self, request: &SignRequest) -> Result<Vec<u8>>;
+}
+
+/// Encodes a signature as the base64 lines sent after `pk-sig` or `rsa-sig`
+pub(crate) fn encode_signature(signature: &[u8]) -> Vec<String> {
+    let encoded = STANDARD.encode(signature);
+    encoded
+        .as_bytes()
+        .chunks(SIGNATURE_LINE_LENGTH)
+        .map(|line| String::from_utf8_lossy(line).into_owned())
+        .collect()
+}
+
+fn decode(payload: &str, data: &str) -> Result<Vec<u8>> {
+    STANDARD
+        .decode(data)
+        .map_err(|_| OpenvpnError::MalformedResponse(payload.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_pk_sign_with_parameters() {
+        let request =
+            SignRequest::parse_pk_sign("AQID,RSA_PKCS1_PSS_PADDING,hashalg=SHA256,saltlen=digest")
+                .unwrap();
+
+        assert_eq!(&[1, 2, 3], request.data());
+        assert_eq!(&SignatureAlgorithm::RsaPss, request.algorithm());
+        assert_eq!(Some("SHA256"), request.hash_algorithm());
+        assert_eq!(Some("digest"), request.salt_length());
+        assert_eq!("pk-sig", request.reply_command());
+    }
+
+    #[test]
+    fn test_parse_pk_sign_defaults_to_pkcs1() {
+        let request = SignRequest::parse_pk_sign("AQID").unwrap();
+
+        assert_eq!(&SignatureAlgorithm::RsaPkcs1, request.algorithm());
+        assert_eq!(None, request.hash_algorithm());
+    }
+
+    #[test]
+    fn test_parse_rsa_sign() {
+        let request = SignRequest::parse_rsa_sign("AQID").unwrap();
+
+        assert!(request.is_legacy());
+        assert_eq!("rsa-sig", request.reply_command());
+    }
+
+    #[test]
+    fn test_parse_malformed_sign_request() {
+        assert!(SignRequest::parse_pk_sign("not base64!,ECDSA").is_err());
+    }
+
+    #[test]
+    fn test_encode_signature_in_lines() {
+        let lines = encode_signature(&[0xff; 60]);
+
+        assert_eq!(2, lines.len());
+        assert_eq!(64, lines[0].len());
+        assert_eq!(STANDARD.encode([0xff; 60]), lines.concat());
+    }
+}
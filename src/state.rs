@@ -0,0 +1,154 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use crate::get_utc_start_time;
+use chrono::prelude::{DateTime, Utc};
+use std::net::IpAddr;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// An openvpn state change, as returned by the `state` command or sent in a `>STATE`
+/// notification, e.g. `1547913893,CONNECTED,SUCCESS,10.8.0.6,198.51.100.1,1194,,`
+pub struct StateRecord {
+    timestamp: DateTime<Utc>,
+    name: String,
+    description: String,
+    local_ip: Option<IpAddr>,
+    remote_ip: Option<IpAddr>,
+    remote_port: Option<u16>,
+    local_address: Option<IpAddr>,
+    local_port: Option<u16>,
+    local_ipv6: Option<IpAddr>,
+}
+
+impl StateRecord {
+    pub fn new(timestamp: DateTime<Utc>, name: String, description: String) -> StateRecord {
+        StateRecord {
+            timestamp,
+            name,
+            description,
+            local_ip: None,
+            remote_ip: None,
+            remote_port: None,
+            local_address: None,
+            local_port: None,
+            local_ipv6: None,
+        }
+    }
+
+    /// Parses a state line. Only the time and the state name are required, the other fields
+    /// are empty or missing depending on the state and the openvpn version
+    pub fn parse(line: &str) -> Result<StateRecord> {
+        let fields: Vec<_> = line.split(',').collect();
+        if fields.len() < 2 {
+            return Err(OpenvpnError::MalformedResponse(line.to_string()));
+        }
+        let field = |index: usize| {
+            fields
+                .get(index)
+                .map(|f| f.trim())
+                .filter(|f| !f.is_empty())
+        };
+        let address = |index: usize| -> Result<Option<IpAddr>> {
+            Ok(match field(index) {
+                Some(address) => Some(address.parse()?),
+                None => None,
+            })
+        };
+        let port = |index: usize| -> Result<Option<u16>> {
+            Ok(match field(index) {
+                Some(port) => Some(port.parse()?),
+                None => None,
+            })
+        };
+        Ok(StateRecord {
+            timestamp: get_utc_start_time(fields[0].parse()?)?,
+            name: fields[1].to_string(),
+            description: field(2).unwrap_or_default().to_string(),
+            local_ip: address(3)?,
+            remote_ip: address(4)?,
+            remote_port: port(5)?,
+            local_address: address(6)?,
+            local_port: port(7)?,
+            local_ipv6: address(8)?,
+        })
+    }
+
+    /// When the state was entered
+    pub fn timestamp(&self) -> &DateTime<Utc> {
+        &self.timestamp
+    }
+
+    /// The state, e.g. `CONNECTING`, `WAIT`, `AUTH`, `GET_CONFIG`, `CONNECTED` or `RECONNECTING`
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Details on the state, e.g. `SUCCESS` or the reason of a reconnection
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// The local tunnel address
+    pub fn local_ip(&self) -> Option<&IpAddr> {
+        self.local_ip.as_ref()
+    }
+
+    /// The address of the remote server
+    pub fn remote_ip(&self) -> Option<&IpAddr> {
+        self.remote_ip.as_ref()
+    }
+
+    pub fn remote_port(&self) -> Option<u16> {
+        self.remote_port
+    }
+
+    /// The local address the connection goes out from
+    pub fn local_address(&self) -> Option<&IpAddr> {
+        self.local_address.as_ref()
+    }
+
+    pub fn local_port(&self) -> Option<u16> {
+        self.local_port
+    }
+
+    /// The local IPv6 tunnel address
+    pub fn local_ipv6(&self) -> Option<&IpAddr> {
+        self.local_ipv6.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_client_connected_state() {
+        let state = StateRecord::parse(
+            "1547913893,CONNECTED,SUCCESS,10.8.0.6,198.51.100.1,1194,192.168.1.2,51234,fd00::1000",
+        )
+        .unwrap();
+
+        assert_eq!(&get_utc_start_time(1547913893).unwrap(), state.timestamp());
+        assert_eq!("CONNECTED", state.name());
+        assert_eq!("SUCCESS", state.description());
+        assert_eq!("10.8.0.6", state.local_ip().unwrap().to_string());
+        assert_eq!("198.51.100.1", state.remote_ip().unwrap().to_string());
+        assert_eq!(Some(1194), state.remote_port());
+        assert_eq!(Some(51234), state.local_port());
+        assert_eq!("fd00::1000", state.local_ipv6().unwrap().to_string());
+    }
+
+    #[test]
+    fn test_parse_state_with_empty_fields() {
+        let state = StateRecord::parse("1547913893,RECONNECTING,ping-restart,,,,").unwrap();
+
+        assert_eq!("ping-restart", state.description());
+        assert_eq!(None, state.local_ip());
+        assert_eq!(None, state.remote_port());
+    }
+
+    #[test]
+    fn test_parse_malformed_state() {
+        assert!(StateRecord::parse("CONNECTED").is_err());
+        assert!(StateRecord::parse("1547913893,CONNECTED,SUCCESS,not-an-ip").is_err());
+    }
+}
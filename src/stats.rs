@@ -0,0 +1,75 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// The global server statistics returned by `load-stats`
+pub struct LoadStats {
+    clients: u64,
+    bytes_in: f64,
+    bytes_out: f64,
+}
+
+impl LoadStats {
+    pub fn new(clients: u64, bytes_in: f64, bytes_out: f64) -> LoadStats {
+        LoadStats {
+            clients,
+            bytes_in,
+            bytes_out,
+        }
+    }
+
+    /// Parses the success message of `load-stats`, e.g. `nclients=2,bytesin=1024,bytesout=2048`
+    pub fn parse(message: &str) -> Result<LoadStats> {
+        let mut clients = None;
+        let mut bytes_in = None;
+        let mut bytes_out = None;
+        for field in message.split(',') {
+            match field.trim().split_once('=') {
+                Some(("nclients", value)) => clients = Some(value.parse()?),
+                Some(("bytesin", value)) => bytes_in = Some(value.parse()?),
+                Some(("bytesout", value)) => bytes_out = Some(value.parse()?),
+                _ => {}
+            }
+        }
+        match (clients, bytes_in, bytes_out) {
+            (Some(clients), Some(bytes_in), Some(bytes_out)) => {
+                Ok(LoadStats::new(clients, bytes_in, bytes_out))
+            }
+            _ => Err(OpenvpnError::MalformedResponse(message.to_string())),
+        }
+    }
+
+    /// Number of connected clients
+    pub fn clients(&self) -> u64 {
+        self.clients
+    }
+
+    /// Bytes received by the server
+    pub fn bytes_in(&self) -> f64 {
+        self.bytes_in
+    }
+
+    /// Bytes sent by the server
+    pub fn bytes_out(&self) -> f64 {
+        self.bytes_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_load_stats() {
+        assert_eq!(
+            LoadStats::new(2, 1024.0, 2048.0),
+            LoadStats::parse("nclients=2,bytesin=1024,bytesout=2048").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_malformed_load_stats() {
+        assert!(LoadStats::parse("nclients=2,bytesin=1024").is_err());
+        assert!(LoadStats::parse("nclients=two,bytesin=1024,bytesout=2048").is_err());
+    }
+}
@@ -0,0 +1,104 @@
+use crate::error::{OpenvpnError, OpenvpnResult as Result};
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+use std::time::Duration;
+
+const PASSWORD_PROMPT: &[u8] = b"ENTER PASSWORD:";
+const PASSWORD_ACCEPTED: &str = "SUCCESS:";
+const MANAGEMENT_REALM: &str = "management";
+
+/// A connection to the management interface, over TCP or a unix socket
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+    pub(crate) fn try_clone(&self) -> io::Result<Stream> {
+        match *self {
+            Stream::Tcp(ref stream) => stream.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(ref stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+
+    pub(crate) fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(ref stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    pub(crate) fn read_timeout(&self) -> io::Result<Option<Duration>> {
+        match *self {
+            Stream::Tcp(ref stream) => stream.read_timeout(),
+            #[cfg(unix)]
+            Stream::Unix(ref stream) => stream.read_timeout(),
+        }
+    }
+
+    /// Answers the `ENTER PASSWORD:` prompt sent by a management interface started with a
+    /// password file. The prompt is not terminated by a newline, so it is read unbuffered
+    pub(crate) fn authenticate(&mut self, password: &str) -> Result<()> {
+        let mut prompt = vec![0; PASSWORD_PROMPT.len()];
+        self.read_exact(&mut prompt)?;
+        if prompt != PASSWORD_PROMPT {
+            return Err(OpenvpnError::MalformedResponse(
+                String::from_utf8_lossy(&prompt).into_owned(),
+            ));
+        }
+        self.write_all(password.as_bytes())?;
+        self.write_all(b"\n")?;
+        let reply = self.read_unbuffered_line()?;
+        if !reply.trim_start().starts_with(PASSWORD_ACCEPTED) {
+            return Err(OpenvpnError::VerificationFailed(
+                MANAGEMENT_REALM.to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    fn read_unbuffered_line(&mut self) -> Result<String> {
+        let mut line = Vec::new();
+        let mut byte = [0; 1];
+        loop {
+            if self.read(&mut byte)? == 0 || byte[0] == b'\n' {
+                break;
+            }
+            line.push(byte[0]);
+        }
+        Ok(String::from_utf8_lossy(&line).trim_end().to_string())
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut stream) => stream.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match *self {
+            Stream::Tcp(ref mut stream) => stream.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match *self {
+            Stream::Tcp(ref mut stream) => stream.flush(),
+            #[cfg(unix)]
+            Stream::Unix(ref mut stream) => stream.flush(),
+        }
+    }
+}
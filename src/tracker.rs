@@ -0,0 +1,415 @@
+use crate::client::Client;
+use crate::client_env::ClientEnv;
+use crate::notification::{ClientEvent, ClientEventKind, Notification};
+use chrono::prelude::{DateTime, Utc};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum SessionState {
+    /// `>CLIENT:CONNECT` was received, the client is waiting for authentication
+    Authenticating,
+    /// `>CLIENT:ESTABLISHED` was received
+    Established,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// A client session which has not disconnected yet
+pub struct ActiveSession {
+    cid: u64,
+    state: SessionState,
+    connected_since: DateTime<Utc>,
+    env: ClientEnv,
+}
+
+impl ActiveSession {
+    /// Client ID
+    pub fn cid(&self) -> u64 {
+        self.cid
+    }
+
+    pub fn state(&self) -> SessionState {
+        self.state
+    }
+
+    /// Date time the client connected at
+    pub fn connected_since(&self) -> &DateTime<Utc> {
+        &self.connected_since
+    }
+
+    /// The environment of the client notifications for this session, later notifications
+    /// overriding earlier ones
+    pub fn env(&self) -> &ClientEnv {
+        &self.env
+    }
+
+    /// Common Name
+    pub fn common_name(&self) -> Option<&str> {
+        self.env.common_name()
+    }
+
+    /// Remote IP address
+    pub fn real_address(&self) -> Option<IpAddr> {
+        self.env.trusted_ip().or_else(|| self.env.untrusted_ip())
+    }
+
+    /// Virtual IPv4 address
+    pub fn virtual_address(&self) -> Option<IpAddr> {
+        self.env.ifconfig_pool_remote_ip()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+/// Accounting for a finished client session, built from the `>CLIENT:DISCONNECT` environment
+pub struct SessionRecord {
+    pub(crate) cid: Option<u64>,
+    pub(crate) common_name: Option<String>,
+    pub(crate) username: Option<String>,
+    pub(crate) real_address: Option<IpAddr>,
+    pub(crate) real_port: Option<u16>,
+    pub(crate) virtual_address: Option<IpAddr>,
+    pub(crate) virtual_address6: Option<IpAddr>,
+    pub(crate) connected_since: DateTime<Utc>,
+    pub(crate) disconnected_at: DateTime<Utc>,
+    pub(crate) bytes_received: f64,
+    pub(crate) bytes_sent: f64,
+}
+
+impl SessionRecord {
+    fn from_disconnect(
+        cid: u64,
+        env: &ClientEnv,
+        session: Option<&ActiveSession>,
+        now: DateTime<Utc>,
+    ) -> SessionRecord {
+        let duration = env.time_duration().map(to_chrono_duration);
+        let known_env = session.map(|s| &s.env);
+        let lookup = |name: &str| {
+            env.get(name)
+                .or_else(|| known_env.and_then(|e| e.get(name)))
+                .map(str::to_owned)
+        };
+        let connected_since = env
+            .connected_since()
+            .or_else(|| session.map(|s| &s.connected_since))
+            .cloned();
+        let disconnected_at = match (connected_since, duration) {
+            (Some(since), Some(duration)) => since + duration,
+            _ => now,
+        };
+        let connected_since = connected_since
+            .or_else(|| duration.map(|d| disconnected_at - d))
+            .unwrap_or(disconnected_at);
+        SessionRecord {
+            cid: Some(cid),
+            common_name: lookup("common_name"),
+            username: lookup("username"),
+            real_address: env
+                .trusted_ip()
+                .or_else(|| env.untrusted_ip())
+                .or_else(|| session.and_then(ActiveSession::real_address)),
+            real_port: env
+                .trusted_port()
+                .or_else(|| env.untrusted_port())
+                .or_else(|| known_env.and_then(ClientEnv::untrusted_port)),
+            virtual_address: env
+                .ifconfig_pool_remote_ip()
+                .or_else(|| known_env.and_then(ClientEnv::ifconfig_pool_remote_ip)),
+            virtual_address6: env
+                .ifconfig_pool_remote_ip6()
+                .or_else(|| known_env.and_then(ClientEnv::ifconfig_pool_remote_ip6)),
+            connected_since,
+            disconnected_at,
+            bytes_received: env.bytes_received().unwrap_or_default(),
+            bytes_sent: env.bytes_sent().unwrap_or_default(),
+        }
+    }
+
+    /// Builds the record of a client which left the status, e.g. as reported by
+    /// [`StatusWatcher`](crate::StatusWatcher). The client ID is missing when the status does not
+    /// list client IDs
+    pub fn from_client(client: &Client, disconnected_at: DateTime<Utc>) -> SessionRecord {
+        SessionRecord {
+            cid: client.cid(),
+            common_name: Some(client.name().to_string()),
+            username: None,
+            real_address: client.ip_address().parse().ok(),
+            real_port: None,
+            virtual_address: client.virtual_address(),
+            virtual_address6: None,
+            connected_since: *client.connected_since(),
+            disconnected_at,
+            bytes_received: client.bytes_received(),
+            bytes_sent: client.bytes_sent(),
+        }
+    }
+
+    /// Client ID, missing for the clients of a status without client IDs
+    pub fn cid(&self) -> Option<u64> {
+        self.cid
+    }
+
+    /// Common Name
+    pub fn common_name(&self) -> Option<&str> {
+        self.common_name.as_deref()
+    }
+
+    pub fn username(&self) -> Option<&str> {
+        self.username.as_deref()
+    }
+
+    /// Remote IP address
+    pub fn real_address(&self) -> Option<IpAddr> {
+        self.real_address
+    }
+
+    /// Remote port
+    pub fn real_port(&self) -> Option<u16> {
+        self.real_port
+    }
+
+    /// Virtual IPv4 address
+    pub fn virtual_address(&self) -> Option<IpAddr> {
+        self.virtual_address
+    }
+
+    /// Virtual IPv6 address
+    pub fn virtual_address6(&self) -> Option<IpAddr> {
+        self.virtual_address6
+    }
+
+    /// Date time the client connected at
+    pub fn connected_since(&self) -> &DateTime<Utc> {
+        &self.connected_since
+    }
+
+    /// Date time the client disconnected at
+    pub fn disconnected_at(&self) -> &DateTime<Utc> {
+        &self.disconnected_at
+    }
+
+    pub fn duration(&self) -> Duration {
+        (self.disconnected_at - self.connected_since)
+            .to_std()
+            .unwrap_or_default()
+    }
+
+    /// Bytes received from the client during the session
+    pub fn bytes_received(&self) -> f64 {
+        self.bytes_received
+    }
+
+    /// Bytes sent to the client during the session
+    pub fn bytes_sent(&self) -> f64 {
+        self.bytes_sent
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+/// Keeps a live table of client sessions from the `>CLIENT` notifications and produces a
+/// [`SessionRecord`] for each session that ends
+pub struct SessionTracker {
+    sessions: HashMap<u64, ActiveSession>,
+}
+
+impl SessionTracker {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Updates the table from a notification. Returns the completed record when a client
+    /// disconnects
+    pub fn apply(&mut self, notification: &Notification) -> Option<SessionRecord> {
+        match *notification {
+            Notification::Client(ref event) => self.apply_client_event(event),
+            _ => None,
+        }
+    }
+
+    fn apply_client_event(&mut self, event: &ClientEvent) -> Option<SessionRecord> {
+        let cid = event.cid();
+        let env = event.env().clone();
+        let now = Utc::now();
+        match event.kind() {
+            ClientEventKind::Connect => {
+                let connected_since = env.connected_since().cloned().unwrap_or(now);
+                self.sessions.insert(
+                    cid,
+                    ActiveSession {
+                        cid,
+                        state: SessionState::Authenticating,
+                        connected_since,
+                        env,
+                    },
+                );
+                None
+            }
+            ClientEventKind::Reauth => {
+                if let Some(session) = self.sessions.get_mut(&cid) {
+                    session.env = merge_env(&session.env, env);
+                }
+                None
+            }
+            ClientEventKind::Established => {
+                let connected_since = env.connected_since().cloned();
+                let session = self.sessions.entry(cid).or_insert_with(|| ActiveSession {
+                    cid,
+                    state: SessionState::Established,
+                    connected_since: connected_since.unwrap_or(now),
+                    env: env.clone(),
+                });
+                session.state = SessionState::Established;
+                session.env = merge_env(&session.env, env);
+                None
+            }
+            ClientEventKind::Disconnect => {
+                let session = self.sessions.remove(&cid);
+                Some(SessionRecord::from_disconnect(
+                    cid,
+                    event.env(),
+                    session.as_ref(),
+                    now,
+                ))
+            }
+        }
+    }
+
+    /// The sessions which have not disconnected yet
+    pub fn sessions(&self) -> impl Iterator<Item = &ActiveSession> {
+        self.sessions.values()
+    }
+
+    pub fn session(&self, cid: u64) -> Option<&ActiveSession> {
+        self.sessions.get(&cid)
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+/// Variables of the newer environment take precedence, the others are kept from the older one
+fn merge_env(older: &ClientEnv, newer: ClientEnv) -> ClientEnv {
+    let mut vars = older.vars().clone();
+    vars.extend(newer.vars().clone());
+    ClientEnv::from_vars(vars).unwrap_or(newer)
+}
+
+fn to_chrono_duration(duration: Duration) -> chrono::Duration {
+    chrono::Duration::from_std(duration).unwrap_or_else(|_| chrono::Duration::zero())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn client_event(kind: ClientEventKind, cid: u64, env: &[&str]) -> Notification {
+        let env = ClientEnv::parse(env.iter().cloned()).unwrap();
+        Notification::Client(ClientEvent::new(kind, cid, None, env))
+    }
+
+    #[test]
+    fn test_session_lifecycle() {
+        let mut tracker = SessionTracker::new();
+
+        let connect = client_event(
+            ClientEventKind::Connect,
+            1,
+            &[
+                ">CLIENT:ENV,common_name=test-client",
+                ">CLIENT:ENV,untrusted_ip=192.168.0.3",
+                ">CLIENT:ENV,untrusted_port=41954",
+                ">CLIENT:ENV,time_unix=1546277714",
+            ],
+        );
+        assert_eq!(None, tracker.apply(&connect));
+        assert_eq!(
+            SessionState::Authenticating,
+            tracker.session(1).unwrap().state()
+        );
+
+        let established = client_event(
+            ClientEventKind::Established,
+            1,
+            &[
+                ">CLIENT:ENV,common_name=test-client",
+                ">CLIENT:ENV,trusted_ip=192.168.0.3",
+                ">CLIENT:ENV,ifconfig_pool_remote_ip=10.8.0.2",
+            ],
+        );
+        assert_eq!(None, tracker.apply(&established));
+        let session = tracker.session(1).unwrap();
+        assert_eq!(SessionState::Established, session.state());
+        assert_eq!(1_546_277_714, session.connected_since().timestamp());
+        assert_eq!(Some("10.8.0.2".parse().unwrap()), session.virtual_address());
+
+        let disconnect = client_event(
+            ClientEventKind::Disconnect,
+            1,
+            &[
+                ">CLIENT:ENV,bytes_received=100",
+                ">CLIENT:ENV,bytes_sent=200",
+                ">CLIENT:ENV,time_duration=60",
+            ],
+        );
+        let record = tracker.apply(&disconnect).expect("session record");
+        assert!(tracker.is_empty());
+        assert_eq!(Some("test-client"), record.common_name());
+        assert_eq!(Some("192.168.0.3".parse().unwrap()), record.real_address());
+        assert_eq!(Some(41954), record.real_port());
+        assert_eq!(Some("10.8.0.2".parse().unwrap()), record.virtual_address());
+        assert_eq!(1_546_277_714, record.connected_since().timestamp());
+        assert_eq!(1_546_277_774, record.disconnected_at().timestamp());
+        assert_eq!(Duration::from_secs(60), record.duration());
+        assert_eq!(100.0, record.bytes_received());
+        assert_eq!(200.0, record.bytes_sent());
+    }
+
+    #[test]
+    fn test_disconnect_of_unknown_session() {
+        let mut tracker = SessionTracker::new();
+
+        let disconnect = client_event(
+            ClientEventKind::Disconnect,
+            4,
+            &[
+                ">CLIENT:ENV,common_name=test-client",
+                ">CLIENT:ENV,bytes_received=100",
+                ">CLIENT:ENV,bytes_sent=200",
+                ">CLIENT:ENV,time_duration=30",
+            ],
+        );
+        let record = tracker.apply(&disconnect).expect("session record");
+
+        assert_eq!(Some(4), record.cid());
+        assert_eq!(Some("test-client"), record.common_name());
+        assert_eq!(Duration::from_secs(30), record.duration());
+    }
+
+    #[test]
+    fn test_established_without_connect_is_tracked() {
+        let mut tracker = SessionTracker::new();
+
+        let established = client_event(
+            ClientEventKind::Established,
+            2,
+            &[">CLIENT:ENV,common_name=test-client"],
+        );
+        tracker.apply(&established);
+
+        assert_eq!(1, tracker.len());
+        assert_eq!(
+            Some("test-client"),
+            tracker.session(2).unwrap().common_name()
+        );
+    }
+}
@@ -0,0 +1,325 @@
+use crate::client::Client;
+use crate::diff::ClientChange;
+use crate::{CommandManager, EventManager, Status};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use std::time::Duration;
+
+const DEFAULT_INTERVAL: Duration = Duration::from_secs(10);
+const DEFAULT_MAX_BACKOFF: Duration = Duration::from_secs(300);
+const DEFAULT_UNREACHABLE_AFTER: u32 = 3;
+
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
+/// A change seen by a [`StatusWatcher`] between two polls
+pub enum WatchEvent {
+    /// A client appeared in the status. Every client of the first status is reported
+    Connected(Client),
+    /// A client left the status, with the last byte counts seen for it
+    Disconnected(Client),
+    /// The byte counts or the address of a client changed
+    Updated(ClientChange),
+    /// The status could not be read several times in a row, with the last error
+    Unreachable(String),
+    /// The status could be read again after [`WatchEvent::Unreachable`]
+    Recovered,
+}
+
+type Callback = Box<dyn FnMut(&WatchEvent) + Send>;
+
+/// Polls the status of the server and reports the clients which connected, disconnected or
+/// changed in between, for servers whose management interface cannot be kept open by a
+/// [`Session`](crate::Session). Repeated errors slow the polling down up to a maximum delay
+pub struct StatusWatcher<M = CommandManager> {
+    manager: M,
+    interval: Duration,
+    jitter: f64,
+    max_backoff: Duration,
+    unreachable_after: u32,
+    callbacks: Vec<Callback>,
+    senders: Vec<Sender<WatchEvent>>,
+    previous: Option<Status>,
+    failures: u32,
+    unreachable: bool,
+    random: RandomState,
+    polls: u64,
+}
+
+impl<M: EventManager> StatusWatcher<M> {
+    pub fn new(manager: M) -> StatusWatcher<M> {
+        StatusWatcher {
+            manager,
+            interval: DEFAULT_INTERVAL,
+            jitter: 0.0,
+            max_backoff: DEFAULT_MAX_BACKOFF,
+            unreachable_after: DEFAULT_UNREACHABLE_AFTER,
+            callbacks: Vec::new(),
+            senders: Vec::new(),
+            previous: None,
+            failures: 0,
+            unreachable: false,
+            random: RandomState::new(),
+            polls: 0,
+        }
+    }
+
+    /// the delay between two polls. Default value is 10 seconds
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// the fraction of the delay added or removed at random, e.g. 0.1 for ±10%, so that
+    /// several watchers do not poll at the same time. A value that is not finite means no
+    /// jitter. Default value is no jitter
+    pub fn jitter(&mut self, jitter: f64) -> &mut Self {
+        self.jitter = if jitter.is_finite() {
+            jitter.clamp(0.0, 1.0)
+        } else {
+            0.0
+        };
+        self
+    }
+
+    /// the longest delay between two polls while errors repeat, the delay doubling after each
+    /// error. Default value is 5 minutes
+    pub fn max_backoff(&mut self, max_backoff: Duration) -> &mut Self {
+        self.max_backoff = max_backoff;
+        self
+    }
+
+    /// the number of errors in a row after which [`WatchEvent::Unreachable`] is sent. Default
+    /// value is 3
+    pub fn unreachable_after(&mut self, errors: u32) -> &mut Self {
+        self.unreachable_after = errors.max(1);
+        self
+    }
+
+    /// Calls the callback with each event
+    pub fn on_event<F: FnMut(&WatchEvent) + Send + 'static>(&mut self, callback: F) -> &mut Self {
+        self.callbacks.push(Box::new(callback));
+        self
+    }
+
+    /// Returns a channel receiving each event. The channel is dropped once its receiver is
+    pub fn subscribe(&mut self) -> Receiver<WatchEvent> {
+        let (sender, receiver) = mpsc::channel();
+        self.senders.push(sender);
+        receiver
+    }
+
+    /// The last status read
+    pub fn status(&self) -> Option<&Status> {
+        self.previous.as_ref()
+    }
+
+    /// Errors in a row since the status was last read
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// Reads the status once, sends the events and returns the delay before the next poll
+    pub fn poll(&mut self) -> Duration {
+        self.polls += 1;
+        match self.manager.get_status() {
+            Ok(status) => {
+                self.failures = 0;
+                if self.unreachable {
+                    self.unreachable = false;
+                    self.emit(WatchEvent::Recovered);
+                }
+                let events = match self.previous {
+                    Some(ref previous) => {
+                        let diff = status.diff(previous);
+                        let mut events: Vec<_> = diff
+                            .disconnected()
+                            .iter()
+                            .cloned()
+                            .map(WatchEvent::Disconnected)
+                            .collect();
+                        events.extend(diff.connected().iter().cloned().map(WatchEvent::Connected));
+                        events.extend(diff.changed().iter().cloned().map(WatchEvent::Updated));
+                        events
+                    }
+                    None => status
+                        .clients()
+                        .iter()
+                        .cloned()
+                        .map(WatchEvent::Connected)
+                        .collect(),
+                };
+                self.previous = Some(status);
+                for event in events {
+                    self.emit(event);
+                }
+                self.jittered(self.interval)
+            }
+            Err(e) => {
+                self.failures += 1;
+                if self.failures >= self.unreachable_after && !self.unreachable {
+                    self.unreachable = true;
+                    self.emit(WatchEvent::Unreachable(e.to_string()));
+                }
+                let backoff = self
+                    .interval
+                    .checked_mul(1 << (self.failures - 1).min(16))
+                    .map_or(self.max_backoff, |delay| delay.min(self.max_backoff));
+                self.jittered(backoff)
+            }
+        }
+    }
+
+    /// Polls forever, e.g. on a thread of its own
+    pub fn run(&mut self) -> ! {
+        loop {
+            let delay = self.poll();
+            thread::sleep(delay);
+        }
+    }
+
+    fn emit(&mut self, event: WatchEvent) {
+        for callback in &mut self.callbacks {
+            callback(&event);
+        }
+        self.senders
+            .retain(|sender| sender.send(event.clone()).is_ok());
+    }
+
+    fn jittered(&self, delay: Duration) -> Duration {
+        if self.jitter == 0.0 {
+            return delay;
+        }
+        let mut hasher = self.random.build_hasher();
+        hasher.write_u64(self.polls);
+        // a fraction between -1 and 1
+        let fraction = hasher.finish() as f64 / u64::MAX as f64 * 2.0 - 1.0;
+        delay.mul_f64(1.0 + self.jitter * fraction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{OpenvpnError, OpenvpnResult as Result};
+    use crate::fixtures::{client, status as status_of};
+    use std::collections::VecDeque;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    struct FakeManager {
+        replies: VecDeque<Result<Status>>,
+    }
+
+    impl EventManager for FakeManager {
+        fn get_status(&mut self) -> Result<Status> {
+            self.replies.pop_front().unwrap()
+        }
+    }
+
+    fn status(clients: &[(&str, u64, f64)]) -> Result<Status> {
+        Ok(status_of(
+            clients
+                .iter()
+                .map(|&(name, cid, bytes)| {
+                    client(name, "1.2.3.4", 1_700_000_000, bytes, bytes).with_cid(cid)
+                })
+                .collect(),
+        ))
+    }
+
+    fn refused() -> Result<Status> {
+        Err(OpenvpnError::Io(io::Error::from(
+            io::ErrorKind::ConnectionRefused,
+        )))
+    }
+
+    fn watcher(replies: Vec<Result<Status>>) -> StatusWatcher<FakeManager> {
+        StatusWatcher::new(FakeManager {
+            replies: replies.into(),
+        })
+    }
+
+    #[test]
+    fn test_client_events() {
+        let mut watcher = watcher(vec![
+            status(&[("alice", 1, 10.0), ("bob", 2, 20.0)]),
+            status(&[("alice", 1, 15.0), ("carol", 3, 0.0)]),
+        ]);
+        let events = watcher.subscribe();
+        let names = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&names);
+        watcher.on_event(move |event| {
+            if let WatchEvent::Connected(ref client) = *event {
+                seen.lock().unwrap().push(client.name().to_string());
+            }
+        });
+
+        watcher.poll();
+        watcher.poll();
+
+        let events: Vec<_> = events.try_iter().collect();
+        assert_eq!(5, events.len());
+        assert!(matches!(events[2], WatchEvent::Disconnected(ref c) if c.name() == "bob"));
+        assert!(matches!(events[3], WatchEvent::Connected(ref c) if c.name() == "carol"));
+        assert!(
+            matches!(events[4], WatchEvent::Updated(ref change) if change.bytes_received_delta() == 5.0)
+        );
+        assert_eq!(vec!["alice", "bob", "carol"], *names.lock().unwrap());
+    }
+
+    #[test]
+    fn test_backoff_and_recovery() {
+        let mut watcher = watcher(vec![
+            status(&[("alice", 1, 10.0)]),
+            refused(),
+            refused(),
+            refused(),
+            refused(),
+            status(&[]),
+        ]);
+        watcher
+            .interval(Duration::from_secs(10))
+            .max_backoff(Duration::from_secs(60));
+        let events = watcher.subscribe();
+
+        let delays: Vec<_> = (0..6).map(|_| watcher.poll().as_secs()).collect();
+
+        assert_eq!(vec![10, 10, 20, 40, 60, 10], delays);
+        let events: Vec<_> = events.try_iter().collect();
+        assert_eq!(4, events.len());
+        assert!(matches!(events[1], WatchEvent::Unreachable(_)));
+        assert_eq!(WatchEvent::Recovered, events[2]);
+        assert!(matches!(events[3], WatchEvent::Disconnected(ref c) if c.name() == "alice"));
+        assert_eq!(0, watcher.failures());
+    }
+
+    #[test]
+    fn test_jitter() {
+        let replies = (0..20).map(|_| status(&[])).collect();
+        let mut watcher = watcher(replies);
+        watcher.interval(Duration::from_secs(10)).jitter(0.2);
+
+        let delays: Vec<_> = (0..20).map(|_| watcher.poll()).collect();
+
+        assert!(delays
+            .iter()
+            .all(|delay| *delay >= Duration::from_secs(8) && *delay <= Duration::from_secs(12)));
+        assert!(delays.iter().any(|delay| *delay != delays[0]));
+    }
+
+    #[test]
+    fn test_jitter_not_finite() {
+        for jitter in &[f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+            let mut watcher = watcher(vec![status(&[])]);
+            watcher.interval(Duration::from_secs(10)).jitter(*jitter);
+
+            assert_eq!(Duration::from_secs(10), watcher.poll());
+        }
+    }
+}
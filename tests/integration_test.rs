@@ -1,6 +1,13 @@
 extern crate openvpn_management;
 use chrono::prelude::{DateTime, TimeZone, Utc};
-use openvpn_management::{Client, EventManager, OpenvpnError, Status};
+use openvpn_management::{
+    AddressMap, AddressPool, AuthRetry, ByteCount, CertificateProvider, CertificateRequest, Client,
+    CredentialProvider, Credentials, DynamicChallenge, EventManager, InfoMessageHandler,
+    Notification, OpenvpnError, PasswordRequest, PoolAllocator, PromptHandler, PromptRequest,
+    ProxyAction, ProxyPolicy, ProxyRequest, Quota, QuotaEnforcer, QuotaEvent, QuotaPeriod,
+    RemoteAction, RemotePolicy, RemoteRequest, SignRequest, Signal, SignatureAlgorithm, Signer,
+    StaticChallenge, Status, StatusWatcher, VirtualAddress, WatchEvent, WebAuthFlags,
+};
 use std::io::{BufRead, BufReader, Write};
 use std::net::TcpListener;
 use std::thread;
@@ -30,14 +37,74 @@ fn setup_tcp_server(
     })
 }
 
+/// Serves a single session: for each step, waits for the expected command lines (if any) and
+/// writes the response
+fn setup_session_server(
+    port: u16,
+    script: Vec<(&'static str, &'static str)>,
+) -> thread::JoinHandle<()> {
+    let listener = TcpListener::bind(format!("localhost:{}", port)).unwrap();
+    thread::spawn(move || {
+        let mut stream = listener.accept().unwrap().0;
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        for (expected_command, response) in script {
+            let mut output = String::new();
+            for _ in expected_command.lines() {
+                reader.read_line(&mut output).unwrap();
+            }
+            assert_eq!(expected_command, output);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    })
+}
+
+struct StaticCredentials {
+    auth_token: Option<String>,
+}
+
+impl CredentialProvider for StaticCredentials {
+    fn credentials(
+        &mut self,
+        request: &PasswordRequest,
+    ) -> openvpn_management::Result<Credentials> {
+        assert_eq!("Auth", request.realm());
+        Ok(Credentials::new(
+            Some("alice".to_string()),
+            "pass \"word\"".to_string(),
+        ))
+    }
+
+    fn static_challenge(
+        &mut self,
+        _request: &PasswordRequest,
+        challenge: &StaticChallenge,
+    ) -> openvpn_management::Result<String> {
+        assert_eq!("Please enter PIN", challenge.text());
+        Ok("123456".to_string())
+    }
+
+    fn dynamic_challenge(
+        &mut self,
+        challenge: &DynamicChallenge,
+    ) -> openvpn_management::Result<String> {
+        assert_eq!("Enter OTP", challenge.text());
+        Ok("8675309".to_string())
+    }
+
+    fn auth_token(&mut self, token: &str) {
+        self.auth_token = Some(token.to_string());
+    }
+}
+
 fn new_mock_client(
     name: &'static str,
     ip_address: &'static str,
+    virtual_address: &'static str,
     epoch_seconds: i64,
     bytes_received: f64,
     bytes_sent: f64,
 ) -> Client {
-    let datetime: DateTime<Utc> = Utc.timestamp(epoch_seconds, 0);
+    let datetime: DateTime<Utc> = Utc.timestamp_opt(epoch_seconds, 0).unwrap();
     Client::new(
         name.to_string(),
         ip_address.to_string(),
@@ -45,10 +112,11 @@ fn new_mock_client(
         bytes_received,
         bytes_sent,
     )
+    .with_virtual_address(virtual_address.parse().unwrap())
 }
 
 fn new_mock_status(title: &'static str, epoch_seconds: i64, clients: Vec<Client>) -> Status {
-    let datetime: DateTime<Utc> = Utc.timestamp(epoch_seconds, 0);
+    let datetime: DateTime<Utc> = Utc.timestamp_opt(epoch_seconds, 0).unwrap();
     Status::new(String::from(title), datetime, clients)
 }
 
@@ -107,7 +175,14 @@ fn test_client_details_too_short_in_response() {
 #[test]
 fn test_client_correct_details_in_response() {
     let server_response = "TITLE\ttest-title\r\nTIME\ttimestamp\t1547913893\r\nHEADER\tCLIENT_LIST\r\nCLIENT_LIST\ttest-client\t127.0.0.1:12345\t10.8.0.2\t\t100\t200\tdate-string\t1546277714\r\nEND";
-    let expected_client = new_mock_client("test-client", "127.0.0.1", 1_546_277_714, 100.0, 200.0);
+    let expected_client = new_mock_client(
+        "test-client",
+        "127.0.0.1",
+        "10.8.0.2",
+        1_546_277_714,
+        100.0,
+        200.0,
+    );
     let expected_status = new_mock_status("test-title", 1_547_913_893, vec![expected_client; 1]);
     let handle = setup_tcp_server(5555, server_response, None);
     let mut api = openvpn_management::CommandManagerBuilder::new()
@@ -158,8 +233,22 @@ fn test_missing_timestamp() {
 fn test_multiple_clients_details() {
     let server_response = "TITLE\ttest-title\r\nTIME\ttimestamp\t1547913893\r\nHEADER\tCLIENT_LIST\r\nCLIENT_LIST\ttest-client\t127.0.0.1:12345\t10.8.0.2\t\t100\t200\tdate-string\t1546277714\r\nCLIENT_LIST\ttest-client2\t192.168.0.3:12345\t10.8.0.3\t\t300\t400\tdate-string\t1546277715\r\nEND";
     let expected_clients = vec![
-        new_mock_client("test-client", "127.0.0.1", 1_546_277_714, 100.0, 200.0),
-        new_mock_client("test-client2", "192.168.0.3", 1_546_277_715, 300.0, 400.0),
+        new_mock_client(
+            "test-client",
+            "127.0.0.1",
+            "10.8.0.2",
+            1_546_277_714,
+            100.0,
+            200.0,
+        ),
+        new_mock_client(
+            "test-client2",
+            "192.168.0.3",
+            "10.8.0.3",
+            1_546_277_715,
+            300.0,
+            400.0,
+        ),
     ];
     let expected_status = new_mock_status("test-title", 1_547_913_893, expected_clients);
     let handle = setup_tcp_server(5555, server_response, None);
@@ -183,10 +272,7 @@ fn test_parse_error_in_client_response_bytes_received() {
     let status_response = api.get_status();
     handle.join().unwrap();
     assert!(status_response.is_err());
-    let expected_error = match status_response {
-        Err(OpenvpnError::ParseFloat(_)) => true,
-        _ => false,
-    };
+    let expected_error = matches!(status_response, Err(OpenvpnError::ParseFloat(_)));
 
     assert!(expected_error, "expected unable to parse float");
 }
@@ -201,10 +287,7 @@ fn test_parse_error_in_client_response_bytes_sent() {
     let status_response = api.get_status();
     handle.join().unwrap();
     assert!(status_response.is_err());
-    let expected_error = match status_response {
-        Err(OpenvpnError::ParseFloat(_)) => true,
-        _ => false,
-    };
+    let expected_error = matches!(status_response, Err(OpenvpnError::ParseFloat(_)));
 
     assert!(expected_error, "expected unable to parse float");
 }
@@ -219,10 +302,7 @@ fn test_parse_error_in_client_response_timestamp() {
     let status_response = api.get_status();
     handle.join().unwrap();
     assert!(status_response.is_err());
-    let expected_error = match status_response {
-        Err(OpenvpnError::ParseInt(_)) => true,
-        _ => false,
-    };
+    let expected_error = matches!(status_response, Err(OpenvpnError::ParseInt(_)));
 
     assert!(expected_error, "expected unable to parse int");
 }
@@ -234,10 +314,7 @@ fn test_io_error_on_missing_server() {
         .expect("api build successfully");
     let status_response = api.get_status();
     assert!(status_response.is_err());
-    let expected_error = match status_response {
-        Err(OpenvpnError::Io(_)) => true,
-        _ => false,
-    };
+    let expected_error = matches!(status_response, Err(OpenvpnError::Io(_)));
 
     assert!(expected_error, "expected io error");
 }
@@ -245,7 +322,14 @@ fn test_io_error_on_missing_server() {
 #[test]
 fn test_client_correct_details_within_read_timeout() {
     let server_response = "TITLE\ttest-title\r\nTIME\ttimestamp\t1547913893\r\nHEADER\tCLIENT_LIST\r\nCLIENT_LIST\ttest-client\t127.0.0.1:12345\t10.8.0.2\t\t100\t200\tdate-string\t1546277714\r\nEND";
-    let expected_client = new_mock_client("test-client", "127.0.0.1", 1_546_277_714, 100.0, 200.0);
+    let expected_client = new_mock_client(
+        "test-client",
+        "127.0.0.1",
+        "10.8.0.2",
+        1_546_277_714,
+        100.0,
+        200.0,
+    );
     let expected_status = new_mock_status("test-title", 1_547_913_893, vec![expected_client; 1]);
     let read_latency = READ_TIMEOUT - Duration::from_millis(100);
     let handle = setup_tcp_server(5555, server_response, Some(read_latency));
@@ -272,10 +356,7 @@ fn test_client_error_with_slow_server_response() {
     let status_response = api.get_status();
     handle.join().unwrap();
     assert!(status_response.is_err());
-    let expected_error = match status_response {
-        Err(OpenvpnError::Io(_)) => true,
-        _ => false,
-    };
+    let expected_error = matches!(status_response, Err(OpenvpnError::Io(_)));
 
     assert!(expected_error, "expected io error");
 }
@@ -283,7 +364,14 @@ fn test_client_error_with_slow_server_response() {
 #[test]
 fn test_client_correct_details_within_connect_timeout() {
     let server_response = "TITLE\ttest-title\r\nTIME\ttimestamp\t1547913893\r\nHEADER\tCLIENT_LIST\r\nCLIENT_LIST\ttest-client\t127.0.0.1:12345\t10.8.0.2\t\t100\t200\tdate-string\t1546277714\r\nEND";
-    let expected_client = new_mock_client("test-client", "127.0.0.1", 1_546_277_714, 100.0, 200.0);
+    let expected_client = new_mock_client(
+        "test-client",
+        "127.0.0.1",
+        "10.8.0.2",
+        1_546_277_714,
+        100.0,
+        200.0,
+    );
     let expected_status = new_mock_status("test-title", 1_547_913_893, vec![expected_client; 1]);
     let handle = setup_tcp_server(5555, server_response, None);
     let mut api = openvpn_management::CommandManagerBuilder::new()
@@ -306,10 +394,887 @@ fn test_client_error_slow_connection() {
         .expect("api build successfully");
     let status_response = api.get_status();
     assert!(status_response.is_err());
-    let expected_error = match status_response {
-        Err(OpenvpnError::Io(_)) => true,
-        _ => false,
-    };
+    let expected_error = matches!(status_response, Err(OpenvpnError::Io(_)));
 
     assert!(expected_error, "expected io error");
 }
+
+#[test]
+fn test_session_queues_notifications_received_during_command() {
+    let handle = setup_session_server(
+        5556,
+        vec![
+            (
+                "",
+                ">INFO:OpenVPN Management Interface Version 5\r\n>CLIENT:ADDRESS,1,10.8.0.6,1\r\n",
+            ),
+            (
+                "status\n",
+                ">CLIENT:ADDRESS,1,192.168.1.0/24,0\r\nTITLE\ttest-title\r\nTIME\ttimestamp\t1547913893\r\nHEADER\tCLIENT_LIST\r\nEND\r\n",
+            ),
+        ],
+    );
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5556")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    let status = session.get_status().expect("status parsed successfully");
+    assert_eq!(
+        new_mock_status("test-title", 1_547_913_893, Vec::new()),
+        status
+    );
+
+    let mut addresses = AddressMap::new();
+    match session.next_notification() {
+        Ok(Notification::Info(_)) => {}
+        _ => panic!("was expecting the info banner"),
+    }
+    assert!(addresses.apply(&session.next_notification().unwrap()));
+    assert!(addresses.apply(&session.next_notification().unwrap()));
+    handle.join().unwrap();
+
+    assert_eq!(2, addresses.addresses(1).len());
+    assert_eq!(
+        Some(&VirtualAddress::Address("10.8.0.6".parse().unwrap())),
+        addresses.primary(1)
+    );
+    let closed = session.next_notification();
+    assert!(matches!(closed, Err(OpenvpnError::Io(_))));
+}
+
+#[test]
+fn test_session_command_error() {
+    let handle = setup_session_server(
+        5557,
+        vec![("client-kill 42\n", "ERROR: client-kill command failed\r\n")],
+    );
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5557")
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    let response = session.command("client-kill 42");
+    handle.join().unwrap();
+    match response {
+        Err(OpenvpnError::CommandFailed(message)) => {
+            assert_eq!("client-kill command failed", message)
+        }
+        _ => panic!("was expecting command failure"),
+    }
+}
+
+#[test]
+fn test_session_client_auth_with_pool_address() {
+    let handle = setup_session_server(
+        5558,
+        vec![
+            (
+                "",
+                ">CLIENT:CONNECT,1,0\r\n>CLIENT:ENV,common_name=test-client\r\n>CLIENT:ENV,END\r\n",
+            ),
+            (
+                "client-auth 1 0\nifconfig-push 10.8.0.10 255.255.255.0\nEND\n",
+                "SUCCESS: client-auth command succeeded\r\n",
+            ),
+        ],
+    );
+    let mut allocator = PoolAllocator::new();
+    allocator.add_pool(
+        "default",
+        AddressPool::new()
+            .ipv4(
+                "10.8.0.10".parse().unwrap(),
+                "10.8.0.100".parse().unwrap(),
+                24,
+            )
+            .unwrap(),
+    );
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5558")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    let event = match session.next_notification() {
+        Ok(Notification::Client(event)) => event,
+        _ => panic!("was expecting client connect"),
+    };
+    let config = allocator.connect(&event, "default").unwrap();
+    let response = session.client_auth(event.cid(), event.kid().unwrap(), &config);
+    handle.join().unwrap();
+
+    assert!(response.is_ok());
+    assert_eq!(Some(1), allocator.leases()[0].cid());
+}
+
+#[test]
+fn test_session_answers_password_prompts() {
+    let handle = setup_session_server(
+        5559,
+        vec![
+            (
+                "",
+                ">PASSWORD:Need 'Auth' username/password\r\n",
+            ),
+            (
+                "username \"Auth\" \"alice\"\n",
+                "SUCCESS: 'Auth' username entered, but not yet verified\r\n",
+            ),
+            (
+                "password \"Auth\" \"pass \\\"word\\\"\"\n",
+                "SUCCESS: 'Auth' password entered, but not yet verified\r\n>PASSWORD:Auth-Token:SESS_ID_abc\r\n>PASSWORD:Verification Failed: 'Auth'\r\n",
+            ),
+        ],
+    );
+    let mut provider = StaticCredentials { auth_token: None };
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5559")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    let mut responses = Vec::new();
+    for _ in 0..3 {
+        match session.next_notification() {
+            Ok(Notification::Password(password)) => {
+                responses.push(session.respond_to_password(&password, &mut provider))
+            }
+            _ => panic!("was expecting password notification"),
+        }
+    }
+    handle.join().unwrap();
+
+    assert!(responses[0].is_ok());
+    assert!(responses[1].is_ok());
+    assert_eq!(Some("SESS_ID_abc".to_string()), provider.auth_token);
+    match responses[2] {
+        Err(OpenvpnError::VerificationFailed(ref realm)) => assert_eq!("Auth", realm),
+        _ => panic!("was expecting verification failure"),
+    }
+}
+
+struct FixedSigner;
+
+impl Signer for FixedSigner {
+    fn sign(&mut self, request: &SignRequest) -> openvpn_management::Result<Vec<u8>> {
+        assert_eq!(&SignatureAlgorithm::Ecdsa, request.algorithm());
+        assert_eq!(b"digest", request.data());
+        Ok(vec![0xff; 60])
+    }
+}
+
+struct FixedCertificate;
+
+impl CertificateProvider for FixedCertificate {
+    fn certificate(&mut self, request: &CertificateRequest) -> openvpn_management::Result<String> {
+        assert_eq!("pkcs11:subject:CN=client", request.hint());
+        Ok("subject=CN = client\r\n-----BEGIN CERTIFICATE-----\r\nMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAw\r\nMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAw\r\n-----END CERTIFICATE-----\r\n".to_string())
+    }
+}
+
+struct RegionPolicy;
+
+impl RemotePolicy for RegionPolicy {
+    fn select(&mut self, request: &RemoteRequest) -> openvpn_management::Result<RemoteAction> {
+        match request.host() {
+            "us.example.com" => Ok(RemoteAction::Skip(1)),
+            _ => Ok(RemoteAction::Modify("eu2.example.com".to_string(), 443)),
+        }
+    }
+}
+
+struct CorporateProxy;
+
+impl ProxyPolicy for CorporateProxy {
+    fn proxy(&mut self, request: &ProxyRequest) -> openvpn_management::Result<ProxyAction> {
+        match request.protocol() {
+            "TCP" => Ok(ProxyAction::Http {
+                host: "proxy.corp".to_string(),
+                port: 3128,
+                non_cleartext_only: true,
+            }),
+            _ => Ok(ProxyAction::None),
+        }
+    }
+}
+
+#[derive(Default)]
+struct PendingAuth {
+    opened: Vec<String>,
+}
+
+impl InfoMessageHandler for PendingAuth {
+    fn open_url(&mut self, url: &str, flags: &WebAuthFlags) -> openvpn_management::Result<()> {
+        assert!(flags.external());
+        self.opened.push(url.to_string());
+        Ok(())
+    }
+
+    fn challenge_text(&mut self, text: &str, echo: bool) -> openvpn_management::Result<String> {
+        assert_eq!("Enter the SMS code", text);
+        assert!(echo);
+        Ok("424242".to_string())
+    }
+}
+
+struct TokenPrompts;
+
+impl PromptHandler for TokenPrompts {
+    fn confirm(&mut self, request: &PromptRequest) -> openvpn_management::Result<bool> {
+        Ok(request.name() == "token-insertion-request")
+    }
+
+    fn input(&mut self, request: &PromptRequest) -> openvpn_management::Result<String> {
+        assert_eq!("Enter the PKCS#11 ID", request.message());
+        Ok("pkcs11:token=\"my token\"".to_string())
+    }
+}
+
+#[test]
+fn test_session_answers_challenges() {
+    let handle = setup_session_server(
+        5560,
+        vec![
+            (
+                "",
+                ">PASSWORD:Need 'Auth' username/password SC:1,Please enter PIN\r\n",
+            ),
+            (
+                "username \"Auth\" \"alice\"\n",
+                "SUCCESS: 'Auth' username entered, but not yet verified\r\n",
+            ),
+            (
+                "password \"Auth\" \"SCRV1:cGFzcyAid29yZCI=:MTIzNDU2\"\n",
+                "SUCCESS: 'Auth' password entered, but not yet verified\r\n>PASSWORD:Verification Failed: 'Auth' ['CRV1:R,E:Om01u7Fh4LrGBS7uh0SWmzwabUiGiW6l:Y3Ix:Enter OTP']\r\n>PASSWORD:Need 'Auth' username/password\r\n",
+            ),
+            (
+                "username \"Auth\" \"cr1\"\n",
+                "SUCCESS: 'Auth' username entered, but not yet verified\r\n",
+            ),
+            (
+                "password \"Auth\" \"CRV1::Om01u7Fh4LrGBS7uh0SWmzwabUiGiW6l::8675309\"\n",
+                "SUCCESS: 'Auth' password entered, but not yet verified\r\n",
+            ),
+        ],
+    );
+    let mut provider = StaticCredentials { auth_token: None };
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5560")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    for _ in 0..3 {
+        match session.next_notification() {
+            Ok(Notification::Password(password)) => session
+                .respond_to_password(&password, &mut provider)
+                .expect("challenge answered"),
+            _ => panic!("was expecting password notification"),
+        }
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_session_answers_need_ok_and_need_str() {
+    let handle = setup_session_server(
+        5561,
+        vec![
+            (
+                "",
+                ">NEED-OK:Need 'token-insertion-request' confirmation MSG:Please insert your token\r\n>NEED-STR:Need 'pkcs11-id-request' input MSG:Enter the PKCS#11 ID\r\n",
+            ),
+            (
+                "needok \"token-insertion-request\" ok\n",
+                "SUCCESS: needok command succeeded\r\n",
+            ),
+            (
+                "needstr \"pkcs11-id-request\" \"pkcs11:token=\\\"my token\\\"\"\n",
+                "SUCCESS: needstr command succeeded\r\n",
+            ),
+        ],
+    );
+    let mut handler = TokenPrompts;
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5561")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    for _ in 0..2 {
+        match session.next_notification() {
+            Ok(Notification::NeedOk(request)) => session
+                .respond_to_need_ok(&request, &mut handler)
+                .expect("needok sent"),
+            Ok(Notification::NeedStr(request)) => session
+                .respond_to_need_str(&request, &mut handler)
+                .expect("needstr sent"),
+            _ => panic!("was expecting need-ok or need-str notification"),
+        }
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_session_answers_pk_sign() {
+    let handle = setup_session_server(
+        5562,
+        vec![
+            ("", ">PK_SIGN:ZGlnZXN0,ECDSA,hashalg=SHA256\r\n"),
+            (
+                "pk-sig\n////////////////////////////////////////////////////////////////\n////////////////\nEND\n",
+                "SUCCESS: pk-sig command succeeded\r\n",
+            ),
+        ],
+    );
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5562")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    match session.next_notification() {
+        Ok(Notification::Sign(request)) => {
+            assert_eq!(Some("SHA256"), request.hash_algorithm());
+            session
+                .respond_to_sign(&request, &mut FixedSigner)
+                .expect("signature sent")
+        }
+        _ => panic!("was expecting sign request"),
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_session_answers_need_certificate() {
+    let handle = setup_session_server(
+        5563,
+        vec![
+            ("", ">NEED-CERTIFICATE:pkcs11:subject:CN=client\r\n"),
+            (
+                "certificate\n-----BEGIN CERTIFICATE-----\nMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAwMDAw\nMDAwMDAwMDAwMDAw\n-----END CERTIFICATE-----\nEND\n",
+                "SUCCESS: certificate command succeeded\r\n",
+            ),
+        ],
+    );
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5563")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    match session.next_notification() {
+        Ok(Notification::NeedCertificate(request)) => session
+            .respond_to_certificate(&request, &mut FixedCertificate)
+            .expect("certificate sent"),
+        _ => panic!("was expecting certificate request"),
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_session_answers_remote() {
+    let handle = setup_session_server(
+        5564,
+        vec![
+            ("", ">REMOTE:us.example.com,1194,udp\r\n"),
+            (
+                "remote SKIP\n",
+                "SUCCESS: remote command succeeded\r\n>REMOTE:eu.example.com,1194,udp\r\n",
+            ),
+            (
+                "remote MOD \"eu2.example.com\" 443\n",
+                "SUCCESS: remote command succeeded\r\n",
+            ),
+        ],
+    );
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5564")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    for _ in 0..2 {
+        match session.next_notification() {
+            Ok(Notification::Remote(request)) => session
+                .respond_to_remote(&request, &mut RegionPolicy)
+                .expect("remote sent"),
+            _ => panic!("was expecting remote request"),
+        }
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_session_answers_proxy() {
+    let handle = setup_session_server(
+        5565,
+        vec![
+            ("", ">PROXY:1,UDP,vpn.example.com\r\n"),
+            (
+                "proxy NONE\n",
+                "SUCCESS: proxy command succeeded\r\n>PROXY:2,TCP,vpn.example.com\r\n",
+            ),
+            (
+                "proxy HTTP \"proxy.corp\" 3128 nct\n",
+                "SUCCESS: proxy command succeeded\r\n",
+            ),
+        ],
+    );
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5565")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    for _ in 0..2 {
+        match session.next_notification() {
+            Ok(Notification::Proxy(request)) => session
+                .respond_to_proxy(&request, &mut CorporateProxy)
+                .expect("proxy sent"),
+            _ => panic!("was expecting proxy request"),
+        }
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_session_lists_remote_entries() {
+    let handle = setup_session_server(
+        5566,
+        vec![
+            ("remote-entry-count\n", "3\r\nEND\r\n"),
+            (
+                "remote-entry-get all\n",
+                "0,vpn.example.com,1194,udp,enabled\r\n1,vpn.example.net,443,tcp-client,disabled\r\n2,vpn.example.org,1194,udp,enabled\r\nEND\r\n",
+            ),
+            (
+                "remote-entry-get 1 2\n",
+                "1,vpn.example.net,443,tcp-client,disabled\r\nEND\r\n",
+            ),
+        ],
+    );
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5566")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+
+    let count = session.remote_entry_count().expect("count received");
+    let entries = session.get_remote_entries().expect("entries received");
+    let range = session
+        .get_remote_entries_range(1..2)
+        .expect("range received");
+    handle.join().unwrap();
+
+    assert_eq!(3, count);
+    assert_eq!(3, entries.len());
+    assert_eq!("vpn.example.org", entries[2].host());
+    assert_eq!(1, range.len());
+    assert_eq!(443, range[0].port());
+    assert!(range[0].disabled());
+}
+
+#[test]
+fn test_session_retries_rejected_credentials() {
+    let handle = setup_session_server(
+        5567,
+        vec![
+            ("auth-retry interact\n", "SUCCESS: auth-retry parameter changed\r\n"),
+            (
+                "",
+                ">PASSWORD:Verification Failed: 'Auth'\r\n>PASSWORD:Need 'Auth' username/password\r\n",
+            ),
+            (
+                "username \"Auth\" \"alice\"\n",
+                "SUCCESS: 'Auth' username entered, but not yet verified\r\n",
+            ),
+            (
+                "password \"Auth\" \"pass \\\"word\\\"\"\n",
+                "SUCCESS: 'Auth' password entered, but not yet verified\r\n>PASSWORD:Verification Failed: 'Auth'\r\n",
+            ),
+            ("forget-passwords\n", "SUCCESS: Passwords were forgotten\r\n"),
+        ],
+    );
+    let mut provider = StaticCredentials { auth_token: None };
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5567")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    session
+        .auth_retry(AuthRetry::Interact)
+        .expect("auth-retry set");
+    session.max_auth_retries(1);
+    let mut responses = Vec::new();
+    for _ in 0..3 {
+        match session.next_notification() {
+            Ok(Notification::Password(password)) => {
+                responses.push(session.respond_to_password(&password, &mut provider))
+            }
+            _ => panic!("was expecting password notification"),
+        }
+    }
+    assert_eq!(2, session.auth_failures());
+    session.forget_passwords().expect("passwords forgotten");
+    handle.join().unwrap();
+
+    assert!(responses[0].is_ok());
+    assert!(responses[1].is_ok());
+    match responses[2] {
+        Err(OpenvpnError::VerificationFailed(ref realm)) => assert_eq!("Auth", realm),
+        _ => panic!("was expecting verification failure after the retry limit"),
+    }
+}
+
+#[test]
+fn test_session_answers_info_messages() {
+    let handle = setup_session_server(
+        5568,
+        vec![
+            (
+                "",
+                ">INFOMSG:WEB_AUTH:external:https://auth.example.com/login\r\n>INFOMSG:CR_TEXT:R,E:Enter the SMS code\r\n",
+            ),
+            ("cr-response NDI0MjQy\n", "SUCCESS: cr-response command succeeded\r\n"),
+        ],
+    );
+    let mut handler = PendingAuth::default();
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5568")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    for _ in 0..2 {
+        match session.next_notification() {
+            Ok(Notification::InfoMessage(message)) => session
+                .respond_to_info_message(&message, &mut handler)
+                .expect("info message handled"),
+            _ => panic!("was expecting info message"),
+        }
+    }
+    handle.join().unwrap();
+
+    assert_eq!(
+        vec!["https://auth.example.com/login".to_string()],
+        handler.opened
+    );
+}
+
+#[test]
+fn test_session_controls_openvpn() {
+    let handle = setup_session_server(
+        5569,
+        vec![
+            (
+                "state\n",
+                "1547913893,CONNECTED,SUCCESS,10.8.0.6,198.51.100.1,1194,,\r\nEND\r\n",
+            ),
+            (
+                "state all\n",
+                "1547913890,CONNECTING,,,,,,\r\n1547913893,CONNECTED,SUCCESS,10.8.0.6,198.51.100.1,1194,,\r\nEND\r\n",
+            ),
+            (
+                "log 2\n",
+                "1547913892,W,WARNING: file 'pass.txt' is group or others accessible\r\n1547913893,I,Initialization Sequence Completed\r\nEND\r\n",
+            ),
+            (
+                "load-stats\n",
+                "SUCCESS: nclients=2,bytesin=1024,bytesout=2048\r\n",
+            ),
+            (
+                "kill \"alice\"\n",
+                "SUCCESS: common name 'alice' found, 1 client(s) killed\r\n",
+            ),
+            ("client-kill 7 \"HALT\"\n", "SUCCESS: client-kill command succeeded\r\n"),
+            ("signal SIGUSR1\n", "SUCCESS: signal SIGUSR1 thrown\r\n"),
+            ("hold release\n", "SUCCESS: hold release succeeded\r\n"),
+            (
+                "log on\n",
+                "SUCCESS: real-time log notification set to ON\r\n>LOG:1547913900,N,TLS Error: bad packet\r\n",
+            ),
+        ],
+    );
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5569")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+
+    let state = session.state().expect("state returned");
+    assert_eq!("CONNECTED", state.name());
+    assert_eq!(Some(1194), state.remote_port());
+    let states = session.state_history().expect("state history returned");
+    assert_eq!(
+        vec!["CONNECTING", "CONNECTED"],
+        states.iter().map(|s| s.name()).collect::<Vec<_>>()
+    );
+    let log = session.log_history(Some(2)).expect("log history returned");
+    assert_eq!(2, log.len());
+    assert_eq!("Initialization Sequence Completed", log[1].message());
+    let stats = session.load_stats().expect("load stats returned");
+    assert_eq!(2, stats.clients());
+    assert_eq!(2048.0, stats.bytes_out());
+    assert_eq!(
+        "common name 'alice' found, 1 client(s) killed",
+        session.kill("alice").expect("client killed")
+    );
+    session
+        .client_kill(7, Some("HALT"))
+        .expect("client killed by id");
+    session.signal(Signal::Usr1).expect("signal sent");
+    session.hold_release().expect("hold released");
+    session.log_on().expect("log notifications enabled");
+    match session.next_notification() {
+        Ok(Notification::Log(entry)) => assert!(entry.is_error()),
+        _ => panic!("was expecting log notification"),
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_session_sends_management_password() {
+    let handle = setup_session_server(
+        5570,
+        vec![
+            ("", "ENTER PASSWORD:"),
+            ("secret\n", "SUCCESS: password is correct\r\n"),
+            ("hold release\n", "SUCCESS: hold release succeeded\r\n"),
+        ],
+    );
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5570")
+        .password("secret")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    session.hold_release().expect("hold released");
+    handle.join().unwrap();
+}
+
+#[test]
+fn test_rejected_management_password() {
+    let handle = setup_session_server(
+        5571,
+        vec![
+            ("", "ENTER PASSWORD:"),
+            ("wrong\n", "ERROR: bad password\r\n"),
+        ],
+    );
+    let result = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5571")
+        .password("wrong")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session();
+    handle.join().unwrap();
+
+    match result {
+        Err(OpenvpnError::VerificationFailed(ref realm)) => assert_eq!("management", realm),
+        _ => panic!("was expecting management password rejection"),
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_socket_status() {
+    use std::os::unix::net::UnixListener;
+
+    let path = std::env::temp_dir().join(format!("ovpn-mgmt-test-{}.sock", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path).unwrap();
+    let handle = thread::spawn(move || {
+        let mut stream = listener.accept().unwrap().0;
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut output = String::new();
+        reader.read_line(&mut output).unwrap();
+        assert_eq!("load-stats\n", output);
+        stream
+            .write_all(b"SUCCESS: nclients=1,bytesin=10,bytesout=20\r\n")
+            .unwrap();
+    });
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .unix_socket(&path)
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    let stats = session.load_stats().expect("load stats returned");
+    handle.join().unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(1, stats.clients());
+}
+
+#[test]
+fn test_session_polls_byte_counts() {
+    let listener = TcpListener::bind("localhost:5572").unwrap();
+    let handle = thread::spawn(move || {
+        let mut stream = listener.accept().unwrap().0;
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut output = String::new();
+        reader.read_line(&mut output).unwrap();
+        assert_eq!("bytecount 5\n", output);
+        stream
+            .write_all(b"SUCCESS: bytecount interval changed\r\n")
+            .unwrap();
+        output.clear();
+        reader.read_line(&mut output).unwrap();
+        assert_eq!("status\n", output);
+        stream.write_all(b"TITLE\ttest-title\r\nTIME\ttimestamp\t1547913893\r\nHEADER\tCLIENT_LIST\r\nCLIENT_LIST\ttest-client\t127.0.0.1:12345\t10.8.0.2\t\t100\t200\tdate-string\t1546277714\talice\t4\t0\tAES-256-GCM\r\nEND\r\n").unwrap();
+        thread::sleep(Duration::from_millis(300));
+        stream.write_all(b">BYTECOUNT_CLI:4,1024,").unwrap();
+        thread::sleep(Duration::from_millis(300));
+        stream.write_all(b"2048\r\n").unwrap();
+    });
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5572")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    session.bytecount(5).expect("bytecount enabled");
+    let status = session.get_status().expect("status returned");
+    assert_eq!(Some(4), status.clients()[0].cid());
+
+    let mut polls = 0;
+    let byte_count = loop {
+        polls += 1;
+        match session.poll_notification(Duration::from_millis(100)) {
+            Ok(Some(Notification::ByteCount(byte_count))) => break byte_count,
+            Ok(None) if polls < 20 => {}
+            _ => panic!("was expecting byte count notification"),
+        }
+    };
+    handle.join().unwrap();
+
+    assert!(polls > 1);
+    assert_eq!(ByteCount::new(Some(4), 1024.0, 2048.0), byte_count);
+}
+
+#[test]
+fn test_status_watcher_reports_client_changes() {
+    let listener = TcpListener::bind("localhost:5573").unwrap();
+    let handle = thread::spawn(move || {
+        let responses = [
+            "TITLE\ttest-title\r\nTIME\ttimestamp\t1547913893\r\nHEADER\tCLIENT_LIST\r\nCLIENT_LIST\talice\t127.0.0.1:12345\t10.8.0.2\t\t100\t200\tdate-string\t1546277714\talice\t4\r\nCLIENT_LIST\tbob\t127.0.0.2:12345\t10.8.0.3\t\t300\t400\tdate-string\t1546277715\tbob\t5\r\nEND\r\n",
+            "TITLE\ttest-title\r\nTIME\ttimestamp\t1547913903\r\nHEADER\tCLIENT_LIST\r\nCLIENT_LIST\talice\t127.0.0.1:12345\t10.8.0.2\t\t150\t250\tdate-string\t1546277714\talice\t4\r\nEND\r\n",
+        ];
+        for response in &responses {
+            let mut stream = listener.accept().unwrap().0;
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut output = String::new();
+            reader.read_line(&mut output).unwrap();
+            assert_eq!("status\n", output);
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    let manager = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5573")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully");
+    let mut watcher = StatusWatcher::new(manager);
+    let events = watcher.subscribe();
+
+    watcher.poll();
+    watcher.poll();
+    handle.join().unwrap();
+    let events: Vec<_> = events.try_iter().collect();
+
+    assert_eq!(4, events.len());
+    assert!(matches!(events[0], WatchEvent::Connected(ref client) if client.name() == "alice"));
+    assert!(matches!(events[1], WatchEvent::Connected(ref client) if client.name() == "bob"));
+    match events[2] {
+        WatchEvent::Disconnected(ref client) => {
+            assert_eq!(Some(5), client.cid());
+            assert_eq!(300.0, client.bytes_received());
+        }
+        ref other => panic!("was expecting a disconnect, got {:?}", other),
+    }
+    match events[3] {
+        WatchEvent::Updated(ref change) => {
+            assert_eq!(50.0, change.bytes_received_delta());
+            assert_eq!(50.0, change.bytes_sent_delta());
+        }
+        ref other => panic!("was expecting an update, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_quota_enforcer_kills_and_denies() {
+    let listener = TcpListener::bind("localhost:5574").unwrap();
+    let handle = thread::spawn(move || {
+        let mut stream = listener.accept().unwrap().0;
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        stream.write_all(b">CLIENT:ESTABLISHED,1\r\n>CLIENT:ENV,common_name=alice\r\n>CLIENT:ENV,END\r\n>CLIENT:ESTABLISHED,3\r\n>CLIENT:ENV,common_name=alice\r\n>CLIENT:ENV,END\r\n>BYTECOUNT_CLI:1,900,200\r\n").unwrap();
+        let mut output = String::new();
+        reader.read_line(&mut output).unwrap();
+        assert_eq!("client-kill 1 \"HALT\"\n", output);
+        // the client left before the kill, the others are still killed
+        stream
+            .write_all(b"ERROR: client-kill command failed\r\n")
+            .unwrap();
+        output.clear();
+        reader.read_line(&mut output).unwrap();
+        assert_eq!("client-kill 3 \"HALT\"\n", output);
+        stream
+            .write_all(b"SUCCESS: client-kill command succeeded\r\n>CLIENT:CONNECT,2,1\r\n>CLIENT:ENV,common_name=alice\r\n>CLIENT:ENV,END\r\n")
+            .unwrap();
+        output.clear();
+        reader.read_line(&mut output).unwrap();
+        assert!(output.starts_with(
+            "client-deny 2 1 \"traffic quota exceeded\" \"traffic quota exceeded until "
+        ));
+        stream
+            .write_all(b"SUCCESS: client-deny command succeeded\r\n")
+            .unwrap();
+    });
+    let mut enforcer = QuotaEnforcer::new();
+    enforcer.default_quota(Quota::new(QuotaPeriod::Monthly, 1000.0));
+    let mut session = openvpn_management::CommandManagerBuilder::new()
+        .management_url("localhost:5574")
+        .read_timeout(Some(READ_TIMEOUT))
+        .build()
+        .expect("api build successfully")
+        .open_session()
+        .expect("session opened successfully");
+    let mut events = Vec::new();
+    for _ in 0..4 {
+        let notification = session.next_notification().expect("notification received");
+        events.extend(enforcer.enforce(&mut session, &notification).unwrap());
+    }
+    handle.join().unwrap();
+
+    assert_eq!(2, events.len());
+    assert!(
+        matches!(events[0], QuotaEvent::Exceeded { ref cids, used, .. } if *cids == [1, 3] && used == 1100.0)
+    );
+    assert!(matches!(events[1], QuotaEvent::Denied { cid: 2, .. }));
+    assert!(enforcer.is_exceeded("alice"));
+}